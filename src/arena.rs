@@ -0,0 +1,92 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    ops::{Index, IndexMut},
+};
+
+/* Owns the nodes of a tree, which refer to each other by Id instead of by Box. Nodes are never
+ * removed, so an id stays valid as long as its arena lives and passes can share the nodes by
+ * borrowing the arena. */
+#[derive(Debug, Clone)]
+pub struct Arena<T> {
+    nodes: Vec<T>,
+}
+
+/* Refers to a node in an Arena<T>. Only meaningful for the arena that allocated it. */
+pub struct Id<T> {
+    index: u32,
+    node: PhantomData<fn() -> T>,
+}
+
+impl<T> Default for Arena<T> {
+    fn default() -> Self {
+        Arena { nodes: Vec::new() }
+    }
+}
+
+impl<T> Arena<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn alloc(&mut self, node: T) -> Id<T> {
+        let id = Id {
+            index: self.nodes.len() as u32,
+            node: PhantomData,
+        };
+        self.nodes.push(node);
+        id
+    }
+
+    pub fn len(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.nodes.is_empty()
+    }
+}
+
+impl<T> Index<Id<T>> for Arena<T> {
+    type Output = T;
+
+    fn index(&self, id: Id<T>) -> &T {
+        &self.nodes[id.index as usize]
+    }
+}
+
+impl<T> IndexMut<Id<T>> for Arena<T> {
+    fn index_mut(&mut self, id: Id<T>) -> &mut T {
+        &mut self.nodes[id.index as usize]
+    }
+}
+
+/* Implemented by hand, deriving would require T to implement them as well */
+impl<T> Clone for Id<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Id<T> {}
+
+impl<T> PartialEq for Id<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<T> Eq for Id<T> {}
+
+impl<T> Hash for Id<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.index.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Id<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.index)
+    }
+}
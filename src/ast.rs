@@ -1,8 +1,53 @@
+use std::fmt;
+
+use crate::{
+    arena::{Arena, Id},
+    diagnostics::Span,
+    symbols::{self, DeclId, Interner, Symbol},
+};
+
+/* Expressions and statements live in the arenas of their Program and refer to each other by id */
+pub type ExpId = Id<Exp>;
+pub type StmtId = Id<Statement>;
+
+/* A variable or parameter as written in the source. Name resolution binds it to its declaration,
+ * until then and if the name is not declared id is None. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Var {
+    pub name: Symbol,
+    pub id: Option<DeclId>,
+}
+
+impl Var {
+    pub fn new(name: Symbol) -> Var {
+        Var { name, id: None }
+    }
+
+    /* The declaration of a resolved variable. Only the phases after the semantic checks may rely
+     * on every variable being resolved. */
+    pub fn decl(&self) -> DeclId {
+        self.id.expect("variable is not resolved")
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Statement<'a> {
-    Simp(Simp<'a>),
-    Control(Box<Control<'a>>),
-    Block(Block<'a>),
+pub enum Statement {
+    Simp(Simp, Span),
+    Control(Control, Span),
+    Block(Block, Span),
+    /* A statement the parser recovered from, only present if parsing reported an error */
+    Error(Span),
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Simp(_, span)
+            | Statement::Control(_, span)
+            | Statement::Block(_, span)
+            | Statement::Error(span) => *span,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -11,56 +56,210 @@ pub enum Type {
     Bool,
 }
 
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Bool => write!(f, "bool"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
-pub enum Decl<'a> {
-    Declare(Type, &'a [u8]),
-    Assign((Type, &'a [u8], Exp<'a>)),
+pub enum Decl {
+    Declare(Type, Var, Span),
+    Assign((Type, Var, ExpId), Span),
 }
 #[derive(Debug, Clone)]
-pub enum Simp<'a> {
-    Simp((Lvalue<'a>, Asnop, Exp<'a>)),
-    Decl(Decl<'a>),
-    Call(Call<'a>),
+pub enum Simp {
+    Simp((Lvalue, Asnop, ExpId)),
+    Decl(Decl),
+    Call(Call),
 }
 
 #[derive(Debug, Clone)]
-pub enum Lvalue<'a> {
-    Ident(&'a [u8]),
+pub enum Lvalue {
+    Ident(Var, Span),
 }
 
-impl<'a> Lvalue<'a> {
-    pub fn get_ident_lvalue(&self) -> &'a [u8] {
+impl Lvalue {
+    pub fn get_ident_lvalue(&self) -> Var {
+        match self {
+            Lvalue::Ident(ident, _) => *ident,
+        }
+    }
+
+    pub fn span(&self) -> Span {
         match self {
-            Lvalue::Ident(ident) => ident,
+            Lvalue::Ident(_, span) => *span,
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub enum Control<'a> {
-    If(Exp<'a>, Statement<'a>, Option<Statement<'a>>),
-    While(Exp<'a>, Statement<'a>),
-    For((Option<Simp<'a>>, Exp<'a>, Option<Simp<'a>>), Statement<'a>),
+pub enum Control {
+    If(ExpId, StmtId, Option<StmtId>),
+    While(ExpId, StmtId),
+    For((Option<Simp>, ExpId, Option<Simp>), StmtId),
     Continue,
     Break,
-    Return(Exp<'a>),
+    Return(ExpId),
 }
 
 #[derive(Debug, Clone)]
-pub enum Exp<'a> {
-    True,
-    False,
-    Intconst(i32),
-    Ident(&'a [u8]),
-    Arithmetic(Box<(Exp<'a>, Binop, Exp<'a>)>),
-    Negative(Box<Exp<'a>>),
-    Not(Box<Exp<'a>>),
-    BitNot(Box<Exp<'a>>),
-    Ternary(Box<(Exp<'a>, Exp<'a>, Exp<'a>)>),
-    Call(Call<'a>),
+pub enum Exp {
+    True(Span),
+    False(Span),
+    Intconst(i32, Span),
+    Ident(Var, Span),
+    Arithmetic(ExpId, Binop, ExpId, Span),
+    Negative(ExpId, Span),
+    Not(ExpId, Span),
+    BitNot(ExpId, Span),
+    Ternary(ExpId, ExpId, ExpId, Span),
+    Call(Call, Span),
 }
 
-#[derive(Debug, Clone)]
+impl Exp {
+    pub fn span(&self) -> Span {
+        match self {
+            Exp::True(span)
+            | Exp::False(span)
+            | Exp::Intconst(_, span)
+            | Exp::Ident(_, span)
+            | Exp::Arithmetic(_, _, _, span)
+            | Exp::Negative(_, span)
+            | Exp::Not(_, span)
+            | Exp::BitNot(_, span)
+            | Exp::Ternary(_, _, _, span)
+            | Exp::Call(_, span) => *span,
+        }
+    }
+}
+
+impl Arena<Exp> {
+    /* Calls f for the expression id and all of its subexpressions */
+    pub fn visit(&self, id: ExpId, f: &mut impl FnMut(&Exp)) {
+        let exp = &self[id];
+        f(exp);
+        match exp {
+            Exp::Arithmetic(e1, _, e2, _) => {
+                self.visit(*e1, f);
+                self.visit(*e2, f);
+            }
+            Exp::Negative(e, _) | Exp::Not(e, _) | Exp::BitNot(e, _) => self.visit(*e, f),
+            Exp::Ternary(e1, e2, e3, _) => {
+                self.visit(*e1, f);
+                self.visit(*e2, f);
+                self.visit(*e3, f);
+            }
+            Exp::Call(call, _) => call.get_args().iter().for_each(|e| self.visit(*e, f)),
+            Exp::True(_) | Exp::False(_) | Exp::Intconst(..) | Exp::Ident(..) => (),
+        }
+    }
+
+    pub fn span(&self, id: ExpId) -> Span {
+        self[id].span()
+    }
+
+    /* Prints the expression id as source code given the names of its program, e.g.
+     * format!("{}", exps.show(id, names)) */
+    pub fn show<'a>(&'a self, id: ExpId, names: &'a Interner) -> ShowExp<'a> {
+        ShowExp {
+            exps: self,
+            names,
+            id,
+        }
+    }
+
+    pub fn show_call<'a>(&'a self, call: &'a Call, names: &'a Interner) -> ShowCall<'a> {
+        ShowCall {
+            exps: self,
+            names,
+            call,
+        }
+    }
+}
+
+/* Prints expressions as source code, with parentheses only where precedence requires them */
+pub struct ShowExp<'a> {
+    exps: &'a Arena<Exp>,
+    names: &'a Interner,
+    id: ExpId,
+}
+
+pub struct ShowCall<'a> {
+    exps: &'a Arena<Exp>,
+    names: &'a Interner,
+    call: &'a Call,
+}
+
+impl fmt::Display for ShowExp<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt_prec(self.exps, self.names, self.id, f, 0)
+    }
+}
+
+/* Precedence of unary operators, binary operators bind weaker. Nested unary operators are
+ * parenthesized so that "- -x" does not print as "--x". */
+const UNARY_PREC: u8 = 12;
+
+fn fmt_prec(
+    exps: &Arena<Exp>,
+    names: &Interner,
+    id: ExpId,
+    f: &mut fmt::Formatter<'_>,
+    prec: u8,
+) -> fmt::Result {
+    let exp = &exps[id];
+    let own = match exp {
+        Exp::Ternary(..) => 1,
+        Exp::Arithmetic(_, binop, _, _) => binop.precedence(),
+        Exp::Negative(..) | Exp::Not(..) | Exp::BitNot(..) => UNARY_PREC,
+        _ => u8::MAX,
+    };
+    if own < prec {
+        write!(f, "(")?;
+    }
+    match exp {
+        Exp::True(_) => write!(f, "true")?,
+        Exp::False(_) => write!(f, "false")?,
+        Exp::Intconst(n, _) => write!(f, "{n}")?,
+        Exp::Ident(var, _) => write!(f, "{}", names.name(var.name))?,
+        /* Binary operators are left associative */
+        Exp::Arithmetic(e1, binop, e2, _) => {
+            fmt_prec(exps, names, *e1, f, own)?;
+            write!(f, " {binop} ")?;
+            fmt_prec(exps, names, *e2, f, own + 1)?;
+        }
+        Exp::Negative(e, _) => {
+            write!(f, "-")?;
+            fmt_prec(exps, names, *e, f, UNARY_PREC + 1)?;
+        }
+        Exp::Not(e, _) => {
+            write!(f, "!")?;
+            fmt_prec(exps, names, *e, f, UNARY_PREC + 1)?;
+        }
+        Exp::BitNot(e, _) => {
+            write!(f, "~")?;
+            fmt_prec(exps, names, *e, f, UNARY_PREC + 1)?;
+        }
+        Exp::Ternary(e1, e2, e3, _) => {
+            fmt_prec(exps, names, *e1, f, 2)?;
+            write!(f, " ? ")?;
+            fmt_prec(exps, names, *e2, f, 1)?;
+            write!(f, " : ")?;
+            fmt_prec(exps, names, *e3, f, 1)?;
+        }
+        Exp::Call(call, _) => write!(f, "{}", exps.show_call(call, names))?,
+    }
+    if own < prec {
+        write!(f, ")")?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum Binop {
     Plus,
     Minus,
@@ -81,7 +280,51 @@ pub enum Binop {
     LShift,
     RShift,
 }
-#[derive(Debug, Clone)]
+impl Binop {
+    /* C precedence, higher binds stronger */
+    fn precedence(&self) -> u8 {
+        match self {
+            Binop::Or => 2,
+            Binop::And => 3,
+            Binop::BitOr => 4,
+            Binop::BitXor => 5,
+            Binop::BitAnd => 6,
+            Binop::Equals | Binop::NotEqual => 7,
+            Binop::LessThan | Binop::LessEqual | Binop::GreaterThan | Binop::GreaterEqual => 8,
+            Binop::LShift | Binop::RShift => 9,
+            Binop::Plus | Binop::Minus => 10,
+            Binop::Mult | Binop::Div | Binop::Mod => 11,
+        }
+    }
+}
+
+impl fmt::Display for Binop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Binop::Plus => "+",
+            Binop::Minus => "-",
+            Binop::Div => "/",
+            Binop::Mult => "*",
+            Binop::Mod => "%",
+            Binop::LessThan => "<",
+            Binop::LessEqual => "<=",
+            Binop::GreaterThan => ">",
+            Binop::GreaterEqual => ">=",
+            Binop::Equals => "==",
+            Binop::NotEqual => "!=",
+            Binop::And => "&&",
+            Binop::Or => "||",
+            Binop::BitAnd => "&",
+            Binop::BitXor => "^",
+            Binop::BitOr => "|",
+            Binop::LShift => "<<",
+            Binop::RShift => ">>",
+        };
+        write!(f, "{op}")
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum Asnop {
     APlus,
     AMinus,
@@ -95,81 +338,157 @@ pub enum Asnop {
     ALShift,
     ARShift,
 }
+impl fmt::Display for Asnop {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Asnop::APlus => "+=",
+            Asnop::AMinus => "-=",
+            Asnop::ADiv => "/=",
+            Asnop::AMult => "*=",
+            Asnop::AMod => "%=",
+            Asnop::Assign => "=",
+            Asnop::ABitOr => "|=",
+            Asnop::ABitAnd => "&=",
+            Asnop::ABitXor => "^=",
+            Asnop::ALShift => "<<=",
+            Asnop::ARShift => ">>=",
+        };
+        write!(f, "{op}")
+    }
+}
+
+/* A parsed unit. The functions own their parameters and their top-level blocks, every expression
+ * and statement is allocated in the arenas. The declarations are the functions the unit calls
+ * without defining them. */
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub declarations: Vec<FunctionDecl>,
+    pub exps: Arena<Exp>,
+    pub statements: Arena<Statement>,
+}
+
 #[derive(Debug, Clone)]
-pub enum Program<'a> {
-    Program(Vec<Function<'a>>),
+pub enum Function {
+    Function(Type, Symbol, ParamList, Block, Span),
 }
 
-impl<'a> Program<'a> {
-    pub fn into_functions(&self) -> &Vec<Function<'a>> {
+impl Function {
+    pub fn get_params(&self) -> &Vec<Param> {
+        match self {
+            Function::Function(_, _, ParamList::ParamList(params), _, _) => params,
+        }
+    }
+
+    pub fn get_block(&self) -> &Block {
+        match self {
+            Function::Function(_, _, _, block, _) => block,
+        }
+    }
+
+    pub fn get_name(&self) -> Symbol {
         match self {
-            Program::Program(func) => func,
+            Function::Function(_, name, _, _, _) => *name,
         }
     }
+
+    pub fn get_type(&self) -> &Type {
+        match self {
+            Function::Function(t, _, _, _, _) => t,
+        }
+    }
+
+    /* The span of the function name */
+    pub fn span(&self) -> Span {
+        match self {
+            Function::Function(_, _, _, _, span) => *span,
+        }
+    }
+}
+
+/* What a unit consists of, in the order of the source */
+#[derive(Debug, Clone)]
+pub enum Item {
+    Function(Function),
+    Declaration(FunctionDecl),
 }
 
+/* A function without a body, e.g. int f(int x); for a function defined in another unit */
 #[derive(Debug, Clone)]
-pub enum Function<'a> {
-    Function(Type, &'a [u8], ParamList<'a>, Block<'a>),
+pub enum FunctionDecl {
+    FunctionDecl(Type, Symbol, ParamList, Span),
 }
 
-impl<'a> Function<'a> {
-    pub fn get_params(&self) -> &Vec<Param<'a>> {
+impl FunctionDecl {
+    pub fn get_params(&self) -> &Vec<Param> {
         match self {
-            Function::Function(_, _, ParamList::ParamList(params), _) => params,
+            FunctionDecl::FunctionDecl(_, _, ParamList::ParamList(params), _) => params,
         }
     }
 
-    pub fn get_block(self) -> Block<'a> {
+    pub fn get_name(&self) -> Symbol {
         match self {
-            Function::Function(_, _, _, block) => block,
+            FunctionDecl::FunctionDecl(_, name, _, _) => *name,
         }
     }
 
-    pub fn get_name(&self) -> &'a [u8] {
+    pub fn get_type(&self) -> &Type {
         match self {
-            Function::Function(_, name, _, _) => name,
+            FunctionDecl::FunctionDecl(t, _, _, _) => t,
         }
     }
 
-    pub fn get_type(&self) -> &Type {
+    /* The span of the function name */
+    pub fn span(&self) -> Span {
         match self {
-            Function::Function(t, _, _, _) => t,
+            FunctionDecl::FunctionDecl(_, _, _, span) => *span,
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub enum ParamList<'a> {
-    ParamList(Vec<Param<'a>>),
+pub enum ParamList {
+    ParamList(Vec<Param>),
 }
 
 #[derive(Debug, Clone)]
-pub enum Param<'a> {
-    Param(Type, &'a [u8]),
+pub enum Param {
+    Param(Type, Var, Span),
 }
 
-impl<'a> Param<'a> {
-    pub fn get_name(&self) -> &'a [u8] {
+impl Param {
+    pub fn get_name(&self) -> Symbol {
+        match self {
+            Param::Param(_, var, _) => var.name,
+        }
+    }
+
+    pub fn get_var(&self) -> Var {
         match self {
-            Param::Param(_, name) => name,
+            Param::Param(_, var, _) => *var,
         }
     }
 
     pub fn get_type(&self) -> &Type {
         match self {
-            Param::Param(t, _) => t,
+            Param::Param(t, _, _) => t,
+        }
+    }
+
+    pub fn span(&self) -> Span {
+        match self {
+            Param::Param(_, _, span) => *span,
         }
     }
 }
 
 #[derive(Debug, Clone)]
-pub enum Block<'a> {
-    Block(Vec<Statement<'a>>),
+pub enum Block {
+    Block(Vec<StmtId>),
 }
 
-impl<'a> Block<'a> {
-    pub fn into_statements(self) -> Vec<Statement<'a>> {
+impl Block {
+    pub fn statements(&self) -> &[StmtId] {
         match self {
             Block::Block(statements) => statements,
         }
@@ -177,28 +496,225 @@ impl<'a> Block<'a> {
 }
 
 #[derive(Debug, Clone)]
-pub enum Call<'a> {
-    Print(ArgList<'a>),
-    Read(ArgList<'a>),
-    Flush(ArgList<'a>),
-    Func(&'a [u8], ArgList<'a>),
+pub enum Call {
+    Print(ArgList),
+    Read(ArgList),
+    Flush(ArgList),
+    Func(Symbol, ArgList),
+}
+
+impl Call {
+    pub fn get_name(&self) -> Symbol {
+        match self {
+            Call::Print(_) => symbols::PRINT,
+            Call::Read(_) => symbols::READ,
+            Call::Flush(_) => symbols::FLUSH,
+            Call::Func(name, _) => *name,
+        }
+    }
+
+    pub fn get_args(&self) -> &[ExpId] {
+        match self {
+            Call::Print(args) | Call::Read(args) | Call::Flush(args) | Call::Func(_, args) => {
+                args.get_args()
+            }
+        }
+    }
+}
+
+impl fmt::Display for ShowCall<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.names.name(self.call.get_name()))?;
+        for (i, arg) in self.call.get_args().iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            fmt_prec(self.exps, self.names, *arg, f, 1)?;
+        }
+        write!(f, ")")
+    }
 }
 
 #[derive(Debug, Clone)]
-pub enum ArgList<'a> {
-    Args(Vec<Exp<'a>>),
+pub enum ArgList {
+    Args(Vec<ExpId>),
 }
 
-impl<'a> ArgList<'a> {
-    pub fn get_args(&self) -> &Vec<Exp<'a>> {
+impl ArgList {
+    pub fn get_args(&self) -> &[ExpId] {
         match self {
             ArgList::Args(args) => args,
         }
     }
+}
 
-    pub fn into_args(self) -> Vec<Exp<'a>> {
-        match self {
-            ArgList::Args(args) => args,
+impl Program {
+    /* Prints the program given the names of its symbols, e.g. for --emit=ast */
+    pub fn show<'a>(&'a self, names: &'a Interner) -> ShowProgram<'a> {
+        ShowProgram {
+            program: self,
+            names,
+        }
+    }
+}
+
+pub struct ShowProgram<'a> {
+    program: &'a Program,
+    names: &'a Interner,
+}
+
+/* The AST is printed as source code with four spaces of indentation, so that a dump can be
+ * compiled again */
+impl fmt::Display for ShowProgram<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        /* Declarations come first, so that they stay in front of the calls when compiled again */
+        for declaration in self.program.declarations.iter() {
+            let FunctionDecl::FunctionDecl(t, name, params, _) = declaration;
+            self.fmt_signature(t, *name, params, f)?;
+            writeln!(f, ";")?;
+        }
+        if !self.program.declarations.is_empty() && !self.program.functions.is_empty() {
+            writeln!(f)?;
+        }
+        for (i, function) in self.program.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            self.fmt_function(function, f)?;
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+fn indent(f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+    write!(f, "{}", "    ".repeat(level))
+}
+
+impl ShowProgram<'_> {
+    fn exp(&self, id: ExpId) -> ShowExp<'_> {
+        self.program.exps.show(id, self.names)
+    }
+
+    fn fmt_function(&self, function: &Function, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Function::Function(t, name, params, block, _) = function;
+        self.fmt_signature(t, *name, params, f)?;
+        write!(f, " ")?;
+        self.fmt_block(block, f, 0)
+    }
+
+    fn fmt_signature(
+        &self,
+        t: &Type,
+        name: Symbol,
+        ParamList::ParamList(params): &ParamList,
+        f: &mut fmt::Formatter<'_>,
+    ) -> fmt::Result {
+        write!(f, "{t} {}(", self.names.name(name))?;
+        for (i, param) in params.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(
+                f,
+                "{} {}",
+                param.get_type(),
+                self.names.name(param.get_name())
+            )?;
+        }
+        write!(f, ")")
+    }
+
+    fn fmt_simp(&self, simp: &Simp, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match simp {
+            Simp::Simp((lvalue, asnop, exp)) => write!(
+                f,
+                "{} {asnop} {}",
+                self.names.name(lvalue.get_ident_lvalue().name),
+                self.exp(*exp)
+            ),
+            Simp::Decl(Decl::Declare(t, var, _)) => {
+                write!(f, "{t} {}", self.names.name(var.name))
+            }
+            Simp::Decl(Decl::Assign((t, var, exp), _)) => {
+                write!(f, "{t} {} = {}", self.names.name(var.name), self.exp(*exp))
+            }
+            Simp::Call(call) => write!(f, "{}", self.program.exps.show_call(call, self.names)),
+        }
+    }
+
+    /* Prints a block starting at the current position, the closing brace is indented by level */
+    fn fmt_block(&self, block: &Block, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+        writeln!(f, "{{")?;
+        for s in block.statements() {
+            indent(f, level + 1)?;
+            self.fmt_statement(*s, f, level + 1)?;
+            writeln!(f)?;
+        }
+        indent(f, level)?;
+        write!(f, "}}")
+    }
+
+    /* Prints the statement without leading indentation or trailing newline. Nested lines are
+     * indented by level. */
+    fn fmt_statement(&self, id: StmtId, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+        match &self.program.statements[id] {
+            Statement::Simp(simp, _) => {
+                self.fmt_simp(simp, f)?;
+                write!(f, ";")
+            }
+            Statement::Block(block, _) => self.fmt_block(block, f, level),
+            Statement::Error(_) => write!(f, "<error>;"),
+            Statement::Control(control, _) => match control {
+                Control::If(exp, then, otherwise) => {
+                    write!(f, "if ({})", self.exp(*exp))?;
+                    self.fmt_body(*then, f, level)?;
+                    if let Some(otherwise) = otherwise {
+                        if matches!(self.program.statements[*then], Statement::Block(..)) {
+                            write!(f, " else")?;
+                        } else {
+                            writeln!(f)?;
+                            indent(f, level)?;
+                            write!(f, "else")?;
+                        }
+                        self.fmt_body(*otherwise, f, level)?;
+                    }
+                    Ok(())
+                }
+                Control::While(exp, body) => {
+                    write!(f, "while ({})", self.exp(*exp))?;
+                    self.fmt_body(*body, f, level)
+                }
+                Control::For((init, exp, step), body) => {
+                    write!(f, "for (")?;
+                    if let Some(init) = init {
+                        self.fmt_simp(init, f)?;
+                    }
+                    write!(f, "; {};", self.exp(*exp))?;
+                    if let Some(step) = step {
+                        write!(f, " ")?;
+                        self.fmt_simp(step, f)?;
+                    }
+                    write!(f, ")")?;
+                    self.fmt_body(*body, f, level)
+                }
+                Control::Continue => write!(f, "continue;"),
+                Control::Break => write!(f, "break;"),
+                Control::Return(exp) => write!(f, "return {};", self.exp(*exp)),
+            },
+        }
+    }
+
+    /* Prints the body of a control statement, a block on the same line and anything else on its
+     * own indented line */
+    fn fmt_body(&self, body: StmtId, f: &mut fmt::Formatter<'_>, level: usize) -> fmt::Result {
+        if let Statement::Block(block, _) = &self.program.statements[body] {
+            write!(f, " ")?;
+            self.fmt_block(block, f, level)
+        } else {
+            writeln!(f)?;
+            indent(f, level + 1)?;
+            self.fmt_statement(body, f, level + 1)
         }
     }
 }
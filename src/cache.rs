@@ -0,0 +1,329 @@
+use std::{
+    collections::HashMap,
+    fmt::Write,
+    fs,
+    hash::{DefaultHasher, Hash, Hasher},
+    path::PathBuf,
+    process,
+};
+
+use crate::{
+    arena::Arena,
+    ast::Exp,
+    ir::{Call, IRCmd, IRExp, IRFunction, Op, Temp},
+    semantics::AbsFunction,
+    symbols::{Declaration, Symbol, SymbolKind, SymbolTable},
+    warnings::calls,
+    x86::{self, Inst},
+};
+
+/* The first line of every entry. Changing the IR, the assembly or the format of the entries
+ * needs a new version, which makes old entries miss. */
+const FORMAT: &str = "compdesign-cache 3";
+
+const OPS: [Op; 16] = [
+    Op::Plus,
+    Op::Minus,
+    Op::Mult,
+    Op::Div,
+    Op::Mod,
+    Op::LessThan,
+    Op::LessEqual,
+    Op::GreaterThan,
+    Op::GreaterEqual,
+    Op::Equals,
+    Op::NotEqual,
+    Op::BitAnd,
+    Op::BitXor,
+    Op::BitOr,
+    Op::LShift,
+    Op::RShift,
+];
+
+/* What the cache holds for a function: its IR right after the translation, which callers need
+ * for inlining, its IR after the optimizations and its instructions */
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub translated: IRFunction,
+    pub optimized: IRFunction,
+    pub assembly: Vec<Inst>,
+}
+
+/* A directory with one file per compiled function, named after the key of the function */
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: PathBuf) -> Cache {
+        Cache { dir }
+    }
+
+    fn path(&self, key: u64) -> PathBuf {
+        self.dir.join(format!("{key:016x}"))
+    }
+
+    /* Returns the entry of the function name with this key. Missing, outdated and damaged entries
+     * all miss. */
+    pub fn load(&self, key: u64, name: &str) -> Option<CacheEntry> {
+        let text = fs::read_to_string(self.path(key)).ok()?;
+        let mut lines = text.split_inclusive('\n');
+        if lines.next()?.trim_end() != FORMAT || lines.next()?.trim_end() != name {
+            return None;
+        }
+        let translated = read_function(&mut lines, name)?;
+        let optimized = read_function(&mut lines, name)?;
+        /* The instructions take the rest of the entry, one per line in assembly syntax */
+        let assembly = lines
+            .map(|line| x86::parse(line.trim_end()))
+            .collect::<Option<_>>()?;
+        Some(CacheEntry {
+            translated,
+            optimized,
+            assembly,
+        })
+    }
+
+    /* A failed write only costs time in the next build, so errors are ignored. The entry is
+     * written to a temporary file first, a concurrent build never reads half of it. */
+    pub fn store(
+        &self,
+        key: u64,
+        translated: &IRFunction,
+        optimized: &IRFunction,
+        assembly: &[Inst],
+    ) {
+        let mut text = format!("{FORMAT}\n{}\n", optimized.name);
+        write_function(&mut text, translated);
+        write_function(&mut text, optimized);
+        for inst in assembly.iter() {
+            let _ = writeln!(text, "{inst}");
+        }
+        let path = self.path(key);
+        let temporary = path.with_extension(format!("{}.tmp", process::id()));
+        let _ = fs::create_dir_all(&self.dir)
+            .and_then(|_| fs::write(&temporary, text))
+            .and_then(|_| fs::rename(&temporary, &path));
+    }
+}
+
+/* The key of the cache entry of f. It covers everything the IR and the assembly of f depend on:
+ * f itself, printed without source locations so that edits elsewhere in the file keep the key,
+ * the types of the functions it calls, the optimizations and, for inlining, the functions
+ * of its unit it calls. */
+pub fn function_key(
+    f: &AbsFunction,
+    exps: &Arena<Exp>,
+    unit: &HashMap<Symbol, &AbsFunction>,
+    symbols: &SymbolTable,
+    optimizations: &[&str],
+) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    FORMAT.hash(&mut hasher);
+    optimizations.hash(&mut hasher);
+    f.show(exps, symbols.names()).to_string().hash(&mut hasher);
+    let mut callees = Vec::new();
+    calls(f, exps, &mut callees);
+    /* The ids of symbols differ between builds, only their names are stable */
+    callees.sort_by_key(|name| symbols.name(*name));
+    callees.dedup();
+    for name in callees {
+        symbols.name(name).hash(&mut hasher);
+        if let Some(Declaration {
+            kind: SymbolKind::Function(params) | SymbolKind::ExternFunction(params),
+            typ,
+            ..
+        }) = symbols.function(name)
+        {
+            for param in params.iter() {
+                param.to_string().hash(&mut hasher);
+            }
+            typ.to_string().hash(&mut hasher);
+        }
+        if optimizations.contains(&"inline")
+            && let Some(callee) = unit.get(&name)
+        {
+            callee
+                .show(exps, symbols.names())
+                .to_string()
+                .hash(&mut hasher);
+        }
+    }
+    hasher.finish()
+}
+
+/* A function is stored as a line with its number of temps, parameters and instructions,
+ * followed by one line per instruction in prefix notation */
+fn write_function(out: &mut String, f: &IRFunction) {
+    let _ = writeln!(
+        out,
+        "{} {} {}",
+        f.num_temps,
+        f.num_params,
+        f.instructions.len()
+    );
+    for cmd in f.instructions.iter() {
+        let mut tokens = Vec::new();
+        cmd_tokens(cmd, &mut tokens);
+        out.push_str(&tokens.join(" "));
+        out.push('\n');
+    }
+}
+
+fn cmd_tokens(cmd: &IRCmd, tokens: &mut Vec<String>) {
+    match cmd {
+        IRCmd::Load(dest, exp) => {
+            tokens.push("load".to_owned());
+            exp_tokens(dest, tokens);
+            exp_tokens(exp, tokens);
+        }
+        IRCmd::JumpIf(exp, label) => {
+            tokens.push(format!("jumpif {label}"));
+            exp_tokens(exp, tokens);
+        }
+        IRCmd::Jump(label) => tokens.push(format!("jump {label}")),
+        IRCmd::Label(label) => tokens.push(format!("label {label}")),
+        IRCmd::Return(exp) => {
+            tokens.push("return".to_owned());
+            exp_tokens(exp, tokens);
+        }
+        IRCmd::Call(call) => {
+            tokens.push("call".to_owned());
+            call_tokens(call, tokens);
+        }
+    }
+}
+
+fn exp_tokens(exp: &IRExp, tokens: &mut Vec<String>) {
+    match exp {
+        IRExp::Temp(t) => tokens.push(format!("t{}.{}", t.name, t.ver)),
+        IRExp::ConstInt(val) => tokens.push(format!("i{val}")),
+        IRExp::ConstBool(val) => tokens.push(val.to_string()),
+        IRExp::Neg(exp) => {
+            tokens.push("neg".to_owned());
+            exp_tokens(exp, tokens);
+        }
+        IRExp::NotBool(exp) => {
+            tokens.push("not".to_owned());
+            exp_tokens(exp, tokens);
+        }
+        IRExp::NotInt(exp) => {
+            tokens.push("inv".to_owned());
+            exp_tokens(exp, tokens);
+        }
+        IRExp::Exp(b) => {
+            tokens.push(format!("op {}", b.1));
+            exp_tokens(&b.0, tokens);
+            exp_tokens(&b.2, tokens);
+        }
+        IRExp::Call(call) => {
+            tokens.push("call".to_owned());
+            call_tokens(call, tokens);
+        }
+    }
+}
+
+fn call_tokens(call: &Call, tokens: &mut Vec<String>) {
+    match call {
+        Call::Print(exp) => {
+            tokens.push("print".to_owned());
+            exp_tokens(exp, tokens);
+        }
+        Call::Read => tokens.push("read".to_owned()),
+        Call::Flush => tokens.push("flush".to_owned()),
+        Call::Func(name, args) => {
+            tokens.push(format!("func {name} {}", args.len()));
+            for arg in args.iter() {
+                exp_tokens(arg, tokens);
+            }
+        }
+    }
+}
+
+fn read_function<'t>(lines: &mut impl Iterator<Item = &'t str>, name: &str) -> Option<IRFunction> {
+    let header: Vec<usize> = lines
+        .next()?
+        .split_whitespace()
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    let [num_temps, num_params, count] = header[..] else {
+        return None;
+    };
+    let mut instructions = Vec::new();
+    for _ in 0..count {
+        let mut tokens = lines.next()?.split_whitespace();
+        instructions.push(read_cmd(&mut tokens)?);
+        if tokens.next().is_some() {
+            return None;
+        }
+    }
+    Some(IRFunction {
+        name: name.to_owned(),
+        num_temps,
+        num_params,
+        instructions,
+    })
+}
+
+fn read_cmd<'t>(tokens: &mut impl Iterator<Item = &'t str>) -> Option<IRCmd> {
+    let cmd = match tokens.next()? {
+        "load" => IRCmd::Load(read_exp(tokens)?, read_exp(tokens)?),
+        "jumpif" => {
+            let label = tokens.next()?.parse().ok()?;
+            IRCmd::JumpIf(read_exp(tokens)?, label)
+        }
+        "jump" => IRCmd::Jump(tokens.next()?.parse().ok()?),
+        "label" => IRCmd::Label(tokens.next()?.parse().ok()?),
+        "return" => IRCmd::Return(read_exp(tokens)?),
+        "call" => IRCmd::Call(read_call(tokens)?),
+        _ => return None,
+    };
+    Some(cmd)
+}
+
+fn read_exp<'t>(tokens: &mut impl Iterator<Item = &'t str>) -> Option<IRExp> {
+    let exp = match tokens.next()? {
+        "true" => IRExp::ConstBool(true),
+        "false" => IRExp::ConstBool(false),
+        "neg" => IRExp::Neg(Box::new(read_exp(tokens)?)),
+        "not" => IRExp::NotBool(Box::new(read_exp(tokens)?)),
+        "inv" => IRExp::NotInt(Box::new(read_exp(tokens)?)),
+        "op" => {
+            let op = tokens.next()?;
+            let op = OPS.into_iter().find(|o| o.to_string() == op)?;
+            IRExp::Exp(Box::new((read_exp(tokens)?, op, read_exp(tokens)?)))
+        }
+        "call" => IRExp::Call(Box::new(read_call(tokens)?)),
+        token => {
+            if let Some(val) = token.strip_prefix('i') {
+                IRExp::ConstInt(val.parse().ok()?)
+            } else {
+                let (name, ver) = token.strip_prefix('t')?.split_once('.')?;
+                IRExp::Temp(Temp {
+                    name: name.parse().ok()?,
+                    ver: ver.parse().ok()?,
+                })
+            }
+        }
+    };
+    Some(exp)
+}
+
+fn read_call<'t>(tokens: &mut impl Iterator<Item = &'t str>) -> Option<Call> {
+    let call = match tokens.next()? {
+        "print" => Call::Print(read_exp(tokens)?),
+        "read" => Call::Read,
+        "flush" => Call::Flush,
+        "func" => {
+            let name = tokens.next()?.to_owned();
+            let count: usize = tokens.next()?.parse().ok()?;
+            let args = (0..count)
+                .map(|_| read_exp(tokens))
+                .collect::<Option<_>>()?;
+            Call::Func(name, args)
+        }
+        _ => return None,
+    };
+    Some(call)
+}
@@ -0,0 +1,300 @@
+use std::{
+    env,
+    ffi::OsString,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use compdesign::{
+    Options,
+    code_gen::{LinkMode, Toolchain},
+    diagnostics::ErrorFormat,
+    error_codes,
+    optimize::OPTIMIZATIONS,
+};
+
+const USAGE: &str = "Usage: compdesign [options] <input>...
+
+Compiles a program to an x86-64 executable. A program may consist of several inputs that call
+each other's functions. An input calls a function it does not define through a declaration
+such as int helper(int x); Use - as input to read the program from stdin. For compatibility,
+compdesign <input> <output> writes to <output> unless it is an existing source. Inputs
+ending in .o or .a are object files and libraries to link the program with.
+
+Options:
+  -o <file>             Write the output to <file>
+  -c                    Write an object file per input instead of linking, same as --emit=obj
+  -S                    Write assembly per input instead of linking, same as --emit=asm
+  --emit=<stage>        Output of one stage: tokens, ast, abs, ir, asm, obj or exe
+  -O0, -O1, -O2         Optimization level, default -O0
+  -fno-<optimization>   Disable an optimization the level would run
+  -W<warning>           Enable a warning, -Wno-<warning> disables it, -Wall all of them
+  -Werror               Report warnings as errors
+  --error-format=<fmt>  Format of diagnostics: human or json
+  --explain <code>      Explain an error code, e.g. E0101
+  -j<n>                 Use <n> threads for register allocation and instruction selection,
+                        default one per core
+  --cache-dir <dir>     Keep the code of every function in <dir> and reuse it for the
+                        functions that did not change
+  --save-temps <dir>    Keep the assembly the executable or objects are built from in <dir>
+  --cc <compiler>       Assemble and link with <compiler> instead of $CC or gcc
+  --integrated-as       Encode object files with the built-in assembler, the compiler then
+                        only links them
+  -L<dir>               Search <dir> for the libraries of -l
+  -l<library>           Link the executable with <library>
+  -static, -pie         Link a static or a position independent executable
+  --freestanding        Link a static executable with a minimal runtime instead of the C
+                        library
+  -v                    Print the commands that assemble and link the program
+  --time-passes         Report the time and memory every compiler pass takes
+  --stats               Report counters such as created temps and spills
+  --help                Print this message
+
+Optimizations: -O1 runs const-fold, copy-prop and dce. -O2 runs inline, const-fold,
+copy-prop, cse and dce.
+
+Warnings, all off unless enabled: unused-variable, unused-parameter, unreachable-code,
+constant-condition, unreachable-function.
+
+Stage dumps are written to stdout unless -o is given. -c and -S name every output after its
+input, e.g. a.o for a.c0, and take -o only with a single input.";
+
+const SOURCE_EXTENSIONS: [&str; 5] = ["l1", "l2", "l3", "l4", "c0"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Abs,
+    Ir,
+    Asm,
+    Obj,
+    Exe,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    /* The units of the program, None reads one from stdin */
+    pub inputs: Vec<Option<PathBuf>>,
+    pub output: Option<PathBuf>,
+    /* The directory to keep intermediate files in, they are removed if there is none */
+    pub save_temps: Option<PathBuf>,
+    pub emit: Emit,
+    pub error_format: ErrorFormat,
+    pub options: Options,
+    pub toolchain: Toolchain,
+    pub time_passes: bool,
+    pub stats: bool,
+}
+
+impl Args {
+    /* The output path. Assembly and object files are written per input and named after it like
+     * gcc does, the other outputs are the same for every input. */
+    pub fn output_path(&self, input: Option<&Path>) -> Option<PathBuf> {
+        if let Some(output) = &self.output {
+            return Some(output.clone());
+        }
+        let stem = || PathBuf::from(unit_name(input));
+        match self.emit {
+            Emit::Exe => Some(PathBuf::from("a.out")),
+            Emit::Obj => Some(stem().with_extension("o")),
+            Emit::Asm => Some(stem().with_extension("s")),
+            Emit::Tokens | Emit::Ast | Emit::Abs | Emit::Ir => None,
+        }
+    }
+
+    /* Whether the inputs are the whole program. They are not if they are compiled to be linked
+     * later, or linked with object files and libraries that may define some of their functions. */
+    pub fn is_whole_program(&self) -> bool {
+        self.emit == Emit::Exe
+            && !self.toolchain.link_args.iter().any(|arg| {
+                let path = Path::new(arg);
+                is_object(path) || arg.to_string_lossy().starts_with("-l")
+            })
+    }
+}
+
+/* The name of the files made from an input, a for stdin */
+pub fn unit_name(input: Option<&Path>) -> String {
+    input
+        .and_then(|p| p.file_stem())
+        .map_or("a".to_owned(), |stem| stem.to_string_lossy().into_owned())
+}
+
+fn usage_error(message: &str) -> ! {
+    println!("Error: {message}");
+    println!("Try 'compdesign --help' for more information.");
+    exit(42);
+}
+
+/* Whether an input is an object file or a library, which only the linker reads */
+fn is_object(path: &Path) -> bool {
+    path.extension().is_some_and(|e| e == "o" || e == "a")
+}
+
+/* Whether a path names a program in one of the languages the compiler reads */
+fn is_source(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|e| SOURCE_EXTENSIONS.iter().any(|source| e == *source))
+}
+
+/* The number of threads of -j<n> or -j <n> */
+fn parse_jobs(jobs: Option<&str>) -> usize {
+    match jobs.and_then(|n| n.parse().ok()) {
+        Some(jobs) if jobs > 0 => jobs,
+        _ => usage_error("-j needs a positive number of threads."),
+    }
+}
+
+/* Parses the command line. Exits for --help, --explain and invalid arguments. */
+pub fn parse_args(mut raw_args: impl Iterator<Item = OsString>) -> Args {
+    let mut args = Args {
+        inputs: Vec::new(),
+        output: None,
+        save_temps: None,
+        emit: Emit::Exe,
+        error_format: ErrorFormat::Human,
+        options: Options::default(),
+        toolchain: Toolchain::default(),
+        time_passes: false,
+        stats: false,
+    };
+    if let Some(cc) = env::var("CC").ok().filter(|cc| !cc.trim().is_empty()) {
+        args.toolchain.cc = cc;
+    }
+    let mut positional = Vec::new();
+    while let Some(arg) = raw_args.next() {
+        let Some(text) = arg.to_str() else {
+            positional.push(arg);
+            continue;
+        };
+        match text {
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                exit(0);
+            }
+            "--explain" => {
+                let code = raw_args.next().unwrap_or_default();
+                let code = code.to_string_lossy();
+                match error_codes::explain(&code) {
+                    Some(text) => {
+                        println!("{}\n\n{text}", code.to_ascii_uppercase());
+                        exit(0);
+                    }
+                    None => usage_error(&format!("Unknown error code \"{code}\".")),
+                }
+            }
+            "-o" => match raw_args.next() {
+                Some(output) => args.output = Some(PathBuf::from(output)),
+                None => usage_error("-o needs an output path."),
+            },
+            "-c" => args.emit = Emit::Obj,
+            "-S" => args.emit = Emit::Asm,
+            "-j" => {
+                args.options.jobs = parse_jobs(raw_args.next().as_ref().and_then(|n| n.to_str()))
+            }
+            "--cache-dir" => match raw_args.next() {
+                Some(dir) => args.options.cache_dir = Some(PathBuf::from(dir)),
+                None => usage_error("--cache-dir needs a directory."),
+            },
+            "--save-temps" => match raw_args.next() {
+                Some(dir) => args.save_temps = Some(PathBuf::from(dir)),
+                None => usage_error("--save-temps needs a directory."),
+            },
+            "--cc" => match raw_args.next().and_then(|cc| cc.into_string().ok()) {
+                Some(cc) if !cc.trim().is_empty() => args.toolchain.cc = cc,
+                _ => usage_error("--cc needs a compiler."),
+            },
+            "-L" | "-l" => match raw_args.next() {
+                Some(value) => {
+                    let mut arg = arg;
+                    arg.push(value);
+                    args.toolchain.link_args.push(arg);
+                }
+                None => usage_error(&format!("{text} needs an argument.")),
+            },
+            "-static" => args.toolchain.link_mode = LinkMode::Static,
+            "-pie" => args.toolchain.link_mode = LinkMode::Pie,
+            "--freestanding" => args.toolchain.freestanding = true,
+            "-v" => args.toolchain.verbose = true,
+            "--integrated-as" => args.toolchain.integrated_as = true,
+            "--time-passes" => args.time_passes = true,
+            "--stats" => args.stats = true,
+            "-O0" => args.options.opt_level = 0,
+            "-O1" => args.options.opt_level = 1,
+            "-O2" => args.options.opt_level = 2,
+            "-" => positional.push(arg),
+            _ => {
+                if let Some(stage) = text.strip_prefix("--emit=") {
+                    args.emit = match stage {
+                        "tokens" => Emit::Tokens,
+                        "ast" => Emit::Ast,
+                        "abs" => Emit::Abs,
+                        "ir" => Emit::Ir,
+                        "asm" => Emit::Asm,
+                        "obj" => Emit::Obj,
+                        "exe" => Emit::Exe,
+                        _ => usage_error(&format!(
+                            "Unknown stage \"{stage}\", expected tokens, ast, abs, ir, asm, obj or exe."
+                        )),
+                    };
+                } else if let Some(format) = text.strip_prefix("--error-format=") {
+                    args.error_format = match format {
+                        "human" => ErrorFormat::Human,
+                        "json" => ErrorFormat::Json,
+                        _ => usage_error(&format!(
+                            "Unknown error format \"{format}\", expected human or json."
+                        )),
+                    };
+                } else if let Some(flag) = text.strip_prefix("-W") {
+                    if !args.options.warnings.apply_flag(flag) {
+                        usage_error(&format!("Unknown warning \"{flag}\"."));
+                    }
+                } else if text.starts_with("-L") || text.starts_with("-l") {
+                    args.toolchain.link_args.push(arg);
+                } else if let Some(jobs) = text.strip_prefix("-j") {
+                    args.options.jobs = parse_jobs(Some(jobs));
+                } else if let Some(name) = text.strip_prefix("-fno-") {
+                    match OPTIMIZATIONS.iter().find(|o| **o == name) {
+                        Some(optimization) => {
+                            args.options.disabled_optimizations.push(optimization)
+                        }
+                        None => usage_error(&format!("Unknown optimization \"{name}\".")),
+                    }
+                } else if text.starts_with('-') {
+                    usage_error(&format!("Unknown option \"{text}\"."));
+                } else if is_object(Path::new(text)) {
+                    args.toolchain.link_args.push(arg);
+                } else {
+                    positional.push(arg);
+                }
+            }
+        }
+    }
+    /* The old form "compdesign <input> <output>", unless the output would overwrite a source */
+    if let [_, output] = &positional[..]
+        && args.output.is_none()
+        && output != "-"
+        && !(Path::new(output).exists() && is_source(Path::new(output)))
+    {
+        args.output = Some(PathBuf::from(output));
+        positional.pop();
+    }
+    if positional.is_empty() {
+        usage_error("No input file.");
+    }
+    if positional.iter().filter(|input| *input == "-").count() > 1 {
+        usage_error("stdin can only be read once.");
+    }
+    if positional.len() > 1 && args.output.is_some() && matches!(args.emit, Emit::Asm | Emit::Obj) {
+        usage_error("-o cannot be used with -c or -S and several inputs.");
+    }
+    if args.toolchain.freestanding && args.toolchain.link_mode == LinkMode::Pie {
+        usage_error("-pie cannot be used with --freestanding.");
+    }
+    args.inputs = positional
+        .into_iter()
+        .map(|input| (input != "-").then(|| PathBuf::from(input)))
+        .collect();
+    args
+}
@@ -1,36 +1,423 @@
 use std::{
-    ffi::OsString,
-    io::Write,
-    process::{Command, Stdio},
+    env,
+    ffi::{OsStr, OsString},
+    fs,
+    io::{self, BufWriter, Write},
+    iter,
+    path::{Path, PathBuf},
+    process::{self, Command, ExitStatus, Stdio},
 };
 
-use crate::{instruction_selection::translate_functions, ir::IRFunction};
-
-pub fn create_binary(program_in_ir: &mut Vec<IRFunction<'_>>, string: OsString) {
-    let mut assembly = ".intel_syntax noprefix
-        .global main
-        .global _main
-        .text
-        main:
-        call _main
-        mov rdi, rax
-        mov rax, 0x3C
-        syscall
-        _main:
-"
-    .to_string();
-    translate_functions(program_in_ir, &mut assembly);
-    //println!("{}", assembly);
-    let output_file = string.to_str().unwrap();
-    /*let output_file = "this_file";*/
-    let mut child = Command::new("gcc")
-        .args(["-xassembler", "-o", output_file, "-"])
-        .stdin(Stdio::piped())
-        .spawn()
-        .expect("Failed to spawn child process");
-    let stdin = child.stdin.as_mut().expect("Failed to open stdin");
-    stdin
-        .write_all(assembly.as_bytes())
-        .expect("Failed to write to stdin");
-    child.wait().expect("gcc couldn't finish execution");
+use crate::{
+    diagnostics::Diagnostics,
+    elf::write_object,
+    error_codes,
+    instruction_selection::translate_function,
+    ir::{IRFunction, symbol},
+    runtime::RUNTIME,
+    x86::{Code, Inst, Reg},
+};
+
+/* The entry point, which calls main and exits with its return value */
+fn entry() -> [Inst; 4] {
+    [
+        Inst::Call(symbol("main")),
+        Inst::Mov64(Reg::Rdi, Reg::Rax),
+        Inst::Mov64Imm(Reg::Rax, 0x3C),
+        Inst::Syscall,
+    ]
+}
+
+/* The instructions of one unit, kept per function in the order of the source so that the
+ * functions can be stored in the cache one by one. They are only put together when they are
+ * written as assembly or encoded into an object file. */
+#[derive(Debug, Clone, Default)]
+pub struct Assembly {
+    pub names: Vec<String>,
+    pub functions: Vec<Vec<Inst>>,
+}
+
+impl Assembly {
+    /* Generates the assembly of one unit. Every function needs the coloring of its temps and its
+     * dead stores. */
+    pub fn generate(
+        program_in_ir: &[IRFunction],
+        colorings: &[Vec<usize>],
+        dead_stores: &[Vec<bool>],
+    ) -> Self {
+        Assembly {
+            names: program_in_ir.iter().map(|f| f.name.clone()).collect(),
+            functions: program_in_ir
+                .iter()
+                .zip(colorings.iter())
+                .zip(dead_stores.iter())
+                .map(|((f, coloring), dead_stores)| translate_function(f, coloring, dead_stores))
+                .collect(),
+        }
+    }
+
+    fn main(&self) -> Option<usize> {
+        self.names.iter().position(|name| name == "main")
+    }
+
+    /* The functions in the order they are written, main first */
+    fn ordered(&self) -> impl Iterator<Item = usize> {
+        let main = self.main();
+        let others = (0..self.names.len()).filter(move |i| Some(*i) != main);
+        main.into_iter().chain(others)
+    }
+
+    /* Writes the functions of the unit, main first if the unit defines it. The functions are
+     * global so that other units can call them. The unit that defines main also gets the entry
+     * point. */
+    pub fn write_to(&self, out: &mut dyn Write) -> io::Result<()> {
+        let main = self.main();
+        writeln!(out, ".intel_syntax noprefix")?;
+        if main.is_some() {
+            writeln!(out, ".global main")?;
+        }
+        for name in self.names.iter() {
+            writeln!(out, ".global {}", symbol(name))?;
+        }
+        writeln!(out, ".text")?;
+        if main.is_some() {
+            writeln!(out, "main:")?;
+            for inst in entry() {
+                writeln!(out, "        {inst}")?;
+            }
+        }
+        for i in self.ordered() {
+            writeln!(out, "\n{}:", symbol(&self.names[i]))?;
+            for inst in self.functions[i].iter() {
+                writeln!(out, "{inst}")?;
+            }
+        }
+        Ok(())
+    }
+
+    /* Writes the unit as assembly or as an object file */
+    pub fn write_as(&self, format: Format, out: &mut dyn Write) -> io::Result<()> {
+        match format {
+            Format::Assembly => self.write_to(out),
+            Format::Object => write_object(&self.encode(), out),
+        }
+    }
+
+    /* Encodes the unit into machine code, laid out like the assembly */
+    pub fn encode(&self) -> Code {
+        let mut code = Code::new();
+        if self.main().is_some() {
+            code.symbol("main");
+            for inst in entry() {
+                code.encode(&inst);
+            }
+        }
+        for i in self.ordered() {
+            code.symbol(&symbol(&self.names[i]));
+            for inst in self.functions[i].iter() {
+                code.encode(inst);
+            }
+        }
+        code.finish();
+        code
+    }
+
+    /* The number of instructions, without labels */
+    pub fn instructions(&self) -> usize {
+        let entry = self.main().map_or(0, |_| entry().len());
+        let functions = self.functions.iter().flatten();
+        entry + functions.filter(|i| !matches!(i, Inst::Label(_))).count()
+    }
+}
+
+/* What the instructions of a unit are written as */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Assembly,
+    /* A relocatable object, encoded by the built-in assembler */
+    Object,
+}
+
+impl Format {
+    fn extension(self) -> &'static str {
+        match self {
+            Format::Assembly => "s",
+            Format::Object => "o",
+        }
+    }
+}
+
+/* How the program is linked into an executable */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LinkMode {
+    /* Whatever the C compiler builds by default */
+    #[default]
+    Default,
+    Static,
+    Pie,
+}
+
+/* The C compiler that assembles and links the units, and what it links them with */
+#[derive(Debug, Clone)]
+pub struct Toolchain {
+    /* The compiler and the arguments it always gets, split at whitespace like make does */
+    pub cc: String,
+    /* Encode the units into object files without the assembler of the compiler, which then
+     * only links them */
+    pub integrated_as: bool,
+    /* -L and -l options and object files, in the order of the command line */
+    pub link_args: Vec<OsString>,
+    pub link_mode: LinkMode,
+    /* Link a static executable with the runtime instead of the C library */
+    pub freestanding: bool,
+    /* Print every command before it runs */
+    pub verbose: bool,
+}
+
+impl Default for Toolchain {
+    fn default() -> Self {
+        Toolchain {
+            cc: "gcc".to_owned(),
+            integrated_as: false,
+            link_args: Vec::new(),
+            link_mode: LinkMode::Default,
+            freestanding: false,
+            verbose: false,
+        }
+    }
+}
+
+/* The assembly the C compiler gets, through stdin or in files */
+enum Sources<'a> {
+    Stdin(&'a Assembly),
+    Files(Vec<PathBuf>),
+}
+
+impl Toolchain {
+    fn program(&self) -> &str {
+        self.cc.split_whitespace().next().unwrap_or("gcc")
+    }
+
+    /* Runs the C compiler with args followed by the sources. Linking adds the libraries and
+     * object files of the command line after the sources. */
+    fn run(&self, args: &[&OsStr], sources: Sources, link: bool) -> Result<(), BuildError> {
+        let mut cc = Command::new(self.program());
+        cc.args(self.cc.split_whitespace().skip(1)).args(args);
+        match &sources {
+            Sources::Stdin(_) => {
+                cc.arg("-xassembler").arg("-").stdin(Stdio::piped());
+            }
+            Sources::Files(paths) => {
+                cc.args(paths);
+            }
+        }
+        if link && !self.link_args.is_empty() {
+            /* Otherwise the object files after stdin would be read as assembly as well */
+            if let Sources::Stdin(_) = sources {
+                cc.arg("-xnone");
+            }
+            cc.args(&self.link_args);
+        }
+        if self.verbose {
+            eprintln!("{}", show_command(&cc));
+        }
+        let not_run = |error| BuildError::NotRun(self.program().to_owned(), error);
+        let mut child = cc.spawn().map_err(not_run)?;
+        if let Sources::Stdin(assembly) = sources {
+            let mut pipe = BufWriter::new(child.stdin.take().unwrap());
+            let written = assembly.write_to(&mut pipe).and_then(|_| pipe.flush());
+            /* Close stdin before waiting so that the compiler sees the end of its input */
+            drop(pipe);
+            if let Err(error) = written {
+                /* The compiler stops reading when it fails, its status tells why */
+                let status = child.wait().map_err(not_run)?;
+                if status.success() {
+                    return Err(BuildError::NotRun(self.program().to_owned(), error));
+                }
+                return Err(BuildError::Failed(self.program().to_owned(), status));
+            }
+        }
+        let status = child.wait().map_err(not_run)?;
+        if !status.success() {
+            return Err(BuildError::Failed(self.program().to_owned(), status));
+        }
+        Ok(())
+    }
+}
+
+/* A command the way a shell would run it, quoting the arguments that need it */
+fn show_command(command: &Command) -> String {
+    iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(|arg| {
+            let arg = arg.to_string_lossy();
+            let plain = |c: char| c.is_ascii_alphanumeric() || "-_./=+,:@%".contains(c);
+            if !arg.is_empty() && arg.chars().all(plain) {
+                arg.into_owned()
+            } else {
+                format!("'{}'", arg.replace('\'', "'\\''"))
+            }
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/* Why an object file or an executable could not be built */
+#[derive(Debug)]
+pub enum BuildError {
+    /* The C compiler could not be started */
+    NotRun(String, io::Error),
+    Failed(String, ExitStatus),
+    /* The assembly could not be written to an intermediate file */
+    Write(PathBuf, io::Error),
+}
+
+impl BuildError {
+    pub fn report(&self, diags: &mut Diagnostics) {
+        match self {
+            BuildError::NotRun(cc, error) if error.kind() == io::ErrorKind::NotFound => {
+                diags.error_file(
+                    error_codes::TOOLCHAIN_NOT_RUN,
+                    &format!(
+                        "C compiler \"{cc}\" not found. Choose another one with --cc or the CC environment variable."
+                    ),
+                )
+            }
+            BuildError::NotRun(cc, error) => diags.error_file(
+                error_codes::TOOLCHAIN_NOT_RUN,
+                &format!("Unable to run the C compiler \"{cc}\": {error}."),
+            ),
+            BuildError::Failed(cc, status) => diags.error_file(
+                error_codes::TOOLCHAIN_FAILED,
+                &format!("C compiler \"{cc}\" failed with {status}."),
+            ),
+            BuildError::Write(path, error) => diags.error_file(
+                error_codes::INTERMEDIATE_FILE,
+                &format!("Unable to write \"{}\": {error}.", path.display()),
+            ),
+        };
+    }
+}
+
+/* The intermediate files of a build. They are kept in the directory the user chose, or else
+ * written to the temporary directory and removed again when the build is done. */
+pub struct Temps {
+    dir: PathBuf,
+    keep: bool,
+    created: Vec<PathBuf>,
+}
+
+impl Temps {
+    pub fn new(keep_in: Option<&Path>) -> Self {
+        Temps {
+            dir: keep_in.map_or_else(env::temp_dir, Path::to_path_buf),
+            keep: keep_in.is_some(),
+            created: Vec::new(),
+        }
+    }
+
+    /* Writes an intermediate file named after the unit it is made from */
+    fn write(
+        &mut self,
+        name: &str,
+        extension: &str,
+        contents: &dyn Fn(&mut dyn Write) -> io::Result<()>,
+    ) -> Result<PathBuf, BuildError> {
+        let mut file_name = format!("{name}.{extension}");
+        if !self.keep {
+            file_name = format!("compdesign-{}-{}", process::id(), file_name);
+        }
+        let path = self.dir.join(file_name);
+        if self.created.contains(&path) {
+            /* Two units with the same name, e.g. a/x.c0 and b/x.c0 */
+            return self.write(
+                &format!("{name}-{}", self.created.len()),
+                extension,
+                contents,
+            );
+        }
+        if self.keep {
+            fs::create_dir_all(&self.dir)
+                .map_err(|error| BuildError::Write(self.dir.clone(), error))?;
+        }
+        self.created.push(path.clone());
+        write_file(&path, contents).map_err(|error| BuildError::Write(path.clone(), error))?;
+        Ok(path)
+    }
+}
+
+impl Drop for Temps {
+    fn drop(&mut self) {
+        if !self.keep {
+            for path in self.created.iter() {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+fn write_file(path: &Path, contents: &dyn Fn(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+    let mut out = BufWriter::new(fs::File::create(path)?);
+    contents(&mut out)?;
+    out.flush()
+}
+
+/* Assembles the units, given with their names, and links them into an executable */
+pub fn create_binary(
+    toolchain: &Toolchain,
+    units: &[(&str, &Assembly)],
+    output: &Path,
+    temps: &mut Temps,
+) -> Result<(), BuildError> {
+    let mode: &[&str] = match toolchain.link_mode {
+        _ if toolchain.freestanding => &["-nostdlib", "-static"],
+        LinkMode::Default => &[],
+        LinkMode::Static => &["-static"],
+        LinkMode::Pie => &["-pie"],
+    };
+    let mut args: Vec<&OsStr> = mode.iter().map(OsStr::new).collect();
+    args.extend(["-o".as_ref(), output.as_os_str()]);
+    if let [(_, assembly)] = units
+        && !temps.keep
+        && !toolchain.integrated_as
+        && !toolchain.freestanding
+    {
+        return toolchain.run(&args, Sources::Stdin(assembly), true);
+    }
+    /* The compiler reads only one file from stdin, so the units and the runtime go through files */
+    let format = match toolchain.integrated_as {
+        true => Format::Object,
+        false => Format::Assembly,
+    };
+    let mut paths = Vec::new();
+    for (name, assembly) in units {
+        paths.push(temps.write(name, format.extension(), &|out| {
+            assembly.write_as(format, out)
+        })?);
+    }
+    if toolchain.freestanding {
+        paths.push(temps.write("runtime", "s", &|out| out.write_all(RUNTIME.as_bytes()))?);
+    }
+    toolchain.run(&args, Sources::Files(paths), true)
+}
+
+/* Assembles the assembly of a unit into an object file without linking it. The built-in
+ * assembler writes the object itself. */
+pub fn create_object(
+    toolchain: &Toolchain,
+    name: &str,
+    assembly: &Assembly,
+    output: &Path,
+    temps: &mut Temps,
+) -> Result<(), BuildError> {
+    if toolchain.integrated_as {
+        return write_file(output, &|out| assembly.write_as(Format::Object, out))
+            .map_err(|error| BuildError::Write(output.to_path_buf(), error));
+    }
+    let args = ["-c".as_ref(), "-o".as_ref(), output.as_os_str()];
+    if temps.keep {
+        let path = temps.write(name, "s", &|out| assembly.write_to(out))?;
+        return toolchain.run(&args, Sources::Files(vec![path]), false);
+    }
+    toolchain.run(&args, Sources::Stdin(assembly), false)
 }
@@ -1,4 +1,17 @@
-use crate::{ir::IRFunction, liveness::analyze_func};
+use std::collections::HashSet;
+
+use crate::{
+    ir::{IRCmd, IRExp},
+    liveness::live_out,
+};
+
+/* Colors from this one on are stack slots, the ones below are registers */
+const SPILL_COLOR: usize = 11;
+
+/* The number of parameters passed in registers */
+const PARAM_REGS: usize = 6;
+
+const UNCOLORED: usize = usize::MAX;
 
 #[derive(Debug)]
 struct Node {
@@ -6,18 +19,32 @@ struct Node {
     neighbors: Vec<usize>,
 }
 
-pub fn color_func(f: &mut IRFunction) -> Vec<usize> {
-    let live_temps = analyze_func(&mut f.instructions);
-    let mut edges = build_interference(&live_temps, f.num_temps);
-    let nodes = order_nodes(&mut edges);
-    color_greedy(&nodes)
+/* Colors the interference graph given by the commands and the live temps of every line. The
+ * parameters passed in registers are moved to the register of their own index on entry, so they
+ * keep that color. */
+pub fn color_func(
+    cmds: &[IRCmd],
+    live_temps: &[Vec<usize>],
+    num_temps: usize,
+    num_params: usize,
+) -> Vec<usize> {
+    let edges = build_interference(cmds, live_temps, num_temps);
+    let nodes = order_nodes(edges);
+    color_greedy(&nodes, num_params.min(PARAM_REGS))
 }
 
-/*Creates a vector with a color for every node in increasing node order */
-fn color_greedy(ordered_nodes: &[Node]) -> Vec<usize> {
-    let mut coloring = Vec::new();
-    coloring.resize_with(ordered_nodes.len(), || 0);
-    for n in ordered_nodes.iter() {
+/* Whether a temp with this color lives on the stack instead of a register */
+pub fn is_spilled(color: usize) -> bool {
+    color >= SPILL_COLOR
+}
+
+/*Creates a vector with a color for every node in increasing node order, the first nodes are
+ * precolored with their index */
+fn color_greedy(ordered_nodes: &[Node], precolored: usize) -> Vec<usize> {
+    let mut coloring: Vec<usize> = (0..ordered_nodes.len())
+        .map(|ind| if ind < precolored { ind } else { UNCOLORED })
+        .collect();
+    for n in ordered_nodes.iter().filter(|n| n.ind >= precolored) {
         let mut min_color = 0;
         while n
             .neighbors
@@ -31,44 +58,43 @@ fn color_greedy(ordered_nodes: &[Node]) -> Vec<usize> {
     coloring
 }
 
-/* Creates a vector that decsribes the node order */
-fn order_nodes(edges: &mut Vec<Vec<usize>>) -> Vec<Node> {
-    let num_nodes = edges.len();
-    let mut weights: Vec<(usize, usize)> = Vec::new();
-    let mut nodes_ordered = Vec::new();
-    let mut x = 0;
-    weights.resize_with(num_nodes, || {
-        x += 1;
-        ((x - 1), 0)
-    });
-    for _ in 0..num_nodes {
-        let (i, (n, _)) = weights.iter().enumerate().max_by_key(|x| *x).unwrap();
-        let current = edges.swap_remove(i);
-        nodes_ordered.push(Node {
-            ind: *n,
-            neighbors: current,
-        });
-        weights.swap_remove(i);
-        for node in nodes_ordered.last().unwrap().neighbors.iter() {
-            if let Some(w) = weights.iter_mut().find(|x| x.0 == *node) {
-                w.1 += 1;
-            }
-        }
-    }
-    nodes_ordered
+/* Creates a vector that decsribes the node order, from the highest temp down */
+fn order_nodes(edges: Vec<Vec<usize>>) -> Vec<Node> {
+    edges
+        .into_iter()
+        .enumerate()
+        .rev()
+        .map(|(ind, neighbors)| Node { ind, neighbors })
+        .collect()
 }
 
-fn build_interference(live_temps: &[Vec<usize>], num_temps: usize) -> Vec<Vec<usize>> {
+/* Temps interfere if they are live at the same line, and a temp interferes with every temp that
+ * is live after a line that defines it, even if the definition itself is dead */
+fn build_interference(
+    cmds: &[IRCmd],
+    live_temps: &[Vec<usize>],
+    num_temps: usize,
+) -> Vec<Vec<usize>> {
     let mut edges: Vec<Vec<usize>> = Vec::new();
     edges.resize_with(num_temps, Vec::new);
+    let mut seen = HashSet::new();
+    let mut add = |t: usize, neighbor: usize| {
+        if neighbor != t && seen.insert((t, neighbor)) {
+            edges[t].push(neighbor);
+        }
+    };
     for line in live_temps.iter() {
         line.iter().for_each(|t| {
-            line.iter().for_each(|neighbor| {
-                if *neighbor != *t && !edges[*t].contains(neighbor) {
-                    edges[*t].push(*neighbor);
-                }
-            })
+            line.iter().for_each(|neighbor| add(*t, *neighbor));
         });
     }
+    for (cmd, out) in cmds.iter().zip(live_out(cmds, live_temps)) {
+        if let IRCmd::Load(IRExp::Temp(t), _) = cmd {
+            for neighbor in out {
+                add(t.name, neighbor);
+                add(neighbor, t.name);
+            }
+        }
+    }
     edges
 }
@@ -0,0 +1,220 @@
+use crate::{
+    arena::Arena,
+    ast::{Exp, ExpId, Var},
+    diagnostics::Diagnostics,
+    elaboration::{Abs, AbsId},
+    error_codes,
+    symbols::{DeclId, DeclSet, SymbolTable},
+};
+
+/* The variables that are definitely assigned at a program point. Code after a return, break
+ * or continue is unreachable and treats every variable as assigned. */
+#[derive(Debug, Clone)]
+enum Assigned {
+    Unreachable,
+    Vars(DeclSet),
+}
+
+impl Assigned {
+    fn contains(&self, id: DeclId) -> bool {
+        match self {
+            Assigned::Unreachable => true,
+            Assigned::Vars(vars) => vars.contains(id),
+        }
+    }
+
+    fn insert(&mut self, id: DeclId) {
+        if let Assigned::Vars(vars) = self {
+            vars.insert(id);
+        }
+    }
+
+    /* Joins two control flow paths, a variable is assigned if it is assigned on both */
+    fn meet(self, other: Assigned) -> Assigned {
+        match (self, other) {
+            (Assigned::Unreachable, a) | (a, Assigned::Unreachable) => a,
+            (Assigned::Vars(mut vars), Assigned::Vars(other)) => {
+                vars.intersect(&other);
+                Assigned::Vars(vars)
+            }
+        }
+    }
+}
+
+/* The states at every break and continue of the innermost loop */
+struct LoopExits {
+    breaks: Assigned,
+    continues: Assigned,
+}
+
+struct Analysis<'d> {
+    diags: &'d mut Diagnostics,
+    nodes: &'d Arena<Abs>,
+    exps: &'d Arena<Exp>,
+    symbols: &'d SymbolTable,
+    loops: Vec<LoopExits>,
+}
+
+/* Checks that every variable is definitely assigned before it is read and reports every offending
+ * use. Variables must have been bound to their declarations by name resolution, which reports the
+ * ones that are not declared, those are skipped. */
+pub fn definite_assignment_check(
+    diags: &mut Diagnostics,
+    nodes: &Arena<Abs>,
+    exps: &Arena<Exp>,
+    symbols: &SymbolTable,
+    params: &[Var],
+    abs: AbsId,
+) {
+    let mut analysis = Analysis {
+        diags,
+        nodes,
+        exps,
+        symbols,
+        loops: Vec::new(),
+    };
+    let params = params.iter().filter_map(|p| p.id).collect();
+    analysis.statement(abs, Assigned::Vars(params));
+}
+
+impl Analysis<'_> {
+    fn uses(&mut self, exp: ExpId, state: &Assigned) {
+        let mut idents = Vec::new();
+        self.exps.visit(exp, &mut |e| {
+            if let Exp::Ident(var, span) = e {
+                idents.push((*var, *span));
+            }
+        });
+        for (var, span) in idents {
+            let Some(id) = var.id else {
+                continue;
+            };
+            if !state.contains(id) {
+                self.diags.error(
+                    error_codes::UNASSIGNED_VARIABLE,
+                    span,
+                    &format!(
+                        "Variable \"{}\" is used before it is definitely assigned.",
+                        self.symbols.name(var.name)
+                    ),
+                );
+            }
+        }
+    }
+
+    /* Returns the state after abs given the state before it */
+    fn statement(&mut self, abs: AbsId, mut state: Assigned) -> Assigned {
+        let nodes = self.nodes;
+        match &nodes[abs] {
+            Abs::ASGN(var, exp, _) => {
+                self.uses(*exp, &state);
+                if let Some(id) = var.id {
+                    state.insert(id);
+                }
+                state
+            }
+            Abs::EXP(exp) => {
+                self.uses(*exp, &state);
+                state
+            }
+            Abs::CALL(_, args, _) => {
+                for exp in args {
+                    self.uses(*exp, &state);
+                }
+                state
+            }
+            Abs::RET(exp, _) => {
+                self.uses(*exp, &state);
+                Assigned::Unreachable
+            }
+            Abs::BRK(_) => {
+                /* A break outside of a loop is reported by break_coninue_check */
+                if let Some(exits) = self.loops.pop() {
+                    self.loops.push(LoopExits {
+                        breaks: exits.breaks.meet(state),
+                        continues: exits.continues,
+                    });
+                }
+                Assigned::Unreachable
+            }
+            Abs::CONT(_) => {
+                if let Some(exits) = self.loops.pop() {
+                    self.loops.push(LoopExits {
+                        breaks: exits.breaks,
+                        continues: exits.continues.meet(state),
+                    });
+                }
+                Assigned::Unreachable
+            }
+            /* Every declaration has an id of its own, which cannot be assigned before it */
+            Abs::DECL(_, _, body, _) => self.statement(*body, state),
+            Abs::IF(exp, abs1, abs2, _) => {
+                self.uses(*exp, &state);
+                let then_state = self.statement(*abs1, state.clone());
+                let else_state = self.statement(*abs2, state);
+                then_state.meet(else_state)
+            }
+            Abs::WHILE(exp, body, _) => {
+                self.uses(*exp, &state);
+                let exits = self.in_loop(|analysis| {
+                    analysis.statement(*body, state.clone());
+                });
+                /* The condition may be false on entry, and assignments only add variables, so
+                 * the body and continue states never weaken the state at the condition */
+                state.meet(exits.breaks)
+            }
+            Abs::FOR(body, _) => self.for_loop(*body, state),
+            Abs::SEQ(items) => {
+                for abs in items {
+                    state = self.statement(*abs, state);
+                }
+                state
+            }
+        }
+    }
+
+    /* A for loop is elaborated into its initializer, the condition as an EXP, the body and the
+     * step as the last statement. An initializer declaration wraps all of them in a DECL. */
+    fn for_loop(&mut self, abs: AbsId, mut state: Assigned) -> Assigned {
+        let nodes = self.nodes;
+        match &nodes[abs] {
+            Abs::DECL(_, _, body, _) => self.for_loop(*body, state),
+            Abs::SEQ(items) => {
+                let Some(cond) = items
+                    .iter()
+                    .position(|abs| matches!(nodes[*abs], Abs::EXP(_)))
+                else {
+                    return self.statement(abs, state);
+                };
+                for abs in items[..cond].iter() {
+                    state = self.statement(*abs, state);
+                }
+                self.statement(items[cond], state.clone());
+                let (body, step) = items[cond + 1..].split_at(items.len().saturating_sub(cond + 2));
+                let mut end = Assigned::Unreachable;
+                let exits = self.in_loop(|analysis| {
+                    let mut body_state = state.clone();
+                    for abs in body {
+                        body_state = analysis.statement(*abs, body_state);
+                    }
+                    end = body_state;
+                });
+                /* The step runs after the body and after every continue */
+                for abs in step {
+                    self.statement(*abs, end.clone().meet(exits.continues.clone()));
+                }
+                state.meet(exits.breaks)
+            }
+            _ => self.statement(abs, state),
+        }
+    }
+
+    fn in_loop(&mut self, f: impl FnOnce(&mut Self)) -> LoopExits {
+        self.loops.push(LoopExits {
+            breaks: Assigned::Unreachable,
+            continues: Assigned::Unreachable,
+        });
+        f(self);
+        self.loops.pop().unwrap()
+    }
+}
@@ -0,0 +1,336 @@
+use std::fmt::Write;
+
+use crate::symbols::{Interner, Symbol};
+
+/* A half-open byte range [start, end) into the source file */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span { start, end }
+    }
+
+    /* The smallest span covering both spans */
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start.min(other.start),
+            end: self.end.max(other.end),
+        }
+    }
+}
+
+pub struct SourceFile<'a> {
+    pub path: String,
+    pub input: &'a [u8],
+}
+
+impl<'a> SourceFile<'a> {
+    pub fn new(path: String, input: &'a [u8]) -> SourceFile<'a> {
+        SourceFile { path, input }
+    }
+
+    /* Returns the 1-based line and column of a byte offset */
+    pub fn line_col(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.input.len());
+        let before = &self.input[..offset];
+        let line = before.iter().filter(|c| **c == b'\n').count() + 1;
+        let line_start = before
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |i| i + 1);
+        (line, offset - line_start + 1)
+    }
+
+    /* Returns the text of the line containing the byte offset, without the newline */
+    fn line_text(&self, offset: usize) -> &'a [u8] {
+        let offset = offset.min(self.input.len());
+        let start = self.input[..offset]
+            .iter()
+            .rposition(|c| *c == b'\n')
+            .map_or(0, |i| i + 1);
+        let end = self.input[offset..]
+            .iter()
+            .position(|c| *c == b'\n')
+            .map_or(self.input.len(), |i| offset + i);
+        &self.input[start..end]
+    }
+
+    /* Formats a message as `file:line:col`, followed by the source line underlined with mark */
+    fn render(&self, span: Span, kind: &str, message: &str, mark: &str) -> String {
+        let (line, col) = self.line_col(span.start);
+        let text = String::from_utf8_lossy(self.line_text(span.start)).replace('\t', " ");
+        let line_no = line.to_string();
+        let gutter = " ".repeat(line_no.len());
+        let line_len = text.len() + 1;
+        let width = span
+            .end
+            .saturating_sub(span.start)
+            .clamp(1, line_len.saturating_sub(col).max(1));
+        format!(
+            "{}:{line}:{col}: {kind}: {message}\n{gutter} |\n{line_no} | {text}\n{gutter} | {}{}\n",
+            self.path,
+            " ".repeat(col - 1),
+            mark.repeat(width),
+        )
+    }
+
+    fn span_to_json(&self, span: Span) -> String {
+        let (line, col) = self.line_col(span.start);
+        let (end_line, end_col) = self.line_col(span.end);
+        format!(
+            "{{\"start\":{},\"end\":{},\"line\":{line},\"column\":{col},\"end_line\":{end_line},\"end_column\":{end_col}}}",
+            span.start, span.end
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl Severity {
+    fn name(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+    Human,
+    Json,
+}
+
+/* A replacement for the text in span that fixes the diagnostic */
+#[derive(Debug, Clone)]
+pub struct Suggestion {
+    pub span: Span,
+    pub replacement: String,
+    pub message: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub code: &'static str,
+    pub span: Option<Span>,
+    pub message: String,
+    pub labels: Vec<(Span, String)>,
+    pub suggestion: Option<Suggestion>,
+}
+
+impl Diagnostic {
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /* Adds a secondary location, e.g. a previous declaration */
+    pub fn label(&mut self, span: Span, message: &str) -> &mut Diagnostic {
+        self.labels.push((span, message.to_owned()));
+        self
+    }
+
+    pub fn suggest(&mut self, span: Span, replacement: &str, message: &str) -> &mut Diagnostic {
+        self.suggestion = Some(Suggestion {
+            span,
+            replacement: replacement.to_owned(),
+            message: message.to_owned(),
+        });
+        self
+    }
+
+    fn render_human(&self, src: &SourceFile) -> String {
+        let kind = format!("{}[{}]", self.severity.name(), self.code);
+        let mut out = match self.span {
+            Some(span) => src.render(span, &kind, &self.message, "^"),
+            None => format!("{}: {kind}: {}\n", src.path, self.message),
+        };
+        for (span, message) in self.labels.iter() {
+            out.push_str(&src.render(*span, "note", message, "-"));
+        }
+        if let Some(s) = &self.suggestion {
+            let (line, col) = src.line_col(s.span.start);
+            let _ = write!(out, "{}:{line}:{col}: help: {}", src.path, s.message);
+            if s.replacement.is_empty() {
+                out.push('\n');
+            } else {
+                let _ = writeln!(out, ": `{}`", s.replacement);
+            }
+        }
+        out
+    }
+
+    fn render_json(&self, src: &SourceFile) -> String {
+        let span = self
+            .span
+            .map_or("null".to_owned(), |span| src.span_to_json(span));
+        let labels: Vec<String> = self
+            .labels
+            .iter()
+            .map(|(span, message)| {
+                format!(
+                    "{{\"span\":{},\"message\":{}}}",
+                    src.span_to_json(*span),
+                    json_string(message)
+                )
+            })
+            .collect();
+        let suggestion = self.suggestion.as_ref().map_or("null".to_owned(), |s| {
+            format!(
+                "{{\"span\":{},\"replacement\":{},\"message\":{}}}",
+                src.span_to_json(s.span),
+                json_string(&s.replacement),
+                json_string(&s.message)
+            )
+        });
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},\"span\":{span},\"labels\":[{}],\"suggestion\":{suggestion}}}\n",
+            self.severity.name(),
+            json_string(self.code),
+            json_string(&self.message),
+            json_string(&src.path),
+            labels.join(","),
+        )
+    }
+}
+
+/* Optimal string alignment distance, i.e. Levenshtein distance that also counts swapping two
+ * adjacent characters as a single edit */
+fn edit_distance(a: &[u8], b: &[u8]) -> usize {
+    let mut d = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in d.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    d[0] = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            d[i][j] = (d[i - 1][j] + 1)
+                .min(d[i][j - 1] + 1)
+                .min(d[i - 1][j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                d[i][j] = d[i][j].min(d[i - 2][j - 2] + 1);
+            }
+        }
+    }
+    d[a.len()][b.len()]
+}
+
+/* Returns the candidate closest to name, if it is close enough to be a likely misspelling */
+pub fn closest_name(
+    name: Symbol,
+    candidates: impl IntoIterator<Item = Symbol>,
+    names: &Interner,
+) -> Option<Symbol> {
+    let name_str = names.name(name);
+    let max_distance = (name_str.len() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|c| *c != name)
+        .map(|c| {
+            (
+                edit_distance(name_str.as_bytes(), names.name(c).as_bytes()),
+                c,
+            )
+        })
+        .filter(|(d, _)| *d <= max_distance)
+        .min_by_key(|(d, _)| *d)
+        .map(|(_, c)| c)
+}
+
+/* Quotes and escapes a string for use in JSON */
+fn json_string(s: &str) -> String {
+    let mut out = String::with_capacity(s.len() + 2);
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(out, "\\u{:04x}", c as u32);
+            }
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/* Prints diagnostics in the given format */
+pub fn emit(diagnostics: &[Diagnostic], format: ErrorFormat, src: &SourceFile) {
+    for d in diagnostics.iter() {
+        match format {
+            ErrorFormat::Human => print!("{}", d.render_human(src)),
+            ErrorFormat::Json => print!("{}", d.render_json(src)),
+        }
+    }
+}
+
+/* Collects the errors of all phases so that they can be reported together */
+#[derive(Debug, Default)]
+pub struct Diagnostics {
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn new() -> Diagnostics {
+        Diagnostics::default()
+    }
+
+    fn push(
+        &mut self,
+        severity: Severity,
+        code: &'static str,
+        span: Option<Span>,
+        message: &str,
+    ) -> &mut Diagnostic {
+        self.diagnostics.push(Diagnostic {
+            severity,
+            code,
+            span,
+            message: message.to_owned(),
+            labels: Vec::new(),
+            suggestion: None,
+        });
+        self.diagnostics.last_mut().unwrap()
+    }
+
+    /* code is one of the constants in error_codes */
+    pub fn error(&mut self, code: &'static str, span: Span, message: &str) -> &mut Diagnostic {
+        self.push(Severity::Error, code, Some(span), message)
+    }
+
+    pub fn warning(&mut self, code: &'static str, span: Span, message: &str) -> &mut Diagnostic {
+        self.push(Severity::Warning, code, Some(span), message)
+    }
+
+    /* Records an error that belongs to the file as a whole */
+    pub fn error_file(&mut self, code: &'static str, message: &str) -> &mut Diagnostic {
+        self.push(Severity::Error, code, None, message)
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.diagnostics
+            .iter()
+            .any(|d| d.severity == Severity::Error)
+    }
+
+    /* Returns every collected diagnostic sorted by location. Diagnostics without a location
+     * come last. */
+    pub fn into_sorted(mut self) -> Vec<Diagnostic> {
+        self.diagnostics
+            .sort_by_key(|d| d.span.map_or((1, 0, 0), |s| (0, s.start, s.end)));
+        self.diagnostics
+    }
+}
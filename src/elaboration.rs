@@ -1,201 +1,326 @@
-use std::{iter, process::exit};
+use std::{
+    fmt,
+    iter::{self, Peekable},
+};
 
-use ast::{Exp, Type};
+use ast::{Exp, Type, Var};
+
+use crate::{
+    arena::{Arena, Id},
+    ast::{self, Asnop, Binop, Block, Call, ExpId, Lvalue, Simp, Statement, StmtId},
+    diagnostics::{Diagnostics, Span},
+    error_codes,
+    symbols::{self, Interner, Symbol},
+};
+
+/* The nodes of a function live in the arena of its AbsFunction, the expressions stay in the arena
+ * of the program */
+pub type AbsId = Id<Abs>;
 
-use crate::ast::{self, Asnop, Binop, Call, Lvalue, Simp, Statement};
 #[derive(Debug, Clone)]
-pub enum Abs<'a> {
-    ASGN(&'a [u8], Exp<'a>),
-    WHILE(Exp<'a>, Box<Abs<'a>>),
-    CONT,
-    RET(Exp<'a>),
-    DECL(&'a [u8], Type, Box<Abs<'a>>),
-    IF(Exp<'a>, Box<Abs<'a>>, Box<Abs<'a>>),
-    FOR(Box<Abs<'a>>),
-    BRK,
-    SEQ(Vec<Abs<'a>>),
-    EXP(Exp<'a>),
-    CALL(&'a [u8], Vec<Exp<'a>>),
+pub enum Abs {
+    ASGN(Var, ExpId, Span),
+    WHILE(ExpId, AbsId, Span),
+    CONT(Span),
+    RET(ExpId, Span),
+    DECL(Var, Type, AbsId, Span),
+    IF(ExpId, AbsId, AbsId, Span),
+    FOR(AbsId, Span),
+    BRK(Span),
+    SEQ(Vec<AbsId>),
+    EXP(ExpId),
+    CALL(Symbol, Vec<ExpId>, Span),
+}
+
+impl Abs {
+    /* The span of the source statement, sequences have none */
+    pub fn span(&self, exps: &Arena<Exp>) -> Option<Span> {
+        match self {
+            Abs::ASGN(_, _, span)
+            | Abs::WHILE(_, _, span)
+            | Abs::CONT(span)
+            | Abs::RET(_, span)
+            | Abs::DECL(_, _, _, span)
+            | Abs::IF(_, _, _, span)
+            | Abs::FOR(_, span)
+            | Abs::BRK(span)
+            | Abs::CALL(_, _, span) => Some(*span),
+            Abs::EXP(exp) => Some(exps.span(*exp)),
+            Abs::SEQ(_) => None,
+        }
+    }
 }
-fn translate_simpopt<'a>(simpopt: Option<Simp<'a>>) -> Abs<'a> {
-    match simpopt {
-        None => Abs::SEQ(vec![]),
-        Some(simp) => match simp {
-            ast::Simp::Simp((l, a, e)) => Abs::ASGN(l.get_ident_lvalue(), map_asnop(l, a, e)),
-            ast::Simp::Decl(decl) => match decl {
-                ast::Decl::Declare(typ, name) => Abs::DECL(name, typ, Box::new(Abs::SEQ(vec![]))),
-                ast::Decl::Assign((typ, name, exp)) => {
-                    Abs::DECL(name, typ, Box::new(Abs::SEQ(vec![Abs::ASGN(name, exp)])))
+
+/* Prints one statement per line, nested statements are indented below the statement that
+ * contains them. Sequences do not print anything of their own. */
+pub(crate) fn fmt_abs(
+    nodes: &Arena<Abs>,
+    exps: &Arena<Exp>,
+    names: &Interner,
+    id: AbsId,
+    f: &mut fmt::Formatter<'_>,
+    level: usize,
+) -> fmt::Result {
+    let indent = "    ".repeat(level);
+    match &nodes[id] {
+        Abs::ASGN(name, exp, _) => {
+            writeln!(
+                f,
+                "{indent}{} = {}",
+                names.name(name.name),
+                exps.show(*exp, names)
+            )
+        }
+        Abs::WHILE(exp, body, _) => {
+            writeln!(f, "{indent}while {}", exps.show(*exp, names))?;
+            fmt_abs(nodes, exps, names, *body, f, level + 1)
+        }
+        Abs::CONT(_) => writeln!(f, "{indent}continue"),
+        Abs::RET(exp, _) => writeln!(f, "{indent}return {}", exps.show(*exp, names)),
+        Abs::DECL(name, typ, body, _) => {
+            writeln!(f, "{indent}decl {}: {typ}", names.name(name.name))?;
+            fmt_abs(nodes, exps, names, *body, f, level + 1)
+        }
+        Abs::IF(exp, then, otherwise, _) => {
+            writeln!(f, "{indent}if {}", exps.show(*exp, names))?;
+            fmt_abs(nodes, exps, names, *then, f, level + 1)?;
+            if !matches!(&nodes[*otherwise], Abs::SEQ(items) if items.is_empty()) {
+                writeln!(f, "{indent}else")?;
+                fmt_abs(nodes, exps, names, *otherwise, f, level + 1)?;
+            }
+            Ok(())
+        }
+        Abs::FOR(body, _) => {
+            writeln!(f, "{indent}for")?;
+            fmt_abs(nodes, exps, names, *body, f, level + 1)
+        }
+        Abs::BRK(_) => writeln!(f, "{indent}break"),
+        Abs::SEQ(items) => items
+            .iter()
+            .try_for_each(|abs| fmt_abs(nodes, exps, names, *abs, f, level)),
+        Abs::EXP(exp) => writeln!(f, "{indent}cond {}", exps.show(*exp, names)),
+        Abs::CALL(name, args, _) => {
+            write!(f, "{indent}call {}(", names.name(*name))?;
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
                 }
-            },
-            ast::Simp::Call(call) => match call {
-                Call::Print(arg_list) => Abs::CALL(b"print", arg_list.into_args()),
-                Call::Read(_) => Abs::CALL(b"read", vec![]),
-                Call::Flush(_) => Abs::CALL(b"flush", vec![]),
-                Call::Func(name, arg_list) => Abs::CALL(name, arg_list.into_args()),
-            },
-        },
+                write!(f, "{}", exps.show(*arg, names))?;
+            }
+            writeln!(f, ")")
+        }
     }
 }
 
-pub fn translate_statement<'a>(
-    stmts: &mut std::iter::Peekable<impl Iterator<Item = Statement<'a>>>,
-) -> Abs<'a> {
-    match stmts.next() {
-        None => Abs::SEQ(vec![]),
-        Some(s) => match s {
-            Statement::Simp(simp) => match simp {
-                ast::Simp::Simp((l, a, e)) => Abs::ASGN(l.get_ident_lvalue(), map_asnop(l, a, e)),
+/* Translates the body of a function into the nodes of its abstract syntax. The statements are
+ * only read, the expressions of compound assignments are added to exps. Returns the nodes and the
+ * sequence of the body. */
+pub fn elaborate(
+    diags: &mut Diagnostics,
+    statements: &Arena<Statement>,
+    exps: &mut Arena<Exp>,
+    body: &Block,
+) -> (Arena<Abs>, AbsId) {
+    let mut elaborator = Elaborator {
+        diags,
+        statements,
+        exps,
+        nodes: Arena::new(),
+    };
+    let mut stmts = body.statements().iter().copied().peekable();
+    let body = elaborator.sequence(&mut stmts);
+    let body = elaborator.nodes.alloc(body);
+    (elaborator.nodes, body)
+}
+
+struct Elaborator<'e> {
+    diags: &'e mut Diagnostics,
+    statements: &'e Arena<Statement>,
+    exps: &'e mut Arena<Exp>,
+    nodes: Arena<Abs>,
+}
+
+impl Elaborator<'_> {
+    fn empty(&mut self) -> AbsId {
+        self.nodes.alloc(Abs::SEQ(vec![]))
+    }
+
+    /* Translates the remaining statements of a block */
+    fn sequence(&mut self, stmts: &mut Peekable<impl Iterator<Item = StmtId>>) -> Abs {
+        let mut items = Vec::new();
+        while stmts.peek().is_some() {
+            let abs = self.statement(stmts);
+            items.push(self.nodes.alloc(abs));
+        }
+        Abs::SEQ(items)
+    }
+
+    /* Translates a statement that is the body of a control statement */
+    fn nested(&mut self, id: StmtId) -> AbsId {
+        let abs = self.statement(&mut iter::once(id).peekable());
+        self.nodes.alloc(abs)
+    }
+
+    fn call(&mut self, call: &Call, span: Span) -> Abs {
+        match call {
+            Call::Print(arg_list) => Abs::CALL(symbols::PRINT, arg_list.get_args().to_vec(), span),
+            Call::Read(_) => Abs::CALL(symbols::READ, vec![], span),
+            Call::Flush(_) => Abs::CALL(symbols::FLUSH, vec![], span),
+            Call::Func(name, arg_list) => Abs::CALL(*name, arg_list.get_args().to_vec(), span),
+        }
+    }
+
+    /* Translates the optional simple statements of a for loop header. Calls have no span of
+     * their own and use the span of the loop. */
+    fn simpopt(&mut self, simpopt: Option<&Simp>, span: Span) -> Abs {
+        match simpopt {
+            None => Abs::SEQ(vec![]),
+            Some(simp) => match simp {
+                ast::Simp::Simp((l, a, e)) => {
+                    let span = l.span().to(self.exps.span(*e));
+                    Abs::ASGN(l.get_ident_lvalue(), self.map_asnop(l, *a, *e), span)
+                }
                 ast::Simp::Decl(decl) => match decl {
-                    ast::Decl::Declare(typ, name) => {
-                        let mut vec = Vec::new();
-                        while stmts.peek().is_some() {
-                            vec.push(translate_statement(stmts));
-                        }
-                        Abs::DECL(name, typ, Box::new(Abs::SEQ(vec)))
+                    ast::Decl::Declare(typ, name, span) => {
+                        Abs::DECL(*name, *typ, self.empty(), *span)
                     }
-                    ast::Decl::Assign((typ, name, exp)) => {
-                        let mut vec = vec![Abs::ASGN(name, exp)];
-                        while stmts.peek().is_some() {
-                            vec.push(translate_statement(stmts));
-                        }
-                        Abs::DECL(name, typ, Box::new(Abs::SEQ(vec)))
+                    ast::Decl::Assign((typ, name, exp), span) => {
+                        let asgn_span = span.to(self.exps.span(*exp));
+                        let asgn = self.nodes.alloc(Abs::ASGN(*name, *exp, asgn_span));
+                        let scope = self.nodes.alloc(Abs::SEQ(vec![asgn]));
+                        Abs::DECL(*name, *typ, scope, *span)
                     }
                 },
-                ast::Simp::Call(call) => match call {
-                    Call::Print(arg_list) => Abs::CALL(b"print", arg_list.into_args()),
-                    Call::Read(_) => Abs::CALL(b"read", vec![]),
-                    Call::Flush(_) => Abs::CALL(b"flush", vec![]),
-                    Call::Func(name, arg_list) => Abs::CALL(name, arg_list.into_args()),
-                },
+                ast::Simp::Call(call) => self.call(call, span),
             },
-            Statement::Control(control) => match *control {
-                ast::Control::If(exp, statement, statement2) => match statement2 {
-                    Some(s) => Abs::IF(
-                        exp,
-                        Box::new(translate_statement(&mut iter::once(statement).peekable())),
-                        Box::new(translate_statement(&mut iter::once(s).peekable())),
-                    ),
-                    None => Abs::IF(
-                        exp,
-                        Box::new(translate_statement(&mut iter::once(statement).peekable())),
-                        Box::new(Abs::SEQ(vec![])),
-                    ),
+        }
+    }
+
+    /* Translates the next statement. A declaration takes the rest of the block as its scope. */
+    fn statement(&mut self, stmts: &mut Peekable<impl Iterator<Item = StmtId>>) -> Abs {
+        let Some(id) = stmts.next() else {
+            return Abs::SEQ(vec![]);
+        };
+        let statements = self.statements;
+        match &statements[id] {
+            Statement::Simp(simp, span) => match simp {
+                ast::Simp::Simp((l, a, e)) => {
+                    Abs::ASGN(l.get_ident_lvalue(), self.map_asnop(l, *a, *e), *span)
+                }
+                ast::Simp::Decl(decl) => match decl {
+                    ast::Decl::Declare(typ, name, name_span) => {
+                        let scope = self.sequence(stmts);
+                        Abs::DECL(*name, *typ, self.nodes.alloc(scope), *name_span)
+                    }
+                    ast::Decl::Assign((typ, name, exp), name_span) => {
+                        let asgn = self.nodes.alloc(Abs::ASGN(*name, *exp, *span));
+                        let Abs::SEQ(rest) = self.sequence(stmts) else {
+                            unreachable!()
+                        };
+                        let scope = iter::once(asgn).chain(rest).collect();
+                        Abs::DECL(*name, *typ, self.nodes.alloc(Abs::SEQ(scope)), *name_span)
+                    }
                 },
-                ast::Control::While(exp, statement) => Abs::WHILE(
-                    exp,
-                    Box::new(translate_statement(&mut iter::once(statement).peekable())),
-                ),
-                ast::Control::For((simp1, exp, simp2), statement) => {
-                    let step = translate_simpopt(simp2);
-                    if matches!(step, Abs::DECL(..)) {
-                        println!(
-                            "Error: The step statememt in a for loop cannot be a declaration."
-                        );
-                        exit(7);
+                ast::Simp::Call(call) => self.call(call, *span),
+            },
+            Statement::Control(control, span) => {
+                let span = *span;
+                match control {
+                    ast::Control::If(exp, statement, statement2) => {
+                        let then = self.nested(*statement);
+                        let otherwise = match statement2 {
+                            Some(s) => self.nested(*s),
+                            None => self.empty(),
+                        };
+                        Abs::IF(*exp, then, otherwise, span)
                     }
-                    let exp_asb = Abs::EXP(exp);
-                    let mut for_loop = Abs::SEQ(vec![]);
-                    let initializer = translate_simpopt(simp1);
-                    match initializer {
-                        Abs::DECL(items, typ, scope) => {
-                            if let Abs::SEQ(vec) = *scope {
-                                let mut new_vec = vec.clone();
-                                new_vec.push(exp_asb);
-                                let body =
-                                    translate_statement(&mut iter::once(statement).peekable());
-                                if let Abs::SEQ(mut statements) = body {
-                                    new_vec.append(&mut statements);
-                                } else {
-                                    new_vec.push(body);
-                                }
-                                new_vec.push(step);
-                                for_loop = Abs::DECL(items, typ, Box::new(Abs::SEQ(new_vec)));
-                            }
-                        }
-                        _ => {
-                            let mut new_vec = vec![initializer, exp_asb];
-                            let body = translate_statement(&mut iter::once(statement).peekable());
-                            if let Abs::SEQ(mut statements) = body {
-                                new_vec.append(&mut statements);
-                            } else {
-                                new_vec.push(body);
-                            }
-
-                            new_vec.push(step);
-                            for_loop = Abs::SEQ(new_vec);
-                        }
-                    };
-                    Abs::FOR(Box::new(for_loop))
+                    ast::Control::While(exp, statement) => {
+                        Abs::WHILE(*exp, self.nested(*statement), span)
+                    }
+                    ast::Control::For((simp1, exp, simp2), statement) => {
+                        self.for_loop(simp1.as_ref(), *exp, simp2.as_ref(), *statement, span)
+                    }
+                    ast::Control::Continue => Abs::CONT(span),
+                    ast::Control::Break => Abs::BRK(span),
+                    ast::Control::Return(exp) => Abs::RET(*exp, span),
                 }
-                ast::Control::Continue => Abs::CONT,
-                ast::Control::Break => Abs::BRK,
-                ast::Control::Return(exp) => Abs::RET(exp),
-            },
-            Statement::Block(block) => {
-                let mut statements = block.into_statements().into_iter().peekable();
-                let mut instructions = Vec::new();
-                while statements.peek().is_some() {
-                    instructions.push(translate_statement(&mut statements));
+            }
+            Statement::Error(_) => Abs::SEQ(vec![]),
+            Statement::Block(block, _) => {
+                self.sequence(&mut block.statements().iter().copied().peekable())
+            }
+        }
+    }
+
+    /* A for loop becomes its initializer, the condition as an EXP, the statements of the body and
+     * the step, in this order. A declaration as initializer wraps all of them. */
+    fn for_loop(
+        &mut self,
+        init: Option<&Simp>,
+        cond: ExpId,
+        step: Option<&Simp>,
+        body: StmtId,
+        span: Span,
+    ) -> Abs {
+        let step = self.simpopt(step, span);
+        if let Abs::DECL(_, _, _, step_span) = step {
+            self.diags.error(
+                error_codes::DECLARATION_AS_FOR_STEP,
+                step_span,
+                "The step statememt in a for loop cannot be a declaration.",
+            );
+        }
+        let step = self.nodes.alloc(step);
+        let cond = self.nodes.alloc(Abs::EXP(cond));
+        let initializer = self.simpopt(init, span);
+        let mut items = Vec::new();
+        let decl = match initializer {
+            Abs::DECL(var, typ, scope, decl_span) => {
+                /* The scope of the initializer only holds its assignment, if it has one */
+                if let Abs::SEQ(asgn) = &self.nodes[scope] {
+                    items.extend(asgn.iter().copied());
                 }
-                Abs::SEQ(instructions)
+                Some((var, typ, scope, decl_span))
+            }
+            initializer => {
+                items.push(self.nodes.alloc(initializer));
+                None
             }
-        },
+        };
+        items.push(cond);
+        match self.statement(&mut iter::once(body).peekable()) {
+            Abs::SEQ(statements) => items.extend(statements),
+            body => items.push(self.nodes.alloc(body)),
+        }
+        items.push(step);
+        let for_loop = match decl {
+            Some((var, typ, scope, decl_span)) => {
+                self.nodes[scope] = Abs::SEQ(items);
+                Abs::DECL(var, typ, scope, decl_span)
+            }
+            None => Abs::SEQ(items),
+        };
+        Abs::FOR(self.nodes.alloc(for_loop), span)
     }
-}
 
-fn map_asnop<'a>(lvalue: Lvalue<'a>, asnop: Asnop, exp: Exp<'a>) -> Exp<'a> {
-    match asnop {
-        Asnop::APlus => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::Plus,
-            exp,
-        ))),
-        Asnop::AMinus => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::Minus,
-            exp,
-        ))),
-        Asnop::ADiv => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::Div,
-            exp,
-        ))),
-        Asnop::AMult => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::Mult,
-            exp,
-        ))),
-        Asnop::AMod => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::Mod,
-            exp,
-        ))),
-        Asnop::Assign => exp,
-        Asnop::ABitOr => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::BitOr,
-            exp,
-        ))),
-        Asnop::ABitAnd => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::BitAnd,
-            exp,
-        ))),
-        Asnop::ABitXor => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::BitXor,
-            exp,
-        ))),
-        Asnop::ALShift => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::LShift,
-            exp,
-        ))),
-        Asnop::ARShift => Exp::Arithmetic(Box::new((
-            Exp::Ident(lvalue.get_ident_lvalue()),
-            Binop::RShift,
-            exp,
-        ))),
+    fn map_asnop(&mut self, lvalue: &Lvalue, asnop: Asnop, exp: ExpId) -> ExpId {
+        let binop = match asnop {
+            Asnop::Assign => return exp,
+            Asnop::APlus => Binop::Plus,
+            Asnop::AMinus => Binop::Minus,
+            Asnop::ADiv => Binop::Div,
+            Asnop::AMult => Binop::Mult,
+            Asnop::AMod => Binop::Mod,
+            Asnop::ABitOr => Binop::BitOr,
+            Asnop::ABitAnd => Binop::BitAnd,
+            Asnop::ABitXor => Binop::BitXor,
+            Asnop::ALShift => Binop::LShift,
+            Asnop::ARShift => Binop::RShift,
+        };
+        let span = lvalue.span().to(self.exps.span(exp));
+        let ident = self
+            .exps
+            .alloc(Exp::Ident(lvalue.get_ident_lvalue(), lvalue.span()));
+        self.exps.alloc(Exp::Arithmetic(ident, binop, exp, span))
     }
 }
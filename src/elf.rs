@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    io::{self, Write},
+};
+
+use crate::x86::{Code, RelocationKind};
+
+/* The sections of the object in the order of their headers. .note.GNU-stack tells the linker
+ * that the code does not need an executable stack. */
+const SECTIONS: [&str; 7] = [
+    "",
+    ".text",
+    ".rela.text",
+    ".symtab",
+    ".strtab",
+    ".shstrtab",
+    ".note.GNU-stack",
+];
+const TEXT: u32 = 1;
+const SYMTAB: u32 = 3;
+const STRTAB: u32 = 4;
+const SHSTRTAB: u16 = 5;
+
+const HEADER_SIZE: usize = 64;
+const SECTION_HEADER_SIZE: u16 = 64;
+const SYMBOL_SIZE: u64 = 24;
+const RELA_SIZE: u64 = 24;
+
+const SHT_PROGBITS: u32 = 1;
+const SHT_SYMTAB: u32 = 2;
+const SHT_STRTAB: u32 = 3;
+const SHT_RELA: u32 = 4;
+const SHF_ALLOC: u64 = 0x2;
+const SHF_EXECINSTR: u64 = 0x4;
+const SHF_INFO_LINK: u64 = 0x40;
+
+const STB_GLOBAL: u8 = 1;
+const STT_NOTYPE: u8 = 0;
+const STT_FUNC: u8 = 2;
+
+const R_X86_64_PC32: u64 = 2;
+const R_X86_64_PLT32: u64 = 4;
+
+/* A string table, which starts with the empty string */
+struct Strings {
+    bytes: Vec<u8>,
+}
+
+impl Strings {
+    fn new() -> Self {
+        Strings { bytes: vec![0] }
+    }
+
+    fn add(&mut self, s: &str) -> u32 {
+        let offset = self.bytes.len() as u32;
+        self.bytes.extend(s.as_bytes());
+        self.bytes.push(0);
+        offset
+    }
+}
+
+struct SectionHeader {
+    name: u32,
+    typ: u32,
+    flags: u64,
+    offset: u64,
+    size: u64,
+    link: u32,
+    info: u32,
+    align: u64,
+    entry_size: u64,
+}
+
+/* Appends zeros until the length is a multiple of align */
+fn pad(bytes: &mut Vec<u8>, align: usize) {
+    bytes.resize(bytes.len().next_multiple_of(align), 0);
+}
+
+/* Appends the contents of a section, returns its offset and size */
+fn place(body: &mut Vec<u8>, contents: &[u8], align: usize) -> (u64, u64) {
+    pad(body, align);
+    let offset = body.len() as u64;
+    body.extend(contents);
+    (offset, contents.len() as u64)
+}
+
+/* Writes the code as an ELF64 relocatable object for x86-64. Every symbol the code defines is a
+ * global function, every other symbol it refers to is left undefined for the linker. */
+pub fn write_object(code: &Code, out: &mut dyn Write) -> io::Result<()> {
+    let mut names = Strings::new();
+    /* Only the null symbol is local, the defined symbols come first */
+    let mut symbols: Vec<&str> = code.symbols.iter().map(|(name, _)| name.as_str()).collect();
+    let defined = symbols.len();
+    /* The index of every symbol in the table, after the null symbol */
+    let mut indices: HashMap<&str, usize> = symbols
+        .iter()
+        .enumerate()
+        .map(|(i, name)| (*name, i + 1))
+        .collect();
+    for relocation in code.relocations.iter() {
+        if !indices.contains_key(relocation.symbol.as_str()) {
+            symbols.push(&relocation.symbol);
+            indices.insert(&relocation.symbol, symbols.len());
+        }
+    }
+    let mut symtab = vec![0; SYMBOL_SIZE as usize];
+    for (i, name) in symbols.iter().enumerate() {
+        let (typ, section, value) = match i < defined {
+            true => (STT_FUNC, TEXT as u16, code.symbols[i].1 as u64),
+            false => (STT_NOTYPE, 0, 0),
+        };
+        symtab.extend(names.add(name).to_le_bytes());
+        symtab.push(STB_GLOBAL << 4 | typ);
+        symtab.push(0);
+        symtab.extend(section.to_le_bytes());
+        symtab.extend(value.to_le_bytes());
+        symtab.extend(0u64.to_le_bytes());
+    }
+    let mut rela = Vec::new();
+    for relocation in code.relocations.iter() {
+        let symbol = indices[relocation.symbol.as_str()];
+        let typ = match relocation.kind {
+            RelocationKind::Pc32 => R_X86_64_PC32,
+            RelocationKind::Plt32 => R_X86_64_PLT32,
+        };
+        rela.extend((relocation.offset as u64).to_le_bytes());
+        rela.extend(((symbol as u64) << 32 | typ).to_le_bytes());
+        /* The field is relative to its end, 4 bytes after the start */
+        rela.extend((-4i64).to_le_bytes());
+    }
+    let mut section_names = Strings::new();
+    let name_offsets: Vec<u32> = SECTIONS
+        .iter()
+        .map(|name| match *name {
+            "" => 0,
+            name => section_names.add(name),
+        })
+        .collect();
+
+    /* The contents of the sections follow the ELF header, the section headers come last */
+    let mut body = vec![0; HEADER_SIZE];
+    let text = place(&mut body, &code.bytes, 16);
+    let rela = place(&mut body, &rela, 8);
+    let symtab = place(&mut body, &symtab, 8);
+    let strtab = place(&mut body, &names.bytes, 1);
+    let shstrtab = place(&mut body, &section_names.bytes, 1);
+    let note = place(&mut body, &[], 1);
+    let section = |i: usize, typ, flags, (offset, size), align| SectionHeader {
+        name: name_offsets[i],
+        typ,
+        flags,
+        offset,
+        size,
+        link: 0,
+        info: 0,
+        align,
+        entry_size: 0,
+    };
+    let headers = [
+        section(0, 0, 0, (0, 0), 0),
+        section(1, SHT_PROGBITS, SHF_ALLOC | SHF_EXECINSTR, text, 16),
+        SectionHeader {
+            link: SYMTAB,
+            info: TEXT,
+            entry_size: RELA_SIZE,
+            ..section(2, SHT_RELA, SHF_INFO_LINK, rela, 8)
+        },
+        SectionHeader {
+            link: STRTAB,
+            /* The index of the first global symbol */
+            info: 1,
+            entry_size: SYMBOL_SIZE,
+            ..section(3, SHT_SYMTAB, 0, symtab, 8)
+        },
+        section(4, SHT_STRTAB, 0, strtab, 1),
+        section(5, SHT_STRTAB, 0, shstrtab, 1),
+        section(6, SHT_PROGBITS, 0, note, 1),
+    ];
+    pad(&mut body, 8);
+    let section_headers = body.len() as u64;
+    for header in headers.iter() {
+        body.extend(header.name.to_le_bytes());
+        body.extend(header.typ.to_le_bytes());
+        body.extend(header.flags.to_le_bytes());
+        /* The address, objects are not loaded */
+        body.extend(0u64.to_le_bytes());
+        body.extend(header.offset.to_le_bytes());
+        body.extend(header.size.to_le_bytes());
+        body.extend(header.link.to_le_bytes());
+        body.extend(header.info.to_le_bytes());
+        body.extend(header.align.to_le_bytes());
+        body.extend(header.entry_size.to_le_bytes());
+    }
+
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    /* 64 bit, little endian, version 1, System V ABI */
+    header.extend(b"\x7fELF\x02\x01\x01\x00");
+    header.extend([0; 8]);
+    /* A relocatable file for x86-64 */
+    header.extend(1u16.to_le_bytes());
+    header.extend(62u16.to_le_bytes());
+    header.extend(1u32.to_le_bytes());
+    /* No entry point and no program headers */
+    header.extend(0u64.to_le_bytes());
+    header.extend(0u64.to_le_bytes());
+    header.extend(section_headers.to_le_bytes());
+    header.extend(0u32.to_le_bytes());
+    header.extend((HEADER_SIZE as u16).to_le_bytes());
+    header.extend(0u16.to_le_bytes());
+    header.extend(0u16.to_le_bytes());
+    header.extend(SECTION_HEADER_SIZE.to_le_bytes());
+    header.extend((headers.len() as u16).to_le_bytes());
+    header.extend(SHSTRTAB.to_le_bytes());
+    body[..HEADER_SIZE].copy_from_slice(&header);
+    out.write_all(&body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::x86::{Inst, Reg};
+
+    fn u16_at(bytes: &[u8], offset: usize) -> u16 {
+        u16::from_le_bytes(bytes[offset..offset + 2].try_into().unwrap())
+    }
+
+    fn u32_at(bytes: &[u8], offset: usize) -> u32 {
+        u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+    }
+
+    fn u64_at(bytes: &[u8], offset: usize) -> u64 {
+        u64::from_le_bytes(bytes[offset..offset + 8].try_into().unwrap())
+    }
+
+    fn string_at(bytes: &[u8], offset: usize) -> &str {
+        let end = offset + bytes[offset..].iter().position(|b| *b == 0).unwrap();
+        std::str::from_utf8(&bytes[offset..end]).unwrap()
+    }
+
+    /* The contents of a section by name, and the header fields link and info */
+    fn section<'o>(object: &'o [u8], name: &str) -> (&'o [u8], u32, u32) {
+        let headers = u64_at(object, 0x28) as usize;
+        let count = u16_at(object, 0x3C) as usize;
+        let header = |i: usize| headers + i * SECTION_HEADER_SIZE as usize;
+        let names = u64_at(object, header(SHSTRTAB as usize) + 0x18) as usize;
+        let i = (0..count)
+            .find(|i| string_at(object, names + u32_at(object, header(*i)) as usize) == name)
+            .unwrap();
+        let offset = u64_at(object, header(i) + 0x18) as usize;
+        let size = u64_at(object, header(i) + 0x20) as usize;
+        let link = u32_at(object, header(i) + 0x28);
+        let info = u32_at(object, header(i) + 0x2C);
+        (&object[offset..offset + size], link, info)
+    }
+
+    #[test]
+    fn writes_symbols_and_relocations() {
+        let mut code = Code::new();
+        code.symbol("main");
+        code.encode(&Inst::Call("_f".to_owned()));
+        code.encode(&Inst::Ret);
+        code.symbol("_f");
+        code.encode(&Inst::LoadGlobal(Reg::Rdi, "stdout".to_owned()));
+        code.encode(&Inst::Call("fflush".to_owned()));
+        code.encode(&Inst::Ret);
+        code.finish();
+        let mut object = Vec::new();
+        write_object(&code, &mut object).unwrap();
+
+        assert_eq!(&object[..4], b"\x7fELF");
+        let (text, _, _) = section(&object, ".text");
+        assert_eq!(text, &code.bytes[..]);
+
+        let (symtab, strtab, first_global) = section(&object, ".symtab");
+        assert_eq!(strtab, STRTAB);
+        assert_eq!(first_global, 1);
+        let (names, _, _) = section(&object, ".strtab");
+        /* name, binding and type, section index and value */
+        let symbols: Vec<(&str, u8, u16, u64)> = symtab
+            .chunks(SYMBOL_SIZE as usize)
+            .skip(1)
+            .map(|s| {
+                (
+                    string_at(names, u32_at(s, 0) as usize),
+                    s[4],
+                    u16_at(s, 6),
+                    u64_at(s, 8),
+                )
+            })
+            .collect();
+        let function = STB_GLOBAL << 4 | STT_FUNC;
+        let undefined = STB_GLOBAL << 4 | STT_NOTYPE;
+        assert_eq!(
+            symbols,
+            [
+                ("main", function, TEXT as u16, 0),
+                ("_f", function, TEXT as u16, 6),
+                ("stdout", undefined, 0, 0),
+                ("fflush", undefined, 0, 0),
+            ]
+        );
+
+        let (rela, symtab_index, text_index) = section(&object, ".rela.text");
+        assert_eq!((symtab_index, text_index), (SYMTAB, TEXT));
+        /* offset, symbol, type and addend */
+        let relocations: Vec<(u64, &str, u64, i64)> = rela
+            .chunks(RELA_SIZE as usize)
+            .map(|r| {
+                let info = u64_at(r, 8);
+                (
+                    u64_at(r, 0),
+                    symbols[(info >> 32) as usize - 1].0,
+                    info & 0xFFFF_FFFF,
+                    u64_at(r, 16) as i64,
+                )
+            })
+            .collect();
+        assert_eq!(
+            relocations,
+            [
+                (1, "_f", R_X86_64_PLT32, -4),
+                (9, "stdout", R_X86_64_PC32, -4),
+                (14, "fflush", R_X86_64_PLT32, -4),
+            ]
+        );
+    }
+}
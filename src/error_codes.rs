@@ -0,0 +1,404 @@
+/* Stable identifiers for every diagnostic. Codes are never renumbered or reused, so that tests
+ * and documentation can refer to an error without depending on the wording of its message.
+ * E00xx are lexical and syntax errors, E01xx function errors, E02xx variable errors, E03xx type
+ * errors, E04xx control flow errors, E05xx errors of the toolchain that assembles and links the
+ * program and W00xx warnings. */
+
+pub const UNKNOWN_CHARACTER: &str = "E0001";
+pub const UNTERMINATED_COMMENT: &str = "E0002";
+pub const EMPTY_HEX_LITERAL: &str = "E0003";
+pub const HEX_LITERAL_TOO_LONG: &str = "E0004";
+pub const DECIMAL_OUT_OF_RANGE: &str = "E0005";
+pub const UNEXPECTED_TOKEN: &str = "E0010";
+pub const UNEXPECTED_EOF: &str = "E0011";
+pub const EXTRA_TOKEN: &str = "E0012";
+pub const INVALID_TOKEN: &str = "E0013";
+
+pub const MISSING_RETURN: &str = "E0101";
+pub const DUPLICATE_FUNCTION: &str = "E0102";
+pub const BUILTIN_REDEFINED: &str = "E0103";
+pub const DUPLICATE_PARAMETER: &str = "E0104";
+pub const MAIN_WITH_PARAMETERS: &str = "E0105";
+pub const MAIN_RETURN_TYPE: &str = "E0106";
+pub const MISSING_MAIN: &str = "E0107";
+pub const CONFLICTING_DECLARATION: &str = "E0108";
+pub const UNDEFINED_FUNCTION: &str = "E0109";
+
+pub const UNDECLARED_VARIABLE: &str = "E0201";
+pub const UNASSIGNED_VARIABLE: &str = "E0202";
+pub const REDECLARED_VARIABLE: &str = "E0203";
+pub const DECLARATION_AS_FOR_STEP: &str = "E0204";
+
+pub const TYPE_MISMATCH: &str = "E0301";
+pub const OPERAND_TYPES_DIFFER: &str = "E0302";
+pub const BRANCH_TYPES_DIFFER: &str = "E0303";
+pub const UNKNOWN_FUNCTION: &str = "E0304";
+pub const WRONG_ARGUMENT_COUNT: &str = "E0305";
+
+pub const JUMP_OUTSIDE_LOOP: &str = "E0401";
+
+pub const TOOLCHAIN_NOT_RUN: &str = "E0501";
+pub const TOOLCHAIN_FAILED: &str = "E0502";
+pub const INTERMEDIATE_FILE: &str = "E0503";
+
+pub const UNUSED_VARIABLE: &str = "W0001";
+pub const UNUSED_PARAMETER: &str = "W0002";
+pub const UNREACHABLE_CODE: &str = "W0003";
+pub const CONSTANT_CONDITION: &str = "W0004";
+pub const UNREACHABLE_FUNCTION: &str = "W0005";
+
+const EXPLANATIONS: [(&str, &str); 36] = [
+    (
+        UNKNOWN_CHARACTER,
+        "The program contains a character that is not part of any token.
+
+Only ASCII letters, digits, whitespace and the operators of the language may
+appear outside of comments.
+
+    int main() {
+        return 1 @ 2; // '@' is not an operator
+    }",
+    ),
+    (
+        UNTERMINATED_COMMENT,
+        "A block comment is opened with /* but never closed with */.
+
+Block comments nest, so every /* inside a comment needs its own */.
+
+    int main() {
+        /* outer /* inner */
+        return 0;
+    }",
+    ),
+    (
+        EMPTY_HEX_LITERAL,
+        "A hexadecimal literal consists of the prefix 0x without any digits.
+
+    int main() {
+        return 0x; // write 0x0 instead
+    }",
+    ),
+    (
+        HEX_LITERAL_TOO_LONG,
+        "A hexadecimal literal does not fit into 32 bits.
+
+Hexadecimal literals may be at most 0xffffffff. Larger values are interpreted
+as negative numbers in two's complement.
+
+    int main() {
+        return 0x100000000;
+    }",
+    ),
+    (
+        DECIMAL_OUT_OF_RANGE,
+        "A decimal literal is larger than 2147483648.
+
+The largest int is 2147483647. The literal 2147483648 is accepted so that the
+smallest int can be written as -2147483648. Without the negation it wraps around
+to -2147483648 as well.
+
+    int main() {
+        return 2147483649;
+    }",
+    ),
+    (
+        UNEXPECTED_TOKEN,
+        "The parser found a token that cannot appear at this position.
+
+The message lists the tokens that would have been accepted. A missing
+semicolon is a common cause.
+
+    int main() {
+        int x = 1 // missing ';'
+        return x;
+    }",
+    ),
+    (
+        UNEXPECTED_EOF,
+        "The file ended in the middle of a function.
+
+Usually a closing brace or semicolon is missing.
+
+    int main() {
+        return 0;",
+    ),
+    (
+        EXTRA_TOKEN,
+        "There are tokens after the last complete function.
+
+    int main() {
+        return 0;
+    }
+    }",
+    ),
+    (
+        INVALID_TOKEN,
+        "The parser received a token it does not know. This indicates a bug in the
+lexer, please report it.",
+    ),
+    (
+        MISSING_RETURN,
+        "A function can reach its end without returning a value.
+
+Every path through a function must end in a return statement. Loops do not
+count, because their body might not be executed.
+
+    int f(int x) {
+        if (x > 0) {
+            return 1;
+        }
+        // missing return for x <= 0
+    }",
+    ),
+    (
+        DUPLICATE_FUNCTION,
+        "Two functions have the same name.
+
+    int f() { return 1; }
+    int f() { return 2; }",
+    ),
+    (
+        BUILTIN_REDEFINED,
+        "A function has the name of a built-in function. print, read and flush cannot
+be redefined.
+
+    int print(int c) { return c; }",
+    ),
+    (
+        DUPLICATE_PARAMETER,
+        "Two parameters of a function have the same name.
+
+    int f(int x, bool x) { return 0; }",
+    ),
+    (
+        MAIN_WITH_PARAMETERS,
+        "The main function must not take any parameters.
+
+    int main(int argc) { return 0; }",
+    ),
+    (
+        MAIN_RETURN_TYPE,
+        "The main function must return an int, which is the exit code of the
+program.
+
+    bool main() { return true; }",
+    ),
+    (
+        MISSING_MAIN,
+        "The program has no main function, so there is no place to start executing.
+
+    int f() { return 0; } // rename to main",
+    ),
+    (
+        CONFLICTING_DECLARATION,
+        "A function is declared with other parameter or return types than its
+definition, or than its first declaration if no unit defines it.
+
+    int f(int x);
+    bool f(int x) { return x > 0; } // the declaration returns int",
+    ),
+    (
+        UNDEFINED_FUNCTION,
+        "A function is declared, but the program does not define it. A declaration
+only stands in for a function that is defined in another unit. This is
+checked when the units make up the whole program; with -c or when object
+files are linked in, the function may be defined elsewhere.
+
+    int helper(int x);
+    int main() {
+        return helper(1); // no unit defines helper
+    }",
+    ),
+    (
+        UNDECLARED_VARIABLE,
+        "A variable is used or assigned, but it is not declared in any enclosing
+scope.
+
+    int main() {
+        x = 1; // write 'int x = 1;'
+        return x;
+    }",
+    ),
+    (
+        UNASSIGNED_VARIABLE,
+        "A variable is read although a value has not been assigned to it on every
+path that reaches the read.
+
+    int main() {
+        int x;
+        if (read() > 0) {
+            x = 1;
+        }
+        return x; // x is unassigned if read() <= 0
+    }",
+    ),
+    (
+        REDECLARED_VARIABLE,
+        "A variable is declared while a variable or parameter with the same name is
+still in scope. Shadowing is not allowed.
+
+    int main() {
+        int x = 1;
+        { int x = 2; }
+        return x;
+    }",
+    ),
+    (
+        DECLARATION_AS_FOR_STEP,
+        "The step of a for loop declares a variable. Only the initializer of a for
+loop may be a declaration.
+
+    int main() {
+        for (int i = 0; i < 3; int j = 0) {}
+        return 0;
+    }",
+    ),
+    (
+        TYPE_MISMATCH,
+        "An expression does not have the type its position requires, e.g. an operand,
+an argument, a condition or a return value.
+
+    int main() {
+        int x = 1 + true; // '+' takes two ints
+        return x;
+    }",
+    ),
+    (
+        OPERAND_TYPES_DIFFER,
+        "The operands of == or != have different types. Both must be int or both
+must be bool.
+
+    int main() {
+        bool b = 1 == true;
+        return 0;
+    }",
+    ),
+    (
+        BRANCH_TYPES_DIFFER,
+        "The branches of a conditional expression have different types.
+
+    int main() {
+        int x = read() > 0 ? 1 : false;
+        return x;
+    }",
+    ),
+    (
+        UNKNOWN_FUNCTION,
+        "A called function is neither defined nor declared in the program and is not
+a built-in function.
+
+    int main() {
+        prnt(65); // did you mean 'print'?
+        return 0;
+    }",
+    ),
+    (
+        WRONG_ARGUMENT_COUNT,
+        "A function is called with a different number of arguments than it has
+parameters.
+
+    int f(int a, int b) { return a + b; }
+    int main() {
+        return f(1);
+    }",
+    ),
+    (
+        JUMP_OUTSIDE_LOOP,
+        "A break or continue statement is not inside a loop.
+
+    int main() {
+        break;
+        return 0;
+    }",
+    ),
+    (
+        TOOLCHAIN_NOT_RUN,
+        "The C compiler that assembles and links the program could not be started,
+usually because it is not installed. compdesign runs gcc unless the CC
+environment variable or --cc names another compiler.
+
+    compdesign --cc clang main.c0",
+    ),
+    (
+        TOOLCHAIN_FAILED,
+        "The C compiler that assembles and links the program reported an error, e.g.
+because a library given with -l does not exist or an object file given as input
+defines a function twice. Its own messages are printed before this error, -v
+shows the command it was run with.
+
+    compdesign main.c0 -lmissing",
+    ),
+    (
+        INTERMEDIATE_FILE,
+        "The assembly of a unit could not be written to a file for the C compiler,
+e.g. because the directory of --save-temps cannot be created.
+
+    compdesign --save-temps /read-only main.c0",
+    ),
+    (
+        UNUSED_VARIABLE,
+        "A declared variable is never read. Assigning to it does not count as a use.
+Enable this warning with -Wunused-variable or -Wall.
+
+    int main() {
+        int x = 1; // never read
+        return 0;
+    }",
+    ),
+    (
+        UNUSED_PARAMETER,
+        "A parameter is never read. Enable this warning with -Wunused-parameter or
+-Wall.
+
+    int f(int x) { return 0; }",
+    ),
+    (
+        UNREACHABLE_CODE,
+        "A statement follows a return, break or continue and is never executed.
+Enable this warning with -Wunreachable-code or -Wall.
+
+    int main() {
+        return 0;
+        print(65);
+    }",
+    ),
+    (
+        CONSTANT_CONDITION,
+        "The condition of a loop does not depend on any variable or call, so the loop
+either never runs or never ends through its condition. A literal true is not
+reported, it marks a loop that ends through break or return. Enable this
+warning with -Wconstant-condition or -Wall.
+
+    int main() {
+        while (1 < 2) { }
+        return 0;
+    }",
+    ),
+    (
+        UNREACHABLE_FUNCTION,
+        "A function is never called, directly or indirectly, from main. Enable this
+warning with -Wunreachable-function or -Wall.
+
+    int unused() { return 0; }
+    int main() { return 0; }",
+    ),
+];
+
+/* Whether a code belongs to a lexical or syntax error, which exit with code 42 instead of 7.
+ * Out of range literals are semantic errors. */
+pub fn is_syntax_error(code: &str) -> bool {
+    code.starts_with("E00") && code != HEX_LITERAL_TOO_LONG && code != DECIMAL_OUT_OF_RANGE
+}
+
+/* Whether a code belongs to an error of the toolchain, which exit with code 1 */
+pub fn is_toolchain_error(code: &str) -> bool {
+    code.starts_with("E05")
+}
+
+/* The longer explanation of an error code, for --explain */
+pub fn explain(code: &str) -> Option<&'static str> {
+    EXPLANATIONS
+        .iter()
+        .find(|(c, _)| c.eq_ignore_ascii_case(code))
+        .map(|(_, text)| *text)
+}
@@ -1,465 +1,338 @@
-use std::fmt::format;
-
 use crate::{
-    coloring::color_func,
+    coloring::is_spilled,
     ir::{IRCmd, IRExp, IRFunction},
+    x86::{AluOp, Cond, Inst, Operand, Reg, ShiftOp, UnaryOp},
 };
 
 pub fn init_stack_counter(num_temps: usize) -> usize {
     (num_temps + 1).saturating_sub(7)
 }
 
-pub fn translate_functions(funcs: &mut Vec<IRFunction<'_>>, assembly: &mut String) {
-    let mut main = funcs.iter_mut().find(|f| f.name == b"main").unwrap();
-    let coloring = color_func(&mut main);
-    let temp_count = main.num_temps;
-    let mut stack_counter = init_stack_counter(main.num_temps);
-    let mut current_temp = "eax".to_owned();
-    for cmd in (main.instructions).iter().cloned() {
+/* Translates a function given the coloring of its temps to instructions, which go after a label
+ * with its name prefixed by an underscore. The commands that find_dead_stores marks are skipped.
+ * Its jump labels are local to the function, so the result does not depend on the other
+ * functions. */
+pub fn translate_function(
+    f: &IRFunction,
+    coloring: &Vec<usize>,
+    dead_stores: &[bool],
+) -> Vec<Inst> {
+    let name = f.name.as_str();
+    let mut assembly = Vec::new();
+    move_params(f.num_params, &mut assembly);
+    let temp_count = f.num_temps;
+    let mut stack_counter = init_stack_counter(f.num_temps);
+    for (cmd, _) in f
+        .instructions
+        .iter()
+        .zip(dead_stores.iter())
+        .filter(|(_, dead)| !**dead)
+    {
         translate_instruction(
+            name,
             temp_count,
             &mut stack_counter,
             cmd,
-            assembly,
-            &coloring,
-            &mut current_temp,
+            &mut assembly,
+            coloring,
         );
     }
-    for f in funcs.iter_mut().filter(|f| f.name != b"main") {
-        assembly.push_str(&format!(
-            "\n_{}:\n",
-            str::from_utf8(f.name).unwrap().to_owned()
-        ));
-        let coloring = color_func(f);
-        let temp_count = f.num_temps;
-        let mut stack_counter = init_stack_counter(f.num_temps);
-        move_params(f.num_params, assembly);
-        let mut current_temp = "eax".to_owned();
-        for cmd in (f.instructions).iter() {
-            translate_instruction(
-                temp_count,
-                &mut stack_counter,
-                cmd.clone(),
-                assembly,
-                &coloring,
-                &mut current_temp,
-            );
-        }
-    }
+    assembly
 }
 
-fn move_params(num_params: usize, assembly: &mut String) {
-    let params_regs = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
-    let local_regs = ["ebx", "edi", "esi", "r8d", "r9d", "r10d"];
+const ARGS_REGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+/* The registers that are saved around calls */
+const SAVED_REGS: [Reg; 7] = [
+    Reg::Rbx,
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+];
+
+fn move_params(num_params: usize, assembly: &mut Vec<Inst>) {
+    let local_regs = [Reg::Rbx, Reg::Rdi, Reg::Rsi, Reg::R8, Reg::R9, Reg::R10];
     let mut i = 0;
-    while i < num_params && i < params_regs.len() {
-        assembly.push_str(&format!("mov {}, {}\n", local_regs[i], params_regs[i]));
+    while i < num_params && i < ARGS_REGS.len() {
+        assembly.push(Inst::Mov(
+            Operand::Reg(local_regs[i]),
+            Operand::Reg(ARGS_REGS[i]),
+        ));
         i += 1;
     }
 }
 
 fn move_args(
-    args: Vec<IRExp>,
-    assembly: &mut String,
+    args: &[IRExp],
+    assembly: &mut Vec<Inst>,
     num_temps: usize,
     stack_counter: &mut usize,
     coloring: &Vec<usize>,
-    current_temp: &mut str,
 ) {
-    let args_regs = ["edi", "esi", "edx", "ecx", "r8d", "r9d"];
     let mut i = 0;
     let mut new_stack_counter = 8;
-    while i < args.len() && i < args_regs.len() {
-        let operand = expr_to_assembly(
-            num_temps,
-            stack_counter,
-            args[i].clone(),
-            assembly,
-            coloring,
-            current_temp,
-        );
-        assembly.push_str(&format!(
-            "mov DWORD PTR [rsp-{}], {operand}\n",
-            new_stack_counter * 4,
-        ));
+    while i < args.len() && i < ARGS_REGS.len() {
+        let operand = expr_to_assembly(num_temps, stack_counter, &args[i], assembly, coloring);
+        assembly.push(Inst::Mov(Operand::Stack(new_stack_counter * 4), operand));
         i += 1;
         new_stack_counter += 1;
     }
     let mut j = 0;
     new_stack_counter = 8;
     while j < i {
-        assembly.push_str(&format!(
-            "mov {}, DWORD PTR [rsp-{}]\n",
-            args_regs[j],
-            new_stack_counter * 4
+        assembly.push(Inst::Mov(
+            Operand::Reg(ARGS_REGS[j]),
+            Operand::Stack(new_stack_counter * 4),
         ));
         j += 1;
         new_stack_counter += 1;
     }
     new_stack_counter = 8;
     while i < args.len() {
-        let operand = expr_to_assembly(
-            num_temps,
-            stack_counter,
-            args[i].clone(),
-            assembly,
-            coloring,
-            current_temp,
-        );
-        assembly.push_str(&format!("mov eax, {operand}\n"));
-        assembly.push_str(&format!(
-            "mov DWORD PTR [rsp-{}], eax\n",
-            new_stack_counter * 4
-        ));
+        let operand = expr_to_assembly(num_temps, stack_counter, &args[i], assembly, coloring);
+        assembly.push(Inst::Mov(EAX, operand));
+        assembly.push(Inst::Mov(Operand::Stack(new_stack_counter * 4), EAX));
         new_stack_counter += 1;
     }
 }
 
-fn save_register_onto_stack(assembly: &mut String) {
-    assembly.push_str("push rbx\n");
-    assembly.push_str("push rsi\n");
-    assembly.push_str("push rdi\n");
-    assembly.push_str("push r8\n");
-    assembly.push_str("push r9\n");
-    assembly.push_str("push r10\n");
-    assembly.push_str("push r11\n");
+fn save_register_onto_stack(assembly: &mut Vec<Inst>) {
+    assembly.extend(SAVED_REGS.map(Inst::Push));
 }
 
-fn get_register_from_stack(assembly: &mut String) {
-    assembly.push_str("pop r11\n");
-    assembly.push_str("pop r10\n");
-    assembly.push_str("pop r9\n");
-    assembly.push_str("pop r8\n");
-    assembly.push_str("pop rdi\n");
-    assembly.push_str("pop rsi\n");
-    assembly.push_str("pop rbx\n");
+fn get_register_from_stack(assembly: &mut Vec<Inst>) {
+    assembly.extend(SAVED_REGS.map(Inst::Pop).into_iter().rev());
 }
 
-fn map_temp_to_register(color: usize, load: bool, assembly: &mut String) -> String {
-    if color < 11 {
-        match color {
-            0 => "ebx".to_owned(),
-            1 => "edi".to_owned(),
-            2 => "esi".to_owned(),
-            3 => "r8d".to_owned(),
-            4 => "r9d".to_owned(),
-            5 => "r10d".to_owned(),
-            6 => "r11d".to_owned(),
-            7 => "r12d".to_owned(),
-            8 => "r13d".to_owned(),
-            9 => "r14d".to_owned(),
-            _ => "r15d".to_owned(),
-        }
+const EAX: Operand = Operand::Reg(Reg::Rax);
+const ECX: Operand = Operand::Reg(Reg::Rcx);
+
+fn map_temp_to_register(color: usize, load: bool, assembly: &mut Vec<Inst>) -> Operand {
+    if !is_spilled(color) {
+        let reg = match color {
+            0 => Reg::Rbx,
+            1 => Reg::Rdi,
+            2 => Reg::Rsi,
+            3 => Reg::R8,
+            4 => Reg::R9,
+            5 => Reg::R10,
+            6 => Reg::R11,
+            7 => Reg::R12,
+            8 => Reg::R13,
+            9 => Reg::R14,
+            _ => Reg::R15,
+        };
+        Operand::Reg(reg)
     } else {
         let stack_i = (color - 10) * 4;
         if load {
-            format!("DWORD PTR [rsp-{}]", stack_i).to_string()
+            Operand::Stack(stack_i)
         } else {
-            assembly.push_str(&format!("mov eax, DWORD PTR [rsp-{stack_i}]\n"));
-            "eax".to_owned()
+            assembly.push(Inst::Mov(EAX, Operand::Stack(stack_i)));
+            EAX
         }
     }
 }
 
+/* Jump labels are named after the function, with the .L prefix that keeps them out of the symbol
+ * table */
 pub fn translate_instruction(
+    function: &str,
     num_temps: usize,
     stack_counter: &mut usize,
-    cmd: IRCmd,
-    assembly: &mut String,
+    cmd: &IRCmd,
+    assembly: &mut Vec<Inst>,
     coloring: &Vec<usize>,
-    current_temp: &mut String,
 ) {
+    let label = |label| format!(".L{function}_{label}");
     match cmd {
         IRCmd::Load(irexp, irexp1) => {
-            let operand = expr_to_assembly(
-                num_temps,
-                stack_counter,
-                irexp1,
-                assembly,
-                coloring,
-                current_temp,
-            );
+            let operand = expr_to_assembly(num_temps, stack_counter, irexp1, assembly, coloring);
             if let IRExp::Temp(i) = irexp {
                 let r = map_temp_to_register(coloring[i.name], true, assembly);
-                assembly.push_str(&format!("mov {r}, {operand}\n"));
-                *current_temp = r;
+                assembly.push(Inst::Mov(r, operand));
             }
         }
-        IRCmd::JumpIf(irexp, label) => {
-            let operand = expr_to_assembly(
-                num_temps,
-                stack_counter,
-                irexp,
-                assembly,
-                coloring,
-                current_temp,
-            );
-            assembly.push_str(&format!("cmp {operand}, 1\n"));
+        IRCmd::JumpIf(irexp, l) => {
+            let operand = expr_to_assembly(num_temps, stack_counter, irexp, assembly, coloring);
+            assembly.push(Inst::Alu(AluOp::Cmp, operand, Operand::Imm(1)));
 
-            assembly.push_str(&format!("je _LABEL_{label}\n"));
+            assembly.push(Inst::Je(label(l)));
         }
-        IRCmd::Jump(label) => assembly.push_str(&format!("jmp _LABEL_{label}\n",)),
-        IRCmd::Label(label) => assembly.push_str(&format!("_LABEL_{label}:\n")),
+        IRCmd::Jump(l) => assembly.push(Inst::Jmp(label(l))),
+        IRCmd::Label(l) => assembly.push(Inst::Label(label(l))),
         IRCmd::Return(irexp) => {
-            let operand = expr_to_assembly(
-                num_temps,
-                stack_counter,
-                irexp,
-                assembly,
-                coloring,
-                current_temp,
-            );
+            let operand = expr_to_assembly(num_temps, stack_counter, irexp, assembly, coloring);
 
-            assembly.push_str(&format!("mov ebx, {operand}\n"));
+            assembly.push(Inst::Mov(Operand::Reg(Reg::Rbx), operand));
 
-            assembly.push_str("mov rdi, QWORD PTR stdout[rip]\n");
-            assembly.push_str("call fflush\n");
-            assembly.push_str("mov eax, ebx\n");
-            assembly.push_str("ret\n");
+            load_stdout(assembly);
+            assembly.push(Inst::Call("fflush".to_owned()));
+            assembly.push(Inst::Mov(EAX, Operand::Reg(Reg::Rbx)));
+            assembly.push(Inst::Ret);
+        }
+        IRCmd::Call(call) => {
+            translate_call(num_temps, stack_counter, call, assembly, coloring, false);
         }
-        IRCmd::Call(call) => match call {
-            crate::ir::Call::Print(irexp) => {
-                save_register_onto_stack(assembly);
-                let old_stack_counter = *stack_counter;
-                let operand = expr_to_assembly(
-                    num_temps,
-                    stack_counter,
-                    irexp,
-                    assembly,
-                    coloring,
-                    current_temp,
-                );
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str(&format!("mov edi, {operand}\n"));
+    }
+}
 
-                *stack_counter = old_stack_counter;
-                assembly.push_str("call putchar\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-            }
-            crate::ir::Call::Read => {
-                assembly.push_str(&format!("mov eax, {}\n", *stack_counter * 4));
-                save_register_onto_stack(assembly);
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str("call getchar\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-            }
-            crate::ir::Call::Flush => {
-                assembly.push_str(&format!("mov eax, {}\n", *stack_counter * 4));
-                save_register_onto_stack(assembly);
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str("mov rdi, QWORD PTR stdout[rip]\n");
-                assembly.push_str("call fflush\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-            }
-            crate::ir::Call::Func(name, args) => {
-                save_register_onto_stack(assembly);
-                let old_stack_counter = *stack_counter;
-                move_args(
-                    args,
-                    assembly,
-                    num_temps,
-                    stack_counter,
-                    coloring,
-                    current_temp,
-                );
-                *stack_counter = old_stack_counter;
-                assembly.push_str(&format!("call {name}\n"));
-                get_register_from_stack(assembly);
+fn load_stdout(assembly: &mut Vec<Inst>) {
+    assembly.push(Inst::LoadGlobal(Reg::Rdi, "stdout".to_owned()));
+}
+
+/* A call as a statement or as an expression, whose result is in eax. Printing flushes stdout
+ * only in an expression. */
+fn translate_call(
+    num_temps: usize,
+    stack_counter: &mut usize,
+    call: &crate::ir::Call,
+    assembly: &mut Vec<Inst>,
+    coloring: &Vec<usize>,
+    in_expression: bool,
+) {
+    match call {
+        crate::ir::Call::Print(irexp) => {
+            save_register_onto_stack(assembly);
+            let old_stack_counter = *stack_counter;
+            let operand = expr_to_assembly(num_temps, stack_counter, irexp, assembly, coloring);
+            *stack_counter = old_stack_counter;
+            assembly.push(Inst::Alu64(AluOp::Sub, Reg::Rsp, 8));
+            assembly.push(Inst::Mov(Operand::Reg(Reg::Rdi), operand));
+            assembly.push(Inst::Call("putchar".to_owned()));
+            if in_expression {
+                load_stdout(assembly);
+                assembly.push(Inst::Call("fflush".to_owned()));
             }
-        },
+            assembly.push(Inst::Alu64(AluOp::Add, Reg::Rsp, 8));
+            get_register_from_stack(assembly);
+        }
+        crate::ir::Call::Read => {
+            assembly.push(Inst::Mov(EAX, Operand::Imm((*stack_counter * 4) as i32)));
+            save_register_onto_stack(assembly);
+            assembly.push(Inst::Alu64(AluOp::Sub, Reg::Rsp, 8));
+            assembly.push(Inst::Call("getchar".to_owned()));
+            assembly.push(Inst::Alu64(AluOp::Add, Reg::Rsp, 8));
+            get_register_from_stack(assembly);
+        }
+        crate::ir::Call::Flush => {
+            assembly.push(Inst::Mov(EAX, Operand::Imm((*stack_counter * 4) as i32)));
+            save_register_onto_stack(assembly);
+            assembly.push(Inst::Alu64(AluOp::Sub, Reg::Rsp, 8));
+            load_stdout(assembly);
+            assembly.push(Inst::Call("fflush".to_owned()));
+            assembly.push(Inst::Alu64(AluOp::Add, Reg::Rsp, 8));
+            get_register_from_stack(assembly);
+        }
+        crate::ir::Call::Func(name, args) => {
+            save_register_onto_stack(assembly);
+            let old_stack_counter = *stack_counter;
+            move_args(args, assembly, num_temps, stack_counter, coloring);
+            *stack_counter = old_stack_counter;
+            assembly.push(Inst::Call(name.clone()));
+            get_register_from_stack(assembly);
+        }
+    }
+}
+
+/* The assembly operand of the right side of an operation. It must not use eax, which holds the
+ * left side, so spilled temps are accessed in memory and nested expressions are moved to ecx. */
+fn operand(
+    num_temps: usize,
+    stack_counter: &mut usize,
+    expr: &IRExp,
+    assembly: &mut Vec<Inst>,
+    coloring: &Vec<usize>,
+) -> Operand {
+    match expr {
+        IRExp::Temp(t) => map_temp_to_register(coloring[t.name], true, assembly),
+        IRExp::ConstInt(val) => Operand::Imm(*val),
+        IRExp::ConstBool(val) => Operand::Imm(*val as i32),
+        expr => {
+            let result = expr_to_assembly(num_temps, stack_counter, expr, assembly, coloring);
+            assembly.push(Inst::Mov(ECX, result));
+            ECX
+        }
     }
 }
 
 fn expr_to_assembly(
     num_temps: usize,
     stack_counter: &mut usize,
-    expr: IRExp,
-    assembly: &mut String,
+    expr: &IRExp,
+    assembly: &mut Vec<Inst>,
     coloring: &Vec<usize>,
-    current_temp: &mut str,
-) -> String {
+) -> Operand {
     match expr {
         IRExp::Temp(t) => map_temp_to_register(coloring[t.name], false, assembly),
-        IRExp::ConstInt(val) => format!("{val}"),
+        IRExp::ConstInt(val) => Operand::Imm(*val),
         IRExp::ConstBool(val) => {
-            if val {
-                assembly.push_str("mov eax, 1\n");
-            } else {
-                assembly.push_str("mov eax, 0\n");
-            }
-            "eax".to_owned()
+            assembly.push(Inst::Mov(EAX, Operand::Imm(*val as i32)));
+            EAX
         }
         IRExp::Neg(irexp) => {
-            assembly.push_str(&format!("mov eax, {}\n", current_temp));
-            assembly.push_str(&format!("neg eax\n"));
-            "eax".to_owned()
+            let operand = operand(num_temps, stack_counter, irexp, assembly, coloring);
+            assembly.push(Inst::Mov(EAX, operand));
+            assembly.push(Inst::Unary(UnaryOp::Neg, EAX));
+            EAX
         }
         IRExp::NotBool(irexp) => {
-            assembly.push_str(&format!("mov eax, {}\n", current_temp));
-            assembly.push_str(&format!("xor eax, 1\n"));
-            "eax".to_owned()
+            let operand = operand(num_temps, stack_counter, irexp, assembly, coloring);
+            assembly.push(Inst::Mov(EAX, operand));
+            assembly.push(Inst::Alu(AluOp::Xor, EAX, Operand::Imm(1)));
+            EAX
         }
         IRExp::NotInt(irexp) => {
-            assembly.push_str(&format!("mov eax, {}\n", current_temp));
-            assembly.push_str(&format!("not eax\n"));
-            "eax".to_owned()
+            let operand = operand(num_temps, stack_counter, irexp, assembly, coloring);
+            assembly.push(Inst::Mov(EAX, operand));
+            assembly.push(Inst::Unary(UnaryOp::Not, EAX));
+            EAX
         }
         IRExp::Exp(b) => {
-            let (e1, op, _) = *b;
-            let second_op = current_temp.to_owned();
-            let first_op = expr_to_assembly(
-                num_temps,
-                stack_counter,
-                e1,
-                assembly,
-                coloring,
-                current_temp,
-            );
+            let (e1, op, e2) = &**b;
+            /* idiv has no immediate form */
+            let immediate = matches!(e2, IRExp::ConstInt(_) | IRExp::ConstBool(_));
+            let mut second_op = operand(num_temps, stack_counter, e2, assembly, coloring);
+            if immediate && matches!(op, crate::ir::Op::Div | crate::ir::Op::Mod) {
+                assembly.push(Inst::Mov(ECX, second_op));
+                second_op = ECX;
+            }
+            let first_op = expr_to_assembly(num_temps, stack_counter, e1, assembly, coloring);
+            assembly.push(Inst::Mov(EAX, first_op));
+            let alu = |op| Inst::Alu(op, EAX, second_op);
+            let compare = |cond| [alu(AluOp::Cmp), Inst::Set(cond), Inst::MovzxAl];
+            let shift = |op| [Inst::Mov(ECX, second_op), Inst::Shift(op)];
             match op {
-                crate::ir::Op::Plus => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("add eax, {second_op}\n"));
-                }
-                crate::ir::Op::Minus => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("sub eax, {second_op}\n"));
-                }
-                crate::ir::Op::Mult => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("imul eax, {second_op}\n"));
-                }
+                crate::ir::Op::Plus => assembly.push(alu(AluOp::Add)),
+                crate::ir::Op::Minus => assembly.push(alu(AluOp::Sub)),
+                crate::ir::Op::Mult => assembly.push(alu(AluOp::Imul)),
                 crate::ir::Op::Div => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str("cdq\n");
-                    assembly.push_str(&format!("idiv {second_op}\n"));
+                    assembly.extend([Inst::Cdq, Inst::Unary(UnaryOp::Idiv, second_op)])
                 }
                 crate::ir::Op::Mod => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str("cdq\n");
-                    assembly.push_str(&format!("idiv {second_op}\n"));
-                    return "edx".to_owned();
-                }
-                crate::ir::Op::LessThan => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("setl al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::LessEqual => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("setle al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::GreaterThan => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("setg al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::GreaterEqual => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("setge al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::Equals => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("sete al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::NotEqual => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("cmp eax, {second_op}\n"));
-                    assembly.push_str("setne al\n");
-                    assembly.push_str("movzx eax, al\n");
-                }
-                crate::ir::Op::BitAnd => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("and eax, {second_op}\n"));
-                }
-                crate::ir::Op::BitXor => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("xor eax, {second_op}\n"));
-                }
-                crate::ir::Op::BitOr => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("or eax, {second_op}\n"));
-                }
-                crate::ir::Op::LShift => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("mov ecx, {second_op}\n"));
-                    assembly.push_str("sal eax, cl\n");
-                }
-                crate::ir::Op::RShift => {
-                    assembly.push_str(&format!("mov eax, {first_op}\n"));
-                    assembly.push_str(&format!("mov ecx, {second_op}\n"));
-                    assembly.push_str("sar eax, cl\n");
+                    assembly.extend([Inst::Cdq, Inst::Unary(UnaryOp::Idiv, second_op)]);
+                    return Operand::Reg(Reg::Rdx);
                 }
+                crate::ir::Op::LessThan => assembly.extend(compare(Cond::L)),
+                crate::ir::Op::LessEqual => assembly.extend(compare(Cond::Le)),
+                crate::ir::Op::GreaterThan => assembly.extend(compare(Cond::G)),
+                crate::ir::Op::GreaterEqual => assembly.extend(compare(Cond::Ge)),
+                crate::ir::Op::Equals => assembly.extend(compare(Cond::E)),
+                crate::ir::Op::NotEqual => assembly.extend(compare(Cond::Ne)),
+                crate::ir::Op::BitAnd => assembly.push(alu(AluOp::And)),
+                crate::ir::Op::BitXor => assembly.push(alu(AluOp::Xor)),
+                crate::ir::Op::BitOr => assembly.push(alu(AluOp::Or)),
+                crate::ir::Op::LShift => assembly.extend(shift(ShiftOp::Sal)),
+                crate::ir::Op::RShift => assembly.extend(shift(ShiftOp::Sar)),
             }
-            "eax".to_owned()
+            EAX
+        }
+        IRExp::Call(call) => {
+            translate_call(num_temps, stack_counter, call, assembly, coloring, true);
+            EAX
         }
-        IRExp::Call(call) => match *call {
-            crate::ir::Call::Print(irexp) => {
-                save_register_onto_stack(assembly);
-                let old_stack_counter = *stack_counter;
-                let operand = expr_to_assembly(
-                    num_temps,
-                    stack_counter,
-                    irexp,
-                    assembly,
-                    coloring,
-                    current_temp,
-                );
-                *stack_counter = old_stack_counter;
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str(&format!("mov edi, {operand}\n"));
-                assembly.push_str("call putchar\n");
-                assembly.push_str("mov rdi, QWORD PTR stdout[rip]\n");
-                assembly.push_str("call fflush\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-                "eax".to_owned()
-            }
-            crate::ir::Call::Read => {
-                assembly.push_str(&format!("mov eax, {}\n", *stack_counter * 4));
-                save_register_onto_stack(assembly);
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str("call getchar\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-                "eax".to_owned()
-            }
-            crate::ir::Call::Flush => {
-                assembly.push_str(&format!("mov eax, {}\n", *stack_counter * 4));
-                save_register_onto_stack(assembly);
-                assembly.push_str("sub rsp, 8\n");
-                assembly.push_str("mov rdi, QWORD PTR stdout[rip]\n");
-                assembly.push_str("call fflush\n");
-                assembly.push_str("add rsp, 8\n");
-                get_register_from_stack(assembly);
-                "eax".to_owned()
-            }
-            crate::ir::Call::Func(name, args) => {
-                save_register_onto_stack(assembly);
-                let old_stack_counter = *stack_counter;
-                move_args(
-                    args,
-                    assembly,
-                    num_temps,
-                    stack_counter,
-                    coloring,
-                    current_temp,
-                );
-                *stack_counter = old_stack_counter;
-                assembly.push_str(&format!("call {name}\n"));
-                get_register_from_stack(assembly);
-                "eax".to_owned()
-            }
-        },
     }
 }
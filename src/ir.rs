@@ -1,16 +1,22 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
-use crate::{ast::Exp, elaboration::Abs, semantics::AbsFunction};
+use crate::{
+    arena::Arena,
+    ast::{Binop, Exp, ExpId},
+    elaboration::{Abs, AbsId},
+    semantics::AbsFunction,
+    symbols::{self, DeclId, Interner},
+};
 
-#[derive(Debug)]
-pub struct IRFunction<'a> {
-    pub name: &'a [u8],
+#[derive(Debug, Clone)]
+pub struct IRFunction {
+    pub name: String,
     pub num_temps: usize,
     pub num_params: usize,
     pub instructions: Vec<IRCmd>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum IRExp {
     Temp(Temp),
     ConstInt(i32),
@@ -22,13 +28,13 @@ pub enum IRExp {
     Call(Box<Call>),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Temp {
     pub name: usize,
     pub ver: usize,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Call {
     Print(IRExp),
     Read,
@@ -46,7 +52,7 @@ pub enum IRCmd {
     Call(Call),
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, PartialEq)]
 pub enum Op {
     Plus,
     Minus,
@@ -66,800 +72,478 @@ pub enum Op {
     RShift,
 }
 
-pub fn translate_to_ir<'a>(funcs: Vec<AbsFunction<'a>>) -> Vec<IRFunction<'a>> {
-    let mut label_count = 0;
-    let mut funcs_in_ir = Vec::new();
-    for f in funcs {
-        let mut num_temps = 0;
-        let label_cont = 0;
-        let label_brk = 0;
-        let mut vars: HashMap<&[u8], IRExp> = HashMap::new();
-        f.param_names.iter().for_each(|name| {
-            vars.insert(
-                name,
-                IRExp::Temp(Temp {
-                    name: num_temps,
-                    ver: 0,
-                }),
-            );
-            num_temps += 1;
-        });
-        let mut instructions = Vec::new();
-        translate_command(
-            f.body,
-            &mut instructions,
-            &mut num_temps,
-            &mut label_count,
-            &mut vars,
-            label_cont,
-            label_brk,
-            None,
-        );
-        funcs_in_ir.push(IRFunction {
-            name: f.name,
-            num_temps,
-            num_params: f.param_names.len(),
-            instructions,
-        });
-    }
-    funcs_in_ir
+/* The symbol of a function of the program. No identifier contains a dot, so the prefix keeps the
+ * functions apart from the symbols of the C library and the startup code, such as _start. */
+pub fn symbol(name: &str) -> String {
+    format!("c0.{name}")
 }
 
-fn translate_command<'a>(
-    abs: Abs<'a>,
-    program: &mut Vec<IRCmd>,
-    temp_count: &mut usize,
-    label_count: &mut usize,
-    vars: &mut HashMap<&'a [u8], IRExp>,
-    label_cont: usize,
-    label_brk: usize,
-    step: Option<&Abs<'a>>,
-) {
-    match abs {
-        Abs::ASGN(ident, mut exp) => {
-            let mut e = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-            program.append(&mut e.0);
-            let temp = vars.get(ident).unwrap();
-            program.push(IRCmd::Load(temp.clone(), e.1));
+impl IRExp {
+    /* Calls have effects and divisions may trap, so neither can be removed or reordered */
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            IRExp::Temp(_) | IRExp::ConstInt(_) | IRExp::ConstBool(_) => false,
+            IRExp::Neg(e) | IRExp::NotBool(e) | IRExp::NotInt(e) => e.has_side_effects(),
+            IRExp::Exp(b) => {
+                matches!(b.1, Op::Div | Op::Mod) || b.0.has_side_effects() || b.2.has_side_effects()
+            }
+            IRExp::Call(_) => true,
         }
-        Abs::WHILE(mut exp, abs) => {
-            let mut e = { exp_to_irexp(&mut exp, temp_count, label_count, vars) };
-            let label_start = *label_count;
-            let label_end = *label_count + 1;
-            *label_count += 2;
-            program.push(IRCmd::Label(label_start));
-            program.append(&mut e.0);
-            program.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(e.1)), label_end));
-            translate_command(
-                *abs,
-                program,
-                temp_count,
-                label_count,
-                vars,
-                label_start,
-                label_end,
-                None,
-            );
-            program.push(IRCmd::Jump(label_start));
-            program.push(IRCmd::Label(label_end));
+    }
+}
+
+/* The IR is printed as one instruction per line. Labels are not indented so that jump targets
+ * stand out. */
+impl fmt::Display for IRFunction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}(", self.name)?;
+        for i in 0..self.num_params {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "t{i}")?;
         }
-        Abs::CONT => {
-            if let Some(abs) = step {
-                translate_command(
-                    abs.clone(),
-                    program,
-                    temp_count,
-                    label_count,
-                    vars,
-                    label_cont,
-                    label_brk,
-                    step,
-                );
+        writeln!(f, "):")?;
+        for cmd in self.instructions.iter() {
+            match cmd {
+                IRCmd::Label(_) => writeln!(f, "{cmd}")?,
+                _ => writeln!(f, "    {cmd}")?,
             }
-            program.push(IRCmd::Jump(label_cont));
         }
-        Abs::RET(mut exp) => {
-            let mut e = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-            program.append(&mut e.0);
-            program.push(IRCmd::Return(e.1));
+        Ok(())
+    }
+}
+
+impl fmt::Display for IRCmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRCmd::Load(dest, exp) => write!(f, "{dest} = {exp}"),
+            IRCmd::JumpIf(exp, label) => write!(f, "if {exp} goto L{label}"),
+            IRCmd::Jump(label) => write!(f, "goto L{label}"),
+            IRCmd::Label(label) => write!(f, "L{label}:"),
+            IRCmd::Return(exp) => write!(f, "return {exp}"),
+            IRCmd::Call(call) => write!(f, "{call}"),
         }
-        Abs::DECL(ident, _, abs) => {
-            vars.insert(
-                ident,
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-            );
-            *temp_count += 1;
-            translate_command(
-                *abs,
-                program,
-                temp_count,
-                label_count,
-                vars,
-                label_cont,
-                label_brk,
-                step,
-            );
+    }
+}
+
+impl fmt::Display for IRExp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IRExp::Temp(temp) => write!(f, "{temp}"),
+            IRExp::ConstInt(val) => write!(f, "{val}"),
+            IRExp::ConstBool(val) => write!(f, "{val}"),
+            IRExp::Neg(exp) => write!(f, "-{}", Operand(exp)),
+            IRExp::NotBool(exp) => write!(f, "!{}", Operand(exp)),
+            IRExp::NotInt(exp) => write!(f, "~{}", Operand(exp)),
+            IRExp::Exp(b) => write!(f, "{} {} {}", Operand(&b.0), b.1, Operand(&b.2)),
+            IRExp::Call(call) => write!(f, "{call}"),
         }
-        Abs::IF(mut exp, abs1, abs2) => {
-            let mut e1 = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-            program.append(&mut e1.0);
-            let then_label = *label_count;
-            let end_label = *label_count + 1;
-            *label_count += 2;
-            program.push(IRCmd::JumpIf(e1.1, then_label));
-            translate_command(
-                *abs2,
-                program,
-                temp_count,
-                label_count,
-                vars,
-                label_cont,
-                label_brk,
-                step,
-            );
-            program.push(IRCmd::Jump(end_label));
-            program.push(IRCmd::Label(then_label));
-            translate_command(
-                *abs1,
-                program,
-                temp_count,
-                label_count,
-                vars,
-                label_cont,
-                label_brk,
-                step,
-            );
-            program.push(IRCmd::Label(end_label));
+    }
+}
+
+/* Wraps nested operations in parentheses, the IR has no precedence */
+struct Operand<'e>(&'e IRExp);
+
+impl fmt::Display for Operand<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            IRExp::Exp(_) => write!(f, "({})", self.0),
+            exp => write!(f, "{exp}"),
         }
-        Abs::FOR(b) => {
-            let mut seq = Vec::new();
-            match *b {
-                Abs::DECL(ident, _, abs) => {
-                    if let Abs::SEQ(vec) = *abs {
-                        seq = vec
-                    }
-                    vars.insert(
-                        ident,
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                    );
-                    *temp_count += 1;
-                    if matches!(seq[0], Abs::ASGN(..)) {
-                        translate_command(
-                            seq.remove(0),
-                            program,
-                            temp_count,
-                            label_count,
-                            vars,
-                            label_cont,
-                            label_brk,
-                            step,
-                        );
-                    }
-                }
-                Abs::SEQ(vec) => {
-                    seq = vec;
-                    if matches!(seq[0], Abs::ASGN(..)) {
-                        translate_command(
-                            seq.remove(0),
-                            program,
-                            temp_count,
-                            label_count,
-                            vars,
-                            label_cont,
-                            label_brk,
-                            step,
-                        );
-                    } else {
-                        seq.remove(0);
+    }
+}
+
+impl fmt::Display for Temp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.ver == 0 {
+            write!(f, "t{}", self.name)
+        } else {
+            write!(f, "t{}.{}", self.name, self.ver)
+        }
+    }
+}
+
+impl fmt::Display for Call {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Call::Print(exp) => write!(f, "print({exp})"),
+            Call::Read => write!(f, "read()"),
+            Call::Flush => write!(f, "flush()"),
+            Call::Func(name, args) => {
+                write!(f, "{name}(")?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
                     }
+                    write!(f, "{arg}")?;
                 }
-                _ => (),
-            }
-            let label_start = *label_count;
-            let label_end = *label_count + 1;
-            *label_count += 2;
-            program.push(IRCmd::Label(label_start));
-            if let Abs::EXP(mut exp) = seq.remove(0) {
-                let mut e = { exp_to_irexp(&mut exp, temp_count, label_count, vars) };
-                program.append(&mut e.0);
-                program.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(e.1)), label_end));
+                write!(f, ")")
             }
-            for i in 0..seq.len() - 1 {
-                translate_command(
-                    seq[i].clone(),
-                    program,
-                    temp_count,
-                    label_count,
-                    vars,
-                    label_start,
-                    label_end,
-                    seq.last(),
-                );
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            Op::Plus => "+",
+            Op::Minus => "-",
+            Op::Mult => "*",
+            Op::Div => "/",
+            Op::Mod => "%",
+            Op::LessThan => "<",
+            Op::LessEqual => "<=",
+            Op::GreaterThan => ">",
+            Op::GreaterEqual => ">=",
+            Op::Equals => "==",
+            Op::NotEqual => "!=",
+            Op::BitAnd => "&",
+            Op::BitXor => "^",
+            Op::BitOr => "|",
+            Op::LShift => "<<",
+            Op::RShift => ">>",
+        };
+        write!(f, "{op}")
+    }
+}
+
+pub fn translate_to_ir(
+    funcs: &[AbsFunction],
+    exps: &Arena<Exp>,
+    names: &Interner,
+) -> Vec<IRFunction> {
+    funcs
+        .iter()
+        .map(|f| function_to_ir(f, exps, names))
+        .collect()
+}
+
+/* Translates a single function, exps are the expressions and names the symbols of its unit.
+ * Temps and labels are numbered per function, so the result does not depend on the other
+ * functions of the program. */
+pub fn function_to_ir(f: &AbsFunction, exps: &Arena<Exp>, names: &Interner) -> IRFunction {
+    let mut translator = Translator {
+        nodes: &f.nodes,
+        exps,
+        names,
+        program: Vec::new(),
+        temp_count: 0,
+        label_count: 0,
+        vars: HashMap::new(),
+    };
+    f.params.iter().for_each(|var| {
+        let temp = translator.new_temp();
+        translator.vars.insert(var.decl(), temp);
+    });
+    let label_cont = 0;
+    let label_brk = 0;
+    translator.command(f.body, label_cont, label_brk, None);
+    IRFunction {
+        name: names.name(f.name).to_owned(),
+        num_temps: translator.temp_count,
+        num_params: f.params.len(),
+        instructions: translator.program,
+    }
+}
+
+/* The state of the translation of one function. The nodes are only read, so a statement like
+ * the step of a for loop can be translated again by its id. */
+struct Translator<'t> {
+    nodes: &'t Arena<Abs>,
+    exps: &'t Arena<Exp>,
+    names: &'t Interner,
+    program: Vec<IRCmd>,
+    temp_count: usize,
+    label_count: usize,
+    vars: HashMap<DeclId, IRExp>,
+}
+
+impl Translator<'_> {
+    fn new_temp(&mut self) -> IRExp {
+        self.temp_count += 1;
+        IRExp::Temp(Temp {
+            name: self.temp_count - 1,
+            ver: 0,
+        })
+    }
+
+    /* The temp of the next load, which new_temp reserves once the load is emitted */
+    fn next_temp(&self) -> IRExp {
+        IRExp::Temp(Temp {
+            name: self.temp_count,
+            ver: 0,
+        })
+    }
+
+    /* Appends the instructions computing exp and returns the operand holding its value */
+    fn value(&mut self, exp: ExpId) -> IRExp {
+        let (mut cmds, value) = self.exp(exp);
+        self.program.append(&mut cmds);
+        value
+    }
+
+    fn command(&mut self, abs: AbsId, label_cont: usize, label_brk: usize, step: Option<AbsId>) {
+        let nodes = self.nodes;
+        match &nodes[abs] {
+            Abs::ASGN(ident, exp, _) => {
+                let value = self.value(*exp);
+                let temp = self.vars[&ident.decl()].clone();
+                self.program.push(IRCmd::Load(temp, value));
             }
-            if let Some(last) = seq.last() {
-                translate_command(
-                    last.clone(),
-                    program,
-                    temp_count,
-                    label_count,
-                    vars,
-                    label_cont,
-                    label_brk,
-                    step,
-                );
+            Abs::WHILE(exp, body, _) => {
+                let (mut cmds, value) = self.exp(*exp);
+                let label_start = self.label_count;
+                let label_end = self.label_count + 1;
+                self.label_count += 2;
+                self.program.push(IRCmd::Label(label_start));
+                self.program.append(&mut cmds);
+                self.program
+                    .push(IRCmd::JumpIf(IRExp::NotBool(Box::new(value)), label_end));
+                self.command(*body, label_start, label_end, None);
+                self.program.push(IRCmd::Jump(label_start));
+                self.program.push(IRCmd::Label(label_end));
             }
-            program.push(IRCmd::Jump(label_start));
-            program.push(IRCmd::Label(label_end));
-        }
-        Abs::BRK => program.push(IRCmd::Jump(label_brk)),
-        Abs::SEQ(items) => {
-            for abs in items {
-                translate_command(
-                    abs,
-                    program,
-                    temp_count,
-                    label_count,
-                    vars,
-                    label_cont,
-                    label_brk,
-                    step,
-                );
+            Abs::CONT(_) => {
+                if let Some(step) = step {
+                    self.command(step, label_cont, label_brk, Some(step));
+                }
+                self.program.push(IRCmd::Jump(label_cont));
             }
-        }
-        Abs::EXP(_) => (),
-        Abs::CALL(name, mut args) => match name {
-            b"print" => {
-                let mut exp = args.pop().unwrap();
-                let mut res = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-                program.append(&mut res.0);
-                program.push(IRCmd::Call(Call::Print(res.1)));
+            Abs::RET(exp, _) => {
+                let value = self.value(*exp);
+                self.program.push(IRCmd::Return(value));
             }
-            b"read" => {
-                program.push(IRCmd::Call(Call::Read));
+            Abs::DECL(ident, _, body, _) => {
+                let temp = self.new_temp();
+                self.vars.insert(ident.decl(), temp);
+                self.command(*body, label_cont, label_brk, step);
             }
-            b"flush" => {
-                program.push(IRCmd::Call(Call::Flush));
+            Abs::IF(exp, then, otherwise, _) => {
+                let value = self.value(*exp);
+                let then_label = self.label_count;
+                let end_label = self.label_count + 1;
+                self.label_count += 2;
+                self.program.push(IRCmd::JumpIf(value, then_label));
+                self.command(*otherwise, label_cont, label_brk, step);
+                self.program.push(IRCmd::Jump(end_label));
+                self.program.push(IRCmd::Label(then_label));
+                self.command(*then, label_cont, label_brk, step);
+                self.program.push(IRCmd::Label(end_label));
             }
-            _ => {
-                let mut cmds = Vec::new();
-                let mut func_args = Vec::new();
-                for mut exp in args {
-                    let mut res = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-                    cmds.append(&mut res.0);
-                    func_args.push(res.1);
+            Abs::FOR(body, _) => self.for_loop(*body, label_cont, label_brk, step),
+            Abs::BRK(_) => self.program.push(IRCmd::Jump(label_brk)),
+            Abs::SEQ(items) => {
+                for abs in items {
+                    self.command(*abs, label_cont, label_brk, step);
                 }
-                program.append(&mut cmds);
-                program.push(IRCmd::Call(Call::Func(
-                    format!("_{}", str::from_utf8(name).unwrap()),
-                    func_args,
-                )));
             }
-        },
-    }
-}
-
-fn exp_to_irexp<'a>(
-    exp: &mut Exp<'a>,
-    temp_count: &mut usize,
-    label_count: &mut usize,
-    vars: &mut HashMap<&'a [u8], IRExp>,
-) -> (Vec<IRCmd>, IRExp) {
-    match exp {
-        Exp::True => {
-            let vec = vec![IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                IRExp::ConstBool(true),
-            )];
-            *temp_count += 1;
-            (
-                vec,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
-        }
-        Exp::False => {
-            let vec = vec![IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                IRExp::ConstBool(false),
-            )];
-            *temp_count += 1;
-            (
-                vec,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
-        }
-        Exp::Intconst(num) => {
-            let vec = vec![IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                IRExp::ConstInt(*num),
-            )];
-            *temp_count += 1;
-            (
-                vec,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
-        }
-        Exp::Ident(name) => {
-            let vec = vec![IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                vars.get(name).unwrap().clone(),
-            )];
-            *temp_count += 1;
-            (
-                vec,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
-        }
-        Exp::Arithmetic(b) => {
-            let mut e1 = exp_to_irexp(&mut b.0, temp_count, label_count, vars);
-            match b.1 {
-                crate::ast::Binop::Plus => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Plus, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::Minus => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Minus, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::Div => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Div, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::Mult => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Mult, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::Mod => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Mod, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::LessThan => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::LessThan, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::LessEqual => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::LessEqual, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::GreaterThan => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::GreaterThan, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::GreaterEqual => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::GreaterEqual, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::Equals => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::Equals, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::NotEqual => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::NotEqual, e2.1))),
-                    ));
+            Abs::EXP(_) => (),
+            Abs::CALL(name, args, _) => match *name {
+                symbols::PRINT => {
+                    let value = self.value(args[args.len() - 1]);
+                    self.program.push(IRCmd::Call(Call::Print(value)));
                 }
-                crate::ast::Binop::And => {
-                    let mut vec = Vec::new();
-                    vec.append(&mut e1.0);
-                    let false_label = *label_count;
-                    let done_label = *label_count + 1;
-                    *label_count += 2;
-                    vec.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(e1.1)), false_label));
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    vec.append(&mut e2.0);
-                    vec.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(e2.1)), false_label));
-                    vec.push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::ConstBool(true),
-                    ));
-                    vec.push(IRCmd::Jump(done_label));
-                    vec.push(IRCmd::Label(false_label));
-                    vec.push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::ConstBool(false),
-                    ));
-                    vec.push(IRCmd::Label(done_label));
-                    *temp_count += 1;
-                    return (
-                        vec,
-                        IRExp::Temp(Temp {
-                            name: *temp_count - 1,
-                            ver: 0,
-                        }),
-                    );
+                symbols::READ => {
+                    self.program.push(IRCmd::Call(Call::Read));
                 }
-                crate::ast::Binop::Or => {
-                    let mut vec = Vec::new();
-                    vec.append(&mut e1.0);
-                    let true_label = *label_count;
-                    let done_label = *label_count + 1;
-                    *label_count += 2;
-                    vec.push(IRCmd::JumpIf(e1.1, true_label));
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    vec.append(&mut e2.0);
-                    vec.push(IRCmd::JumpIf(e2.1, true_label));
-                    vec.push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::ConstBool(false),
-                    ));
-                    vec.push(IRCmd::Jump(done_label));
-                    vec.push(IRCmd::Label(true_label));
-                    vec.push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::ConstBool(true),
-                    ));
-                    vec.push(IRCmd::Label(done_label));
-                    *temp_count += 1;
-                    return (
-                        vec,
-                        IRExp::Temp(Temp {
-                            name: *temp_count - 1,
-                            ver: 0,
-                        }),
-                    );
+                symbols::FLUSH => {
+                    self.program.push(IRCmd::Call(Call::Flush));
                 }
-                crate::ast::Binop::BitAnd => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::BitAnd, e2.1))),
-                    ));
-                }
-                crate::ast::Binop::BitXor => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::BitXor, e2.1))),
-                    ));
+                _ => {
+                    let mut cmds = Vec::new();
+                    let mut func_args = Vec::new();
+                    for exp in args {
+                        let (mut arg_cmds, value) = self.exp(*exp);
+                        cmds.append(&mut arg_cmds);
+                        func_args.push(value);
+                    }
+                    self.program.append(&mut cmds);
+                    self.program.push(IRCmd::Call(Call::Func(
+                        symbol(self.names.name(*name)),
+                        func_args,
+                    )));
                 }
-                crate::ast::Binop::BitOr => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::BitOr, e2.1))),
-                    ));
+            },
+        }
+    }
+
+    /* A for loop is elaborated into its initializer, the condition, the body and the step. The
+     * initializer runs once, a continue in the body jumps to the step. */
+    fn for_loop(&mut self, body: AbsId, label_cont: usize, label_brk: usize, step: Option<AbsId>) {
+        let nodes = self.nodes;
+        let mut seq: &[AbsId] = &[];
+        match &nodes[body] {
+            Abs::DECL(ident, _, scope, _) => {
+                if let Abs::SEQ(items) = &nodes[*scope] {
+                    seq = items
                 }
-                crate::ast::Binop::LShift => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::LShift, e2.1))),
-                    ));
+                let temp = self.new_temp();
+                self.vars.insert(ident.decl(), temp);
+                if matches!(nodes[seq[0]], Abs::ASGN(..)) {
+                    self.command(seq[0], label_cont, label_brk, step);
+                    seq = &seq[1..];
                 }
-                crate::ast::Binop::RShift => {
-                    let mut e2 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-                    (e1.0).append(&mut e2.0);
-                    (e1.0).push(IRCmd::Load(
-                        IRExp::Temp(Temp {
-                            name: *temp_count,
-                            ver: 0,
-                        }),
-                        IRExp::Exp(Box::new((e1.1, Op::RShift, e2.1))),
-                    ));
+            }
+            Abs::SEQ(items) => {
+                seq = items;
+                if matches!(nodes[seq[0]], Abs::ASGN(..)) {
+                    self.command(seq[0], label_cont, label_brk, step);
                 }
+                seq = &seq[1..];
             }
-            *temp_count += 1;
-            (
-                e1.0,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
+            _ => (),
         }
-        Exp::Negative(exp) => {
-            let mut e = exp_to_irexp(exp, temp_count, label_count, vars);
-            {
-                e.0.push(IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::Neg(Box::new(e.1)),
-                ));
-                *temp_count += 1;
-                (
-                    e.0,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
-            }
+        let label_start = self.label_count;
+        let label_end = self.label_count + 1;
+        self.label_count += 2;
+        self.program.push(IRCmd::Label(label_start));
+        if let Abs::EXP(exp) = &nodes[seq[0]] {
+            let value = self.value(*exp);
+            self.program
+                .push(IRCmd::JumpIf(IRExp::NotBool(Box::new(value)), label_end));
         }
-        Exp::Not(exp) => {
-            let mut e = exp_to_irexp(exp, temp_count, label_count, vars);
-            {
-                e.0.push(IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::NotBool(Box::new(e.1)),
-                ));
-                *temp_count += 1;
-                (
-                    e.0,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
-            }
+        let seq = &seq[1..];
+        let (last, body) = seq.split_last().unwrap();
+        for abs in body {
+            self.command(*abs, label_start, label_end, Some(*last));
         }
-        Exp::BitNot(exp) => {
-            let mut e = exp_to_irexp(exp, temp_count, label_count, vars);
-            {
-                e.0.push(IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::NotInt(Box::new(e.1)),
-                ));
-                *temp_count += 1;
-                (
-                    e.0,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
+        self.command(*last, label_cont, label_brk, step);
+        self.program.push(IRCmd::Jump(label_start));
+        self.program.push(IRCmd::Label(label_end));
+    }
+
+    /* Returns the instructions computing exp and the temp that holds its value afterwards */
+    fn exp(&mut self, id: ExpId) -> (Vec<IRCmd>, IRExp) {
+        let exps = self.exps;
+        let (mut cmds, value) = match &exps[id] {
+            Exp::True(_) => (vec![], IRExp::ConstBool(true)),
+            Exp::False(_) => (vec![], IRExp::ConstBool(false)),
+            Exp::Intconst(num, _) => (vec![], IRExp::ConstInt(*num)),
+            Exp::Ident(name, _) => (vec![], self.vars[&name.decl()].clone()),
+            Exp::Arithmetic(e1, Binop::And, e2, _) => return self.and(*e1, *e2),
+            Exp::Arithmetic(e1, Binop::Or, e2, _) => return self.or(*e1, *e2),
+            Exp::Arithmetic(e1, binop, e2, _) => {
+                let (mut cmds, left) = self.exp(*e1);
+                let (mut right_cmds, right) = self.exp(*e2);
+                cmds.append(&mut right_cmds);
+                (cmds, IRExp::Exp(Box::new((left, op(binop), right))))
             }
-        }
-        Exp::Ternary(b) => {
-            let mut e1 = exp_to_irexp(&mut b.0, temp_count, label_count, vars);
-            let mut e2 = exp_to_irexp(&mut b.1, temp_count, label_count, vars);
-            let mut e3 = exp_to_irexp(&mut b.2, temp_count, label_count, vars);
-            let mut vec = Vec::new();
-            vec.append(&mut e1.0);
-            vec.push(IRCmd::JumpIf(e1.1, *label_count));
-            vec.append(&mut e3.0);
-            vec.push(IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                e3.1,
-            ));
-            vec.push(IRCmd::Jump(*label_count + 1));
-            vec.push(IRCmd::Label(*label_count));
-            *label_count += 1;
-            vec.append(&mut e2.0);
-            vec.push(IRCmd::Load(
-                IRExp::Temp(Temp {
-                    name: *temp_count,
-                    ver: 0,
-                }),
-                e2.1,
-            ));
-            vec.push(IRCmd::Label(*label_count));
-            *label_count += 1;
-            *temp_count += 1;
-            (
-                vec,
-                IRExp::Temp(Temp {
-                    name: *temp_count - 1,
-                    ver: 0,
-                }),
-            )
-        }
-        Exp::Call(call) => match call {
-            crate::ast::Call::Print(arg_list) => {
-                let mut cmds = Vec::new();
-                let mut exp = arg_list.clone().into_args().pop().unwrap();
-                let mut res = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-                cmds.append(&mut res.0);
-                cmds.push(IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::Call(Box::new(Call::Print(res.1))),
-                ));
-                *temp_count += 1;
-                (
-                    cmds,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
+            Exp::Negative(e, _) => {
+                let (cmds, value) = self.exp(*e);
+                (cmds, IRExp::Neg(Box::new(value)))
             }
-            crate::ast::Call::Read(..) => {
-                let cmds = vec![IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::Call(Box::new(Call::Read)),
-                )];
-                *temp_count += 1;
-                (
-                    cmds,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
+            Exp::Not(e, _) => {
+                let (cmds, value) = self.exp(*e);
+                (cmds, IRExp::NotBool(Box::new(value)))
             }
-            crate::ast::Call::Flush(..) => {
-                let cmds = vec![IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::Call(Box::new(Call::Flush)),
-                )];
-                *temp_count += 1;
-                (
-                    cmds,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
+            Exp::BitNot(e, _) => {
+                let (cmds, value) = self.exp(*e);
+                (cmds, IRExp::NotInt(Box::new(value)))
             }
-            crate::ast::Call::Func(name, arg_list) => {
-                let mut cmds = Vec::new();
-                let mut args = Vec::new();
-                for mut exp in arg_list.clone().into_args() {
-                    let mut res = exp_to_irexp(&mut exp, temp_count, label_count, vars);
-                    cmds.append(&mut res.0);
-                    args.push(res.1);
+            Exp::Ternary(e1, e2, e3, _) => return self.ternary(*e1, *e2, *e3),
+            Exp::Call(call, _) => match call {
+                crate::ast::Call::Print(arg_list) => {
+                    let args = arg_list.get_args();
+                    let (cmds, value) = self.exp(args[args.len() - 1]);
+                    (cmds, IRExp::Call(Box::new(Call::Print(value))))
                 }
-                cmds.push(IRCmd::Load(
-                    IRExp::Temp(Temp {
-                        name: *temp_count,
-                        ver: 0,
-                    }),
-                    IRExp::Call(Box::new(Call::Func(
-                        format!("_{}", str::from_utf8(name).unwrap()),
-                        args,
-                    ))),
-                ));
-                *temp_count += 1;
-                (
-                    cmds,
-                    IRExp::Temp(Temp {
-                        name: *temp_count - 1,
-                        ver: 0,
-                    }),
-                )
-            }
-        },
+                crate::ast::Call::Read(..) => (vec![], IRExp::Call(Box::new(Call::Read))),
+                crate::ast::Call::Flush(..) => (vec![], IRExp::Call(Box::new(Call::Flush))),
+                crate::ast::Call::Func(name, arg_list) => {
+                    let mut cmds = Vec::new();
+                    let mut args = Vec::new();
+                    for exp in arg_list.get_args() {
+                        let (mut arg_cmds, value) = self.exp(*exp);
+                        cmds.append(&mut arg_cmds);
+                        args.push(value);
+                    }
+                    (
+                        cmds,
+                        IRExp::Call(Box::new(Call::Func(symbol(self.names.name(*name)), args))),
+                    )
+                }
+            },
+        };
+        /* Every expression gets a temp of its own */
+        cmds.push(IRCmd::Load(self.next_temp(), value));
+        (cmds, self.new_temp())
+    }
+
+    /* e1 && e2 only evaluates e2 if e1 is true */
+    fn and(&mut self, e1: ExpId, e2: ExpId) -> (Vec<IRCmd>, IRExp) {
+        let (mut vec, left) = self.exp(e1);
+        let false_label = self.label_count;
+        let done_label = self.label_count + 1;
+        self.label_count += 2;
+        vec.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(left)), false_label));
+        let (mut right_cmds, right) = self.exp(e2);
+        vec.append(&mut right_cmds);
+        vec.push(IRCmd::JumpIf(IRExp::NotBool(Box::new(right)), false_label));
+        vec.push(IRCmd::Load(self.next_temp(), IRExp::ConstBool(true)));
+        vec.push(IRCmd::Jump(done_label));
+        vec.push(IRCmd::Label(false_label));
+        vec.push(IRCmd::Load(self.next_temp(), IRExp::ConstBool(false)));
+        vec.push(IRCmd::Label(done_label));
+        (vec, self.new_temp())
+    }
+
+    /* e1 || e2 only evaluates e2 if e1 is false */
+    fn or(&mut self, e1: ExpId, e2: ExpId) -> (Vec<IRCmd>, IRExp) {
+        let (mut vec, left) = self.exp(e1);
+        let true_label = self.label_count;
+        let done_label = self.label_count + 1;
+        self.label_count += 2;
+        vec.push(IRCmd::JumpIf(left, true_label));
+        let (mut right_cmds, right) = self.exp(e2);
+        vec.append(&mut right_cmds);
+        vec.push(IRCmd::JumpIf(right, true_label));
+        vec.push(IRCmd::Load(self.next_temp(), IRExp::ConstBool(false)));
+        vec.push(IRCmd::Jump(done_label));
+        vec.push(IRCmd::Label(true_label));
+        vec.push(IRCmd::Load(self.next_temp(), IRExp::ConstBool(true)));
+        vec.push(IRCmd::Label(done_label));
+        (vec, self.new_temp())
+    }
+
+    /* Both branches are translated before the condition is tested, but only one of them is
+     * executed */
+    fn ternary(&mut self, e1: ExpId, e2: ExpId, e3: ExpId) -> (Vec<IRCmd>, IRExp) {
+        let (mut vec, cond) = self.exp(e1);
+        let (mut then_cmds, then) = self.exp(e2);
+        let (mut else_cmds, otherwise) = self.exp(e3);
+        vec.push(IRCmd::JumpIf(cond, self.label_count));
+        vec.append(&mut else_cmds);
+        vec.push(IRCmd::Load(self.next_temp(), otherwise));
+        vec.push(IRCmd::Jump(self.label_count + 1));
+        vec.push(IRCmd::Label(self.label_count));
+        self.label_count += 1;
+        vec.append(&mut then_cmds);
+        vec.push(IRCmd::Load(self.next_temp(), then));
+        vec.push(IRCmd::Label(self.label_count));
+        self.label_count += 1;
+        (vec, self.new_temp())
+    }
+}
+
+fn op(binop: &Binop) -> Op {
+    match binop {
+        Binop::Plus => Op::Plus,
+        Binop::Minus => Op::Minus,
+        Binop::Div => Op::Div,
+        Binop::Mult => Op::Mult,
+        Binop::Mod => Op::Mod,
+        Binop::LessThan => Op::LessThan,
+        Binop::LessEqual => Op::LessEqual,
+        Binop::GreaterThan => Op::GreaterThan,
+        Binop::GreaterEqual => Op::GreaterEqual,
+        Binop::Equals => Op::Equals,
+        Binop::NotEqual => Op::NotEqual,
+        Binop::BitAnd => Op::BitAnd,
+        Binop::BitXor => Op::BitXor,
+        Binop::BitOr => Op::BitOr,
+        Binop::LShift => Op::LShift,
+        Binop::RShift => Op::RShift,
+        Binop::And | Binop::Or => unreachable!("{binop} is translated into jumps"),
     }
 }
@@ -0,0 +1,672 @@
+use std::{collections::HashMap, mem, path::PathBuf};
+
+use lalrpop_util::{ParseError, lalrpop_mod};
+
+use crate::{
+    arena::Arena,
+    ast::{Exp, Program},
+    cache::{Cache, CacheEntry, function_key},
+    code_gen::Assembly,
+    coloring::{color_func, is_spilled},
+    diagnostics::{Diagnostic, Diagnostics, SourceFile, Span},
+    instruction_selection::translate_function,
+    ir::{IRFunction, function_to_ir},
+    liveness::{analyze_func, find_dead_stores},
+    name_resolution::resolve_names,
+    optimize::{
+        eliminate_common_subexpressions, eliminate_dead_code, fold_constants, inline_calls,
+        optimizations_for_level, propagate_copies,
+    },
+    parallel::parallel_map,
+    passes::{Pass, PassManager, PassTiming, Stats},
+    semantics::{
+        AbsFunction, check_definitions, check_function_names, check_main, check_semantics,
+    },
+    symbols::{Symbol, SymbolTable},
+    tokenizer::{LexError, SpannedToken, Token, tokenize},
+    warnings::{Warning, WarningOptions, check_warnings, reachable_functions},
+};
+
+lalrpop_mod!(
+    #[allow(clippy::ptr_arg, clippy::large_enum_variant, unused_imports)]
+    #[rustfmt::skip]
+    parser
+);
+
+pub mod arena;
+pub mod ast;
+pub mod cache;
+pub mod code_gen;
+pub mod coloring;
+pub mod definite_assignment;
+pub mod diagnostics;
+pub mod elaboration;
+pub mod elf;
+pub mod error_codes;
+pub mod instruction_selection;
+pub mod ir;
+pub mod liveness;
+pub mod name_resolution;
+pub mod optimize;
+pub mod parallel;
+pub mod passes;
+pub mod runtime;
+pub mod semantics;
+//pub mod ssa;
+pub mod symbols;
+pub mod tokenizer;
+pub mod warnings;
+pub mod x86;
+
+/* Settings that influence a compilation */
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub warnings: WarningOptions,
+    /* 0 to 2, like -O0 to -O2 */
+    pub opt_level: u8,
+    /* Optimizations turned off with -fno-<name> even though the level includes them */
+    pub disabled_optimizations: Vec<&'static str>,
+    /* Where the code of every function is kept to be reused by the next build, see cache.rs */
+    pub cache_dir: Option<PathBuf>,
+    /* Threads for the backend, 0 for one per core */
+    pub jobs: usize,
+}
+
+impl Options {
+    /* The optimizations that run, in order */
+    pub fn optimizations(&self) -> Vec<&'static str> {
+        optimizations_for_level(self.opt_level)
+            .iter()
+            .copied()
+            .filter(|name| !self.disabled_optimizations.contains(name))
+            .collect()
+    }
+}
+
+/* The products of every phase of a successful compilation */
+#[derive(Debug)]
+pub struct Artifact {
+    pub tokens: Vec<SpannedToken>,
+    pub ast: Program,
+    /* Their expressions are those of ast */
+    pub functions: Vec<AbsFunction>,
+    /* The functions of the whole program and the parameters and variables of this unit */
+    pub symbols: SymbolTable,
+    pub ir: Vec<IRFunction>,
+    pub assembly: Assembly,
+    pub warnings: Vec<Diagnostic>,
+    pub timings: Vec<PassTiming>,
+    pub stats: Stats,
+}
+
+/* The diagnostics of a failed compilation: those of every unit in the order of the units, sorted by
+ * location, and those of linking the program, which belong to no unit */
+#[derive(Debug)]
+pub struct Failure {
+    pub units: Vec<Vec<Diagnostic>>,
+    pub link: Vec<Diagnostic>,
+}
+
+/* The state the passes of one unit share, every pass fills in the fields of its phase */
+struct Compilation<'a> {
+    source: &'a [u8],
+    /* The index of the unit in the program */
+    unit: usize,
+    options: Options,
+    diags: Diagnostics,
+    tokens: Vec<SpannedToken>,
+    /* Out of range literals, reported with the semantic errors */
+    range_errors: Vec<LexError>,
+    ast: Option<Program>,
+    /* The functions of every unit, and those reachable from main if the program has one and their
+     * warning is enabled. Both are computed across units by compile_program. Name resolution adds the parameters and variables
+     * of the unit to the symbols. */
+    symbols: SymbolTable,
+    reachable: Option<Vec<Symbol>>,
+    functions: Vec<AbsFunction>,
+    ir: Vec<IRFunction>,
+    /* The cache entry of every function, None for the functions that are compiled. The IR of the
+     * cached functions is only used for inlining until codegen takes their code from the cache. */
+    cached: Vec<Option<CacheEntry>>,
+    keys: Vec<u64>,
+    /* The IR of the compiled functions right after the translation, to be stored in the cache */
+    translated: Vec<IRFunction>,
+    /* The live temps of every line, per function */
+    liveness: Vec<Vec<Vec<usize>>>,
+    colorings: Vec<Vec<usize>>,
+    assembly: Assembly,
+    stats: Stats,
+}
+
+impl<'a> Compilation<'a> {
+    fn new(source: &'a [u8], unit: usize, options: &Options) -> Self {
+        Compilation {
+            source,
+            unit,
+            options: options.clone(),
+            diags: Diagnostics::new(),
+            tokens: Vec::new(),
+            range_errors: Vec::new(),
+            ast: None,
+            symbols: SymbolTable::new(),
+            reachable: None,
+            functions: Vec::new(),
+            ir: Vec::new(),
+            cached: Vec::new(),
+            keys: Vec::new(),
+            translated: Vec::new(),
+            liveness: Vec::new(),
+            colorings: Vec::new(),
+            assembly: Assembly::default(),
+            stats: Stats::default(),
+        }
+    }
+}
+
+/* Turns a terminal name as LALRPOP reports it, e.g. "\";\"", into a readable one */
+fn terminal_name(name: &str) -> String {
+    match name.trim_matches('"') {
+        "ident" => "identifier".to_owned(),
+        "num" => "number".to_owned(),
+        other => format!("`{other}`"),
+    }
+}
+
+fn expected_list(expected: &[String]) -> String {
+    let names: Vec<String> = expected.iter().map(|e| terminal_name(e)).collect();
+    match names.len() {
+        0 => String::new(),
+        1 => format!(", expected {}", names[0]),
+        _ => format!(", expected one of {}", names.join(", ")),
+    }
+}
+
+fn report_parse_error(
+    diags: &mut Diagnostics,
+    input: &[u8],
+    error: ParseError<usize, Token, &'static str>,
+) {
+    match error {
+        ParseError::UnrecognizedToken {
+            token: (l, _, r),
+            expected,
+        } => {
+            let found = String::from_utf8_lossy(&input[l..r]);
+            diags.error(
+                error_codes::UNEXPECTED_TOKEN,
+                Span::new(l, r),
+                &format!("Unexpected `{found}`{}.", expected_list(&expected)),
+            );
+        }
+        ParseError::UnrecognizedEof { location, expected } => {
+            diags.error(
+                error_codes::UNEXPECTED_EOF,
+                Span::new(location, location),
+                &format!("Unexpected end of file{}.", expected_list(&expected)),
+            );
+        }
+        ParseError::ExtraToken { token: (l, _, r) } => {
+            let found = String::from_utf8_lossy(&input[l..r]);
+            diags.error(
+                error_codes::EXTRA_TOKEN,
+                Span::new(l, r),
+                &format!("Unexpected `{found}` after the last function."),
+            );
+        }
+        ParseError::InvalidToken { location } => {
+            diags.error(
+                error_codes::INVALID_TOKEN,
+                Span::new(location, location),
+                "Invalid token.",
+            );
+        }
+        ParseError::User { error } => {
+            diags.error_file(error_codes::UNEXPECTED_TOKEN, error);
+        }
+    }
+}
+
+fn lex(c: &mut Compilation) -> bool {
+    let mut lex_errors = Vec::new();
+    tokenize(
+        c.source,
+        &mut lex_errors,
+        &mut c.tokens,
+        c.symbols.names_mut(),
+    );
+    c.stats.add("tokens", c.tokens.len());
+    /* Out of range literals are semantic errors, unless the program has lexical errors anyway */
+    let (range_errors, lex_errors): (Vec<_>, Vec<_>) =
+        lex_errors.into_iter().partition(|e| e.is_out_of_range());
+    if !lex_errors.is_empty() {
+        for e in lex_errors.iter().chain(range_errors.iter()) {
+            c.diags.error(e.code(), e.span(), &e.message(c.source));
+        }
+        return false;
+    }
+    c.range_errors = range_errors;
+    true
+}
+
+fn parse(c: &mut Compilation) -> bool {
+    let mut errors = Vec::new();
+    let mut program = Program::default();
+    let result = parser::ProgramParser::new().parse(
+        c.source,
+        &mut errors,
+        &mut program.exps,
+        &mut program.statements,
+        c.tokens.iter().cloned(),
+    );
+    for recovery in errors {
+        report_parse_error(&mut c.diags, c.source, recovery.error);
+    }
+    match result {
+        Ok((functions, declarations)) => {
+            program.functions = functions;
+            program.declarations = declarations;
+            c.ast = Some(program);
+        }
+        Err(e) => report_parse_error(&mut c.diags, c.source, e),
+    }
+    !c.diags.has_errors()
+}
+
+/* Binds the variables to their declarations. Succeeds even with errors, they are reported
+ * together with the other semantic errors. */
+fn resolve(c: &mut Compilation) -> bool {
+    resolve_names(
+        &mut c.diags,
+        c.ast.as_mut().unwrap(),
+        c.unit,
+        &mut c.symbols,
+    );
+    true
+}
+
+/* Succeeds even with errors, they are reported together with the warnings */
+fn semantics(c: &mut Compilation) -> bool {
+    for e in c.range_errors.iter() {
+        c.diags.error(e.code(), e.span(), &e.message(c.source));
+    }
+    c.functions = check_semantics(&mut c.diags, c.ast.as_mut().unwrap(), &c.symbols);
+    c.stats.add("functions", c.functions.len());
+    true
+}
+
+fn warnings(c: &mut Compilation) -> bool {
+    check_warnings(
+        &mut c.diags,
+        &c.options.warnings,
+        &c.functions,
+        &c.ast.as_ref().unwrap().exps,
+        c.symbols.names(),
+        c.reachable.as_deref(),
+    );
+    !c.diags.has_errors()
+}
+
+/* Looks up every function in the cache, the functions that are found skip the rest of the
+ * pipeline */
+fn load_cache(c: &mut Compilation) -> bool {
+    let cache = Cache::new(c.options.cache_dir.clone().unwrap());
+    let optimizations = c.options.optimizations();
+    let exps = &c.ast.as_ref().unwrap().exps;
+    let unit: HashMap<Symbol, &AbsFunction> = c.functions.iter().map(|f| (f.name, f)).collect();
+    c.keys = c
+        .functions
+        .iter()
+        .map(|f| function_key(f, exps, &unit, &c.symbols, &optimizations))
+        .collect();
+    c.cached = c
+        .functions
+        .iter()
+        .zip(c.keys.iter())
+        .map(|(f, key)| cache.load(*key, c.symbols.name(f.name)))
+        .collect();
+    c.stats
+        .add("functions from cache", c.cached.iter().flatten().count());
+    true
+}
+
+/* The functions that are compiled rather than taken from the cache */
+fn compiled<'c>(
+    ir: &'c mut [IRFunction],
+    cached: &'c [Option<CacheEntry>],
+) -> impl Iterator<Item = &'c mut IRFunction> {
+    ir.iter_mut()
+        .zip(cached.iter())
+        .filter(|(_, cached)| cached.is_none())
+        .map(|(f, _)| f)
+}
+
+fn ir(c: &mut Compilation) -> bool {
+    c.cached.resize_with(c.functions.len(), || None);
+    let exps = &c.ast.as_ref().unwrap().exps;
+    c.ir = c
+        .functions
+        .iter()
+        .zip(c.cached.iter())
+        .map(|(f, cached)| match cached {
+            Some(entry) => entry.translated.clone(),
+            None => function_to_ir(f, exps, c.symbols.names()),
+        })
+        .collect();
+    for f in compiled(&mut c.ir, &c.cached) {
+        c.stats.add("temps created", f.num_temps);
+        c.stats.add("IR instructions", f.instructions.len());
+    }
+    if c.options.cache_dir.is_some() {
+        c.translated = c.ir.clone();
+    }
+    true
+}
+
+fn inline(c: &mut Compilation) -> bool {
+    let callers: Vec<bool> = c.cached.iter().map(Option::is_none).collect();
+    let inlined = inline_calls(&mut c.ir, &callers);
+    c.stats.add("calls inlined", inlined);
+    true
+}
+
+fn const_fold(c: &mut Compilation) -> bool {
+    for f in compiled(&mut c.ir, &c.cached) {
+        c.stats.add("constants folded", fold_constants(f));
+    }
+    true
+}
+
+fn copy_prop(c: &mut Compilation) -> bool {
+    for f in compiled(&mut c.ir, &c.cached) {
+        c.stats.add("copies propagated", propagate_copies(f));
+    }
+    true
+}
+
+fn cse(c: &mut Compilation) -> bool {
+    for f in compiled(&mut c.ir, &c.cached) {
+        c.stats
+            .add("subexpressions reused", eliminate_common_subexpressions(f));
+    }
+    true
+}
+
+fn dce(c: &mut Compilation) -> bool {
+    for f in compiled(&mut c.ir, &c.cached) {
+        c.stats
+            .add("dead instructions removed", eliminate_dead_code(f));
+    }
+    true
+}
+
+/* The backend handles every function on its own, so liveness, coloring and codegen spread the
+ * functions over c.options.jobs threads */
+fn liveness(c: &mut Compilation) -> bool {
+    c.liveness = parallel_map(c.options.jobs, &c.ir, |i, f| match c.cached[i] {
+        Some(_) => Vec::new(),
+        None => analyze_func(&f.instructions),
+    });
+    true
+}
+
+fn coloring(c: &mut Compilation) -> bool {
+    c.colorings = parallel_map(c.options.jobs, &c.ir, |i, f| match c.cached[i] {
+        Some(_) => Vec::new(),
+        None => color_func(&f.instructions, &c.liveness[i], f.num_temps, f.num_params),
+    });
+    for coloring in c.colorings.iter() {
+        c.stats.add(
+            "spilled temps",
+            coloring.iter().filter(|c| is_spilled(**c)).count(),
+        );
+    }
+    true
+}
+
+/* Cached functions get their optimized IR and their assembly from the cache, the compiled ones
+ * are stored in it. The assembly of the functions is put together in the order of the source. */
+fn codegen(c: &mut Compilation) -> bool {
+    let cache = c.options.cache_dir.clone().map(Cache::new);
+    let compiled = parallel_map(c.options.jobs, &c.ir, |i, f| {
+        if c.cached[i].is_some() {
+            return None;
+        }
+        let dead_stores = find_dead_stores(&f.instructions, &c.liveness[i]);
+        let removed = dead_stores.iter().filter(|dead| **dead).count();
+        let assembly = translate_function(f, &c.colorings[i], &dead_stores);
+        if let Some(cache) = &cache {
+            cache.store(c.keys[i], &c.translated[i], f, &assembly);
+        }
+        Some((removed, assembly))
+    });
+    let mut functions = Vec::new();
+    for (i, result) in compiled.into_iter().enumerate() {
+        match result {
+            Some((removed, assembly)) => {
+                c.stats.add("dead stores removed", removed);
+                functions.push(assembly);
+            }
+            None => {
+                let entry = c.cached[i].take().unwrap();
+                c.ir[i] = entry.optimized;
+                functions.push(entry.assembly);
+            }
+        }
+    }
+    c.assembly = Assembly {
+        names: c.ir.iter().map(|f| f.name.clone()).collect(),
+        functions,
+    };
+    c.stats
+        .add("instructions emitted", c.assembly.instructions());
+    true
+}
+
+/* The phases of a unit, from the source text to assembly. The optimizations of the level run
+ * one after the other between the translation to IR and the liveness analysis. */
+fn pipeline<'a>(options: &Options) -> PassManager<Compilation<'a>> {
+    let mut manager = PassManager::new();
+    let front: [Pass<Compilation>; 5] = [
+        Pass {
+            name: "lex",
+            requires: vec![],
+            run: lex,
+        },
+        Pass {
+            name: "parse",
+            requires: vec!["lex"],
+            run: parse,
+        },
+        Pass {
+            name: "resolve",
+            requires: vec!["parse"],
+            run: resolve,
+        },
+        Pass {
+            name: "semantics",
+            requires: vec!["resolve"],
+            run: semantics,
+        },
+        Pass {
+            name: "warnings",
+            requires: vec!["semantics"],
+            run: warnings,
+        },
+    ];
+    for pass in front {
+        manager.register(pass);
+    }
+    let mut ir_requires = vec!["semantics", "warnings"];
+    if options.cache_dir.is_some() {
+        manager.register(Pass {
+            name: "cache",
+            requires: vec!["warnings"],
+            run: load_cache,
+        });
+        ir_requires.push("cache");
+    }
+    manager.register(Pass {
+        name: "ir",
+        requires: ir_requires,
+        run: ir,
+    });
+    let mut previous = "ir";
+    for name in options.optimizations() {
+        let run = match name {
+            "inline" => inline,
+            "const-fold" => const_fold,
+            "copy-prop" => copy_prop,
+            "cse" => cse,
+            "dce" => dce,
+            _ => unreachable!("unknown optimization {name}"),
+        };
+        manager.register(Pass {
+            name,
+            requires: vec![previous],
+            run,
+        });
+        previous = name;
+    }
+    let back: [Pass<Compilation>; 3] = [
+        Pass {
+            name: "liveness",
+            requires: vec![previous],
+            run: liveness,
+        },
+        Pass {
+            name: "coloring",
+            requires: vec!["liveness"],
+            run: coloring,
+        },
+        Pass {
+            name: "codegen",
+            requires: vec!["ir", "liveness", "coloring"],
+            run: codegen,
+        },
+    ];
+    for pass in back {
+        manager.register(pass);
+    }
+    manager
+}
+
+/* Runs every phase up to the generation of assembly for a program of a single unit. On failure all
+ * errors and warnings are returned sorted by location. Never exits the process. */
+pub fn compile(source: &[u8], options: &Options) -> Result<Artifact, Vec<Diagnostic>> {
+    let unit = SourceFile::new(String::new(), source);
+    match compile_program(&[unit], options, true) {
+        Ok(mut artifacts) => Ok(artifacts.pop().unwrap()),
+        Err(mut failure) => {
+            let mut diagnostics = failure.units.pop().unwrap();
+            diagnostics.extend(failure.link);
+            Err(diagnostics)
+        }
+    }
+}
+
+/* Compiles the units of a program to one artifact each. Calls between units are checked against the
+ * functions every unit defines or declares. link tells whether the units make up the whole
+ * program, which then needs a main function and a definition of every declared function;
+ * otherwise they are compiled to be linked with other code later. Every unit is taken through the
+ * front end before the names are checked across units, and through the semantic checks before
+ * unreachable functions are searched for across units. */
+pub fn compile_program<'a>(
+    units: &[SourceFile<'a>],
+    options: &Options,
+    link: bool,
+) -> Result<Vec<Artifact>, Failure> {
+    let mut compilations: Vec<Compilation> = units
+        .iter()
+        .enumerate()
+        .map(|(i, unit)| Compilation::new(unit.input, i, options))
+        .collect();
+    let mut managers: Vec<_> = units.iter().map(|_| pipeline(options)).collect();
+    let mut link_diags = Diagnostics::new();
+    /* The units share the names of their symbols, so every unit is lexed with the table the
+     * units before it left */
+    let mut symbols = SymbolTable::new();
+    let mut ok = true;
+    for (c, manager) in compilations.iter_mut().zip(managers.iter_mut()) {
+        c.symbols = mem::take(&mut symbols);
+        ok &= manager.run(c, "parse");
+        symbols = mem::take(&mut c.symbols);
+    }
+    let mut run_all = |compilations: &mut [Compilation<'a>], target| {
+        let mut ok = true;
+        for (c, manager) in compilations.iter_mut().zip(managers.iter_mut()) {
+            ok &= manager.run(c, target);
+        }
+        ok
+    };
+
+    if ok {
+        let mut diags = Vec::new();
+        let mut programs = Vec::new();
+        for (c, unit) in compilations.iter_mut().zip(units.iter()) {
+            diags.push(&mut c.diags);
+            programs.push((unit.path.as_str(), c.ast.as_ref().unwrap()));
+        }
+        check_function_names(&mut diags, &programs, &mut symbols);
+        if link {
+            check_definitions(&mut diags, &symbols);
+            check_main(&mut link_diags, &symbols);
+        }
+        for c in compilations.iter_mut() {
+            c.symbols = symbols.clone();
+        }
+        run_all(&mut compilations, "semantics");
+        if options.warnings.is_enabled(Warning::UnreachableFunction) {
+            let functions: Vec<(&AbsFunction, &Arena<Exp>)> = compilations
+                .iter()
+                .flat_map(|c| {
+                    let exps = &c.ast.as_ref().unwrap().exps;
+                    c.functions.iter().map(move |f| (f, exps))
+                })
+                .collect();
+            let reachable = reachable_functions(&functions);
+            for c in compilations.iter_mut() {
+                c.reachable = reachable.clone();
+            }
+        }
+        ok = run_all(&mut compilations, "warnings") && !link_diags.has_errors();
+    }
+    if !ok {
+        return Err(Failure {
+            units: compilations
+                .into_iter()
+                .map(|c| c.diags.into_sorted())
+                .collect(),
+            link: link_diags.into_sorted(),
+        });
+    }
+    run_all(&mut compilations, "codegen");
+    Ok(compilations
+        .into_iter()
+        .zip(managers)
+        .map(|(c, manager)| Artifact {
+            tokens: c.tokens,
+            ast: c.ast.unwrap(),
+            functions: c.functions,
+            symbols: c.symbols,
+            ir: c.ir,
+            assembly: c.assembly,
+            warnings: c.diags.into_sorted(),
+            timings: manager.into_timings(),
+            stats: c.stats,
+        })
+        .collect())
+}
+
+/* The exit code for a failed compilation, 42 for lexical and syntax errors, 1 for errors of the
+ * toolchain and 7 otherwise */
+pub fn exit_code(diagnostics: &[Diagnostic]) -> i32 {
+    let any =
+        |is_code: fn(&str) -> bool| diagnostics.iter().any(|d| d.is_error() && is_code(d.code));
+    if any(error_codes::is_syntax_error) {
+        42
+    } else if any(error_codes::is_toolchain_error) {
+        1
+    } else {
+        7
+    }
+}
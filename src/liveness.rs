@@ -1,4 +1,6 @@
-use crate::ir::{IRCmd, IRExp, Temp};
+use std::collections::HashMap;
+
+use crate::ir::{IRCmd, IRExp};
 
 #[derive(Debug)]
 enum Rules {
@@ -9,7 +11,7 @@ enum Rules {
 }
 
 /* Creates a vector of currently live temps for every line. Repeats until saturated. */
-pub fn analyze_func(cmds: &mut Vec<IRCmd>) -> Vec<Vec<usize>> {
+pub fn analyze_func(cmds: &[IRCmd]) -> Vec<Vec<usize>> {
     let rules = break_func_into_rules(cmds);
     let mut needed_temps = Vec::new();
     for _ in 0..rules.len() {
@@ -20,35 +22,58 @@ pub fn analyze_func(cmds: &mut Vec<IRCmd>) -> Vec<Vec<usize>> {
             break;
         }
     }
-    let len = cmds.len();
-    for cmd in cmds.iter_mut() {
-        if let IRCmd::Load(IRExp::Temp(t), _) = cmd {
-            if !needed_temps.iter().any(|x| x.contains(&t.name)) {
-                *cmd = IRCmd::Label(len);
+    needed_temps
+}
+
+/* The temps live after every line, those live at the start of any line that may follow it */
+pub fn live_out(cmds: &[IRCmd], live_temps: &[Vec<usize>]) -> Vec<Vec<usize>> {
+    break_func_into_rules(cmds)
+        .iter()
+        .map(|line| {
+            let mut out: Vec<usize> = Vec::new();
+            for rule in line.iter() {
+                if let Rules::Succ(l) = rule
+                    && *l < live_temps.len()
+                {
+                    for temp in live_temps[*l].iter() {
+                        if !out.contains(temp) {
+                            out.push(*temp);
+                        }
+                    }
+                }
             }
-        }
-    }
-    cmds.retain(|r| {
-        if let IRCmd::Label(l) = r {
-            if *l == len {
-                return false;
+            out
+        })
+        .collect()
+}
+
+/* Finds the assignments to temps that are not live after them and have no effect of their own.
+ * Such an assignment may not even get a register of its own, it shares it with a live temp, so it
+ * must not be executed. Returns for every command whether it is such a dead store. */
+pub fn find_dead_stores(cmds: &[IRCmd], live_temps: &[Vec<usize>]) -> Vec<bool> {
+    let live_out = live_out(cmds, live_temps);
+    cmds.iter()
+        .enumerate()
+        .map(|(i, cmd)| match cmd {
+            IRCmd::Load(IRExp::Temp(t), exp) => {
+                !live_out[i].contains(&t.name) && !exp.has_side_effects()
             }
-        };
-        true
-    });
-    needed_temps
+            _ => false,
+        })
+        .collect()
 }
 
+#[allow(dead_code)]
 fn collect_live_temps(rules: &[Vec<Rules>], live_temps: &mut [Vec<usize>]) -> bool {
     let mut saturated = true;
     for (index, line) in rules.iter().rev().enumerate() {
         let i = live_temps.len() - index - 1;
         for rule in line.iter() {
-            if let Rules::Use(temp) = rule {
-                if !live_temps[i].contains(temp) {
-                    saturated = false;
-                    live_temps[i].push(*temp);
-                }
+            if let Rules::Use(temp) = rule
+                && !live_temps[i].contains(temp)
+            {
+                saturated = false;
+                live_temps[i].push(*temp);
             }
         }
         for l in line.iter().filter_map(|r| {
@@ -62,10 +87,10 @@ fn collect_live_temps(rules: &[Vec<Rules>], live_temps: &mut [Vec<usize>]) -> bo
         }) {
             for temp in live_temps[l].clone().iter() {
                 if !line.iter().any(|r| {
-                    if let Rules::Def(t) = r {
-                        if *t == *temp {
-                            return true;
-                        }
+                    if let Rules::Def(t) = r
+                        && *t == *temp
+                    {
+                        return true;
                     }
                     false
                 }) && !live_temps[i].contains(temp)
@@ -84,11 +109,11 @@ fn collect_needed_temps(rules: &[Vec<Rules>], needed_temps: &mut [Vec<usize>]) -
     for (index, line) in rules.iter().rev().enumerate() {
         let i = needed_temps.len() - index - 1;
         for rule in line.iter() {
-            if let Rules::Nec(temp) = rule {
-                if !needed_temps[i].contains(temp) {
-                    saturated = false;
-                    needed_temps[i].push(*temp);
-                }
+            if let Rules::Nec(temp) = rule
+                && !needed_temps[i].contains(temp)
+            {
+                saturated = false;
+                needed_temps[i].push(*temp);
             }
         }
         for l in line.iter().filter_map(|r| {
@@ -102,10 +127,10 @@ fn collect_needed_temps(rules: &[Vec<Rules>], needed_temps: &mut [Vec<usize>]) -
         }) {
             for temp in needed_temps[l].clone().iter() {
                 if !line.iter().any(|r| {
-                    if let Rules::Def(t) = r {
-                        if *t == *temp {
-                            return true;
-                        }
+                    if let Rules::Def(t) = r
+                        && *t == *temp
+                    {
+                        return true;
                     }
                     false
                 }) {
@@ -131,6 +156,15 @@ fn collect_needed_temps(rules: &[Vec<Rules>], needed_temps: &mut [Vec<usize>]) -
 }
 
 fn break_func_into_rules(cmds: &[IRCmd]) -> Vec<Vec<Rules>> {
+    /* The line of every label */
+    let labels: HashMap<usize, usize> = cmds
+        .iter()
+        .enumerate()
+        .filter_map(|(i, cmd)| match cmd {
+            IRCmd::Label(label) => Some((*label, i)),
+            _ => None,
+        })
+        .collect();
     let mut rules = Vec::new();
     for (i, c) in cmds.iter().enumerate() {
         let mut rules_line = Vec::new();
@@ -138,8 +172,12 @@ fn break_func_into_rules(cmds: &[IRCmd]) -> Vec<Vec<Rules>> {
             IRCmd::Load(IRExp::Temp(temp), exp) => {
                 rules_line.push(Rules::Def(temp.name));
                 let temps = get_temps(exp);
-                get_exp_with_effect(temp, exp, &mut rules_line);
                 temps.iter().for_each(|t| rules_line.push(Rules::Use(*t)));
+                /* The operands of a division or a call are needed for its effect even if the
+                 * result is not */
+                if exp.has_side_effects() {
+                    temps.iter().for_each(|t| rules_line.push(Rules::Nec(*t)));
+                }
                 rules_line.push(Rules::Succ(i + 1));
             }
             IRCmd::Load(_, _) => (),
@@ -149,32 +187,12 @@ fn break_func_into_rules(cmds: &[IRCmd]) -> Vec<Vec<Rules>> {
                     rules_line.push(Rules::Use(*t));
                     rules_line.push(Rules::Nec(*t));
                 });
-                let line_i = cmds
-                    .iter()
-                    .position(|x| {
-                        if let IRCmd::Label(line) = x {
-                            if line == l {
-                                return true;
-                            }
-                        }
-                        false
-                    })
-                    .unwrap();
+                let line_i = labels[l];
                 rules_line.push(Rules::Succ(line_i + 1));
                 rules_line.push(Rules::Succ(i + 1));
             }
             IRCmd::Jump(l) => {
-                let line_i = cmds
-                    .iter()
-                    .position(|x| {
-                        if let IRCmd::Label(line) = x {
-                            if line == l {
-                                return true;
-                            }
-                        }
-                        false
-                    })
-                    .unwrap();
+                let line_i = labels[l];
                 rules_line.push(Rules::Succ(line_i + 1));
             }
             IRCmd::Label(_) => rules_line.push(Rules::Succ(i + 1)),
@@ -213,49 +231,6 @@ fn break_func_into_rules(cmds: &[IRCmd]) -> Vec<Vec<Rules>> {
     rules
 }
 
-fn get_exp_with_effect(temp: &Temp, exp: &IRExp, rules: &mut Vec<Rules>) {
-    match exp {
-        IRExp::Temp(_) => (),
-        IRExp::ConstInt(_) => (),
-        IRExp::ConstBool(_) => (),
-        IRExp::Neg(irexp) | IRExp::NotBool(irexp) | IRExp::NotInt(irexp) => {
-            get_exp_with_effect(temp, irexp, rules)
-        }
-
-        IRExp::Exp(b) => {
-            let ar = &**b;
-            match ar.1 {
-                crate::ir::Op::Div | crate::ir::Op::Mod => {
-                    rules.push(Rules::Nec(temp.name));
-                    if let IRExp::Temp(t) = &ar.0 {
-                        rules.push(Rules::Nec(t.name));
-                    }
-                    if let IRExp::Temp(t) = &ar.2 {
-                        rules.push(Rules::Nec(t.name));
-                    }
-                }
-                _ => (),
-            }
-        }
-        IRExp::Call(call) => {
-            rules.push(Rules::Nec(temp.name));
-            match &**call {
-                crate::ir::Call::Print(IRExp::Temp(t)) => {
-                    rules.push(Rules::Nec(t.name));
-                }
-                crate::ir::Call::Func(_, irexps) => {
-                    irexps.iter().for_each(|e| {
-                        if let IRExp::Temp(t) = e {
-                            rules.push(Rules::Nec(t.name));
-                        }
-                    });
-                }
-                _ => (),
-            }
-        }
-    }
-}
-
 /* Collects all the temps in an expression */
 fn get_temps(exp: &IRExp) -> Vec<usize> {
     match exp {
@@ -1,95 +1,204 @@
-use std::{collections::HashMap, fs::File, io::Read, iter, process::exit};
-
-use ast::Program;
-//use elaboration::translate_statement;
-//use elaboration::translate_statement;
-//use evaluation::execute;
-use lalrpop_util::lalrpop_mod;
-//use semantics::{decl_check, return_check};
-use tokenizer::{Token, tokenize};
-
-use crate::{
-    code_gen::create_binary, coloring::color_func, ir::translate_to_ir, liveness::analyze_func,
-    semantics::check_semantics,
+use std::{
+    fs::File,
+    io::{self, BufWriter, Read, Write},
+    path::Path,
+    process::exit,
 };
 
-/*use crate::{
-    code_gen::create_binary,
-    ir::{IRCmd, translate_to_ir},
-    semantics::{break_coninue_check, type_check},
-};*/
+use cli::{Emit, parse_args, unit_name};
+use compdesign::{
+    code_gen::{BuildError, Temps, create_binary, create_object},
+    compile_program,
+    diagnostics::{Diagnostics, ErrorFormat, SourceFile, emit},
+    exit_code,
+    passes::{Stats, TrackingAllocator, format_timings},
+};
 
-lalrpop_mod!(
-    #[allow(clippy::ptr_arg)]
-    #[rustfmt::skip]
-    parser
-);
+mod cli;
 
-pub mod ast;
-pub mod code_gen;
-pub mod coloring;
-pub mod elaboration;
-pub mod instruction_selection;
-pub mod ir;
-pub mod liveness;
-pub mod semantics;
-//pub mod ssa;
-pub mod tokenizer;
+/* Counts allocations for --time-passes */
+#[global_allocator]
+static ALLOCATOR: TrackingAllocator = TrackingAllocator;
 
 fn main() {
-    let mut tokens = Vec::new();
-    let mut args = std::env::args_os().skip(1);
-    let path = args.next();
-    let mut file;
+    let args = parse_args(std::env::args_os().skip(1));
+    let inputs: Vec<(String, Vec<u8>)> = args
+        .inputs
+        .iter()
+        .map(|i| read_input(i.as_deref()))
+        .collect();
+    let units: Vec<SourceFile> = inputs
+        .iter()
+        .map(|(path, input)| SourceFile::new(path.clone(), input))
+        .collect();
+    let link = args.is_whole_program();
+    let artifacts = match compile_program(&units, &args.options, link) {
+        Ok(artifacts) => artifacts,
+        Err(failure) => {
+            for (diagnostics, unit) in failure.units.iter().zip(units.iter()) {
+                emit(diagnostics, args.error_format, unit);
+            }
+            /* Errors of the whole program belong to its only input or else to the executable */
+            let program = match &units[..] {
+                [unit] => SourceFile::new(unit.path.clone(), unit.input),
+                _ => {
+                    let output = args.output_path(None).unwrap();
+                    SourceFile::new(output.to_string_lossy().into_owned(), &[])
+                }
+            };
+            emit(&failure.link, args.error_format, &program);
+            let all: Vec<_> = failure
+                .units
+                .into_iter()
+                .flatten()
+                .chain(failure.link)
+                .collect();
+            exit(exit_code(&all));
+        }
+    };
+    let mut stats = Stats::default();
+    for (artifact, unit) in artifacts.iter().zip(units.iter()) {
+        emit(&artifact.warnings, args.error_format, unit);
+        /* Reports go to stderr so that they do not mix with dumps on stdout */
+        if args.time_passes {
+            if units.len() > 1 {
+                eprintln!("{}:", unit.path);
+            }
+            eprint!("{}", format_timings(&artifact.timings));
+        }
+        stats.merge(&artifact.stats);
+    }
+    if args.stats {
+        eprint!("{stats}");
+    }
+
+    let mut temps = Temps::new(args.save_temps.as_deref());
+    let mut dump = String::new();
+    for (artifact, input) in artifacts.iter().zip(args.inputs.iter()) {
+        let output = args.output_path(input.as_deref());
+        let names = artifact.symbols.names();
+        match args.emit {
+            Emit::Tokens => dump.extend(
+                artifact
+                    .tokens
+                    .iter()
+                    .map(|(start, token, end)| format!("{start}..{end} {}\n", token.show(names))),
+            ),
+            Emit::Ast => dump.push_str(&artifact.ast.show(names).to_string()),
+            Emit::Abs => {
+                let exps = &artifact.ast.exps;
+                let functions: Vec<_> = artifact
+                    .functions
+                    .iter()
+                    .map(|f| f.show(exps, names))
+                    .collect();
+                dump.push_str(&join(&functions))
+            }
+            Emit::Ir => dump.push_str(&join(&artifact.ir)),
+            Emit::Asm => write_output(output.as_deref(), |out| artifact.assembly.write_to(out)),
+            Emit::Obj => {
+                let output = output.unwrap();
+                let built = create_object(
+                    &args.toolchain,
+                    &unit_name(input.as_deref()),
+                    &artifact.assembly,
+                    &output,
+                    &mut temps,
+                );
+                check_build(built, args.error_format, &output);
+            }
+            Emit::Exe => (),
+        }
+    }
+    match args.emit {
+        Emit::Tokens | Emit::Ast | Emit::Abs | Emit::Ir => {
+            write_output(args.output_path(None).as_deref(), |out| {
+                out.write_all(dump.as_bytes())
+            })
+        }
+        Emit::Exe => {
+            let names: Vec<String> = args
+                .inputs
+                .iter()
+                .map(|i| unit_name(i.as_deref()))
+                .collect();
+            let units: Vec<(&str, _)> = names
+                .iter()
+                .map(String::as_str)
+                .zip(artifacts.iter().map(|a| &a.assembly))
+                .collect();
+            let output = args.output_path(None).unwrap();
+            let built = create_binary(&args.toolchain, &units, &output, &mut temps);
+            /* Remove the temporary files before exiting */
+            drop(temps);
+            check_build(built, args.error_format, &output);
+        }
+        Emit::Asm | Emit::Obj => (),
+    }
+}
+
+/* Reads an input file, or stdin for None. Returns the path to report diagnostics with. */
+fn read_input(path: Option<&Path>) -> (String, Vec<u8>) {
+    let mut input = Vec::new();
     match path {
-        Some(input_path) => match File::open(input_path) {
-            Ok(f) => file = f,
-            Err(_) => {
+        Some(path) => {
+            let Ok(mut file) = File::open(path) else {
                 println!("Error: Path cannot be found.");
                 exit(42);
+            };
+            if file.read_to_end(&mut input).is_err() {
+                println!("Unable to read file!");
+                exit(42);
             }
-        },
-        None => {
-            println!("Error: Path cannot be found.");
-            exit(42);
+            (path.to_string_lossy().into_owned(), input)
         }
-    }
-    let mut input = Vec::new();
-    if file.read_to_end(&mut input).is_err() {
-        println!("Unable to read file!");
-        exit(42);
-    };
-    let mut semantic_error = false;
-    if let Err(e) = tokenize(&input, &mut semantic_error, &mut tokens) {
-        if e == 42 {
-            println!("Error: Your program contains unknown tokens.");
-            exit(e)
+        None => {
+            if io::stdin().read_to_end(&mut input).is_err() {
+                println!("Unable to read stdin!");
+                exit(42);
+            }
+            ("<stdin>".to_owned(), input)
         }
     }
-    //println!("{:#?}", &tokens);
-    let lexer = tokens.into_iter();
+}
 
-    let ast: Program<'_>;
-    if let Ok(result) = parser::ProgramParser::new().parse(&input, lexer) {
-        ast = result;
-        //println!("{:#?}", ast);
-    } else {
-        println!("Error: Your program cannot be parsed.");
-        exit(42)
+/* Writes to the output file, or to stdout for None */
+fn write_output(output: Option<&Path>, write: impl FnOnce(&mut dyn Write) -> io::Result<()>) {
+    let written = match output {
+        Some(output) => File::create(output).and_then(|file| {
+            let mut out = BufWriter::new(file);
+            write(&mut out)?;
+            out.flush()
+        }),
+        None => {
+            let mut out = io::stdout().lock();
+            write(&mut out).and_then(|_| out.flush())
+        }
+    };
+    if written.is_err() {
+        println!("Error: Unable to write the output.");
+        exit(42);
     }
+}
 
-    /*Semantic analysis starts here*/
-    if semantic_error {
-        println!("Error: Invalid integer");
-        exit(7)
+/* Exits if the toolchain could not build an object file or the executable. The error belongs to
+ * the output, the toolchain has reported its own errors already. */
+fn check_build(built: Result<(), BuildError>, format: ErrorFormat, output: &Path) {
+    if let Err(error) = built {
+        let mut diags = Diagnostics::new();
+        error.report(&mut diags);
+        let diags = diags.into_sorted();
+        let output = SourceFile::new(output.to_string_lossy().into_owned(), &[]);
+        emit(&diags, format, &output);
+        exit(exit_code(&diags));
     }
-    let funcs = check_semantics(ast);
-    //println!("{:#?}", funcs);
-
-    let mut program_in_ir = translate_to_ir(funcs);
-    //println!("{:#?}", program_in_ir);
+}
 
-    //println!("{:#?}", program_in_ir);
-    let string = args.next().unwrap().to_os_string();
-    create_binary(&mut program_in_ir, string);
+/* Separates the dumps of several functions by an empty line */
+fn join(items: &[impl std::fmt::Display]) -> String {
+    items
+        .iter()
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join("\n")
 }
@@ -0,0 +1,244 @@
+use std::collections::HashMap;
+
+use crate::{
+    arena::Arena,
+    ast::{
+        Control, Decl, Exp, ExpId, Function, Lvalue, Param, ParamList, Program, Simp, Statement,
+        StmtId, Type, Var,
+    },
+    diagnostics::{Diagnostics, Span, closest_name},
+    error_codes,
+    symbols::{DeclId, Declaration, Symbol, SymbolKind, SymbolTable},
+};
+
+/* Binds every variable of the program to its declaration. Parameters and variables are declared
+ * in symbols, so every declaration gets an id of its own even if it reuses a name. Reports uses of
+ * names that are not declared and declarations that shadow a variable or parameter in scope.
+ * unit is the index of the program in the units of the whole program. */
+pub fn resolve_names(
+    diags: &mut Diagnostics,
+    program: &mut Program,
+    unit: usize,
+    symbols: &mut SymbolTable,
+) {
+    let Program {
+        functions,
+        exps,
+        statements,
+        ..
+    } = program;
+    for f in functions.iter_mut() {
+        let Function::Function(_, _, ParamList::ParamList(params), block, _) = f;
+        let mut resolver = Resolver {
+            diags,
+            symbols,
+            unit,
+            exps,
+            scope: Vec::new(),
+            visible: HashMap::new(),
+        };
+        /* Parameters with the same name are reported by check_function_names */
+        for Param::Param(typ, var, span) in params.iter_mut() {
+            resolver.bind(var, SymbolKind::Parameter, *typ, *span);
+        }
+        resolver.block(statements, block.statements());
+    }
+}
+
+/* Statements are passed along separately from the expressions they contain, so that a simple
+ * statement can be resolved while the arena of expressions is borrowed */
+struct Resolver<'r> {
+    diags: &'r mut Diagnostics,
+    symbols: &'r mut SymbolTable,
+    unit: usize,
+    exps: &'r mut Arena<Exp>,
+    /* The declarations in scope, the innermost last */
+    scope: Vec<DeclId>,
+    /* The declarations in scope of every name, the innermost last */
+    visible: HashMap<Symbol, Vec<DeclId>>,
+}
+impl Resolver<'_> {
+    fn lookup(&self, name: Symbol) -> Option<DeclId> {
+        self.visible.get(&name).and_then(|ids| ids.last()).copied()
+    }
+
+    /* Ends the scope of the declarations after the first depth ones */
+    fn leave(&mut self, depth: usize) {
+        for id in self.scope.drain(depth..) {
+            let name = self.symbols.get(id).name;
+            self.visible.get_mut(&name).unwrap().pop();
+        }
+    }
+
+    fn bind(&mut self, var: &mut Var, kind: SymbolKind, typ: Type, span: Span) {
+        let id = self.symbols.declare(Declaration {
+            name: var.name,
+            kind,
+            typ,
+            unit: self.unit,
+            span,
+        });
+        var.id = Some(id);
+        self.scope.push(id);
+        self.visible.entry(var.name).or_default().push(id);
+    }
+
+    fn declare(&mut self, var: &mut Var, typ: Type, span: Span) {
+        if let Some(previous) = self.lookup(var.name) {
+            let previous = self.symbols.get(previous).span;
+            self.diags
+                .error(
+                    error_codes::REDECLARED_VARIABLE,
+                    span,
+                    &format!(
+                        "Variable \"{}\" is declared more than once.",
+                        self.symbols.name(var.name)
+                    ),
+                )
+                .label(previous, "first declared here");
+        }
+        self.bind(var, SymbolKind::Variable, typ, span);
+    }
+
+    /* Binds a use of a variable, or reports it and suggests a variable in scope with a similar
+     * name */
+    fn resolve(&mut self, var: &mut Var, span: Span) {
+        var.id = self.lookup(var.name);
+        if var.id.is_some() {
+            return;
+        }
+        let diag = self.diags.error(
+            error_codes::UNDECLARED_VARIABLE,
+            span,
+            &format!(
+                "Variable \"{}\" is not declared.",
+                self.symbols.name(var.name)
+            ),
+        );
+        let names = self.scope.iter().map(|id| self.symbols.get(*id).name);
+        if let Some(similar) = closest_name(var.name, names, self.symbols.names()) {
+            diag.suggest(
+                span,
+                self.symbols.name(similar),
+                "a variable with a similar name exists",
+            );
+        }
+    }
+
+    /* Declarations end with the block they are in */
+    fn block(&mut self, statements: &mut Arena<Statement>, block: &[StmtId]) {
+        let depth = self.scope.len();
+        for s in block {
+            self.statement(statements, *s);
+        }
+        self.leave(depth);
+    }
+
+    /* A statement that is the body of an if, while or for has a scope of its own even if it is
+     * not a block */
+    fn nested(&mut self, statements: &mut Arena<Statement>, s: StmtId) {
+        let depth = self.scope.len();
+        self.statement(statements, s);
+        self.leave(depth);
+    }
+
+    fn statement(&mut self, statements: &mut Arena<Statement>, id: StmtId) {
+        match &mut statements[id] {
+            Statement::Simp(simp, _) => self.simp(simp),
+            Statement::Control(control, _) => match control {
+                Control::If(exp, then, otherwise) => {
+                    let (exp, then, otherwise) = (*exp, *then, *otherwise);
+                    self.exp(exp);
+                    self.nested(statements, then);
+                    if let Some(otherwise) = otherwise {
+                        self.nested(statements, otherwise);
+                    }
+                }
+                Control::While(exp, body) => {
+                    let (exp, body) = (*exp, *body);
+                    self.exp(exp);
+                    self.nested(statements, body);
+                }
+                /* A variable declared by the initializer is in scope until the end of the loop */
+                Control::For((init, cond, _), body) => {
+                    let (cond, body) = (*cond, *body);
+                    let depth = self.scope.len();
+                    if let Some(init) = init {
+                        self.simp(init);
+                    }
+                    self.exp(cond);
+                    self.nested(statements, body);
+                    if let Statement::Control(Control::For((_, _, Some(step)), _), _) =
+                        &mut statements[id]
+                    {
+                        self.simp(step);
+                    }
+                    self.leave(depth);
+                }
+                Control::Return(exp) => {
+                    let exp = *exp;
+                    self.exp(exp);
+                }
+                Control::Continue | Control::Break => (),
+            },
+            Statement::Block(block, _) => {
+                let block = block.statements().to_vec();
+                self.block(statements, &block);
+            }
+            Statement::Error(_) => (),
+        }
+    }
+
+    fn simp(&mut self, simp: &mut Simp) {
+        match simp {
+            Simp::Simp((Lvalue::Ident(var, span), _, exp)) => {
+                self.exp(*exp);
+                self.resolve(var, *span);
+            }
+            Simp::Decl(Decl::Declare(typ, var, span)) => self.declare(var, *typ, *span),
+            /* The variable is in scope in its initializer, using it there is reported as a use
+             * before assignment */
+            Simp::Decl(Decl::Assign((typ, var, exp), span)) => {
+                self.declare(var, *typ, *span);
+                self.exp(*exp);
+            }
+            Simp::Call(call) => self.args(call.get_args()),
+        }
+    }
+
+    fn exp(&mut self, id: ExpId) {
+        match &mut self.exps[id] {
+            Exp::True(_) | Exp::False(_) | Exp::Intconst(..) => (),
+            Exp::Ident(var, span) => {
+                let (mut resolved, span) = (*var, *span);
+                self.resolve(&mut resolved, span);
+                self.exps[id] = Exp::Ident(resolved, span);
+            }
+            Exp::Arithmetic(e1, _, e2, _) => {
+                let (e1, e2) = (*e1, *e2);
+                self.exp(e1);
+                self.exp(e2);
+            }
+            Exp::Negative(e, _) | Exp::Not(e, _) | Exp::BitNot(e, _) => {
+                let e = *e;
+                self.exp(e);
+            }
+            Exp::Ternary(e1, e2, e3, _) => {
+                let (e1, e2, e3) = (*e1, *e2, *e3);
+                self.exp(e1);
+                self.exp(e2);
+                self.exp(e3);
+            }
+            Exp::Call(call, _) => {
+                let args = call.get_args().to_vec();
+                self.args(&args);
+            }
+        }
+    }
+
+    fn args(&mut self, args: &[ExpId]) {
+        for exp in args {
+            self.exp(*exp);
+        }
+    }
+}
@@ -0,0 +1,493 @@
+use std::{collections::HashMap, mem};
+
+use crate::{
+    ir::{Call, IRCmd, IRExp, IRFunction, Op, Temp, symbol},
+    liveness::{analyze_func, find_dead_stores},
+};
+
+/* Every optimization, each can be disabled with -fno-<name> */
+pub const OPTIMIZATIONS: [&str; 5] = ["inline", "const-fold", "copy-prop", "cse", "dce"];
+
+/* The optimizations of a level in the order they run:
+ * -O0 translates the program as written.
+ * -O1 folds constants, propagates copies and removes dead code, all inside basic blocks.
+ * -O2 additionally inlines small leaf functions first and reuses common subexpressions. */
+pub fn optimizations_for_level(level: u8) -> &'static [&'static str] {
+    match level {
+        0 => &[],
+        1 => &["const-fold", "copy-prop", "dce"],
+        _ => &["inline", "const-fold", "copy-prop", "cse", "dce"],
+    }
+}
+
+/* Functions with at most this many instructions are inlined */
+const INLINE_LIMIT: usize = 32;
+
+/* The expressions an instruction reads, the destination of a load is not one of them */
+fn operands(cmd: &IRCmd) -> Vec<&IRExp> {
+    match cmd {
+        IRCmd::Load(_, exp) | IRCmd::JumpIf(exp, _) | IRCmd::Return(exp) => vec![exp],
+        IRCmd::Call(Call::Print(exp)) => vec![exp],
+        IRCmd::Call(Call::Func(_, args)) => args.iter().collect(),
+        IRCmd::Call(Call::Read | Call::Flush) | IRCmd::Jump(_) | IRCmd::Label(_) => vec![],
+    }
+}
+
+fn operands_mut(cmd: &mut IRCmd) -> Vec<&mut IRExp> {
+    match cmd {
+        IRCmd::Load(_, exp) | IRCmd::JumpIf(exp, _) | IRCmd::Return(exp) => vec![exp],
+        IRCmd::Call(Call::Print(exp)) => vec![exp],
+        IRCmd::Call(Call::Func(_, args)) => args.iter_mut().collect(),
+        IRCmd::Call(Call::Read | Call::Flush) | IRCmd::Jump(_) | IRCmd::Label(_) => vec![],
+    }
+}
+
+/* The temp an instruction assigns */
+fn defined(cmd: &IRCmd) -> Option<usize> {
+    match cmd {
+        IRCmd::Load(IRExp::Temp(t), _) => Some(t.name),
+        _ => None,
+    }
+}
+
+fn visit_temps(exp: &IRExp, f: &mut impl FnMut(&Temp)) {
+    match exp {
+        IRExp::Temp(t) => f(t),
+        IRExp::ConstInt(_) | IRExp::ConstBool(_) => (),
+        IRExp::Neg(e) | IRExp::NotBool(e) | IRExp::NotInt(e) => visit_temps(e, f),
+        IRExp::Exp(b) => {
+            visit_temps(&b.0, f);
+            visit_temps(&b.2, f);
+        }
+        IRExp::Call(call) => match &**call {
+            Call::Print(e) => visit_temps(e, f),
+            Call::Func(_, args) => args.iter().for_each(|e| visit_temps(e, f)),
+            Call::Read | Call::Flush => (),
+        },
+    }
+}
+
+fn mentions(exp: &IRExp, temp: usize) -> bool {
+    let mut found = false;
+    visit_temps(exp, &mut |t| found |= t.name == temp);
+    found
+}
+
+/* Replaces every temp for which replacement returns an expression. Returns the number of
+ * replaced temps. */
+fn replace_temps(exp: &mut IRExp, replacement: &impl Fn(&Temp) -> Option<IRExp>) -> usize {
+    match exp {
+        IRExp::Temp(t) => match replacement(t) {
+            Some(new) => {
+                *exp = new;
+                1
+            }
+            None => 0,
+        },
+        IRExp::ConstInt(_) | IRExp::ConstBool(_) => 0,
+        IRExp::Neg(e) | IRExp::NotBool(e) | IRExp::NotInt(e) => replace_temps(e, replacement),
+        IRExp::Exp(b) => {
+            replace_temps(&mut b.0, replacement) + replace_temps(&mut b.2, replacement)
+        }
+        IRExp::Call(call) => match &mut **call {
+            Call::Print(e) => replace_temps(e, replacement),
+            Call::Func(_, args) => args.iter_mut().map(|e| replace_temps(e, replacement)).sum(),
+            Call::Read | Call::Flush => 0,
+        },
+    }
+}
+
+fn is_operation(exp: &IRExp) -> bool {
+    matches!(
+        exp,
+        IRExp::Neg(_) | IRExp::NotBool(_) | IRExp::NotInt(_) | IRExp::Exp(_)
+    )
+}
+
+fn is_constant(exp: &IRExp) -> bool {
+    matches!(exp, IRExp::ConstInt(_) | IRExp::ConstBool(_))
+}
+
+/* Evaluates an operation on two constants. Divisions that trap are left to the runtime. */
+fn evaluate(left: &IRExp, op: &Op, right: &IRExp) -> Option<IRExp> {
+    match (left, right) {
+        (IRExp::ConstInt(a), IRExp::ConstInt(b)) => {
+            let (a, b) = (*a, *b);
+            Some(match op {
+                Op::Plus => IRExp::ConstInt(a.wrapping_add(b)),
+                Op::Minus => IRExp::ConstInt(a.wrapping_sub(b)),
+                Op::Mult => IRExp::ConstInt(a.wrapping_mul(b)),
+                Op::Div | Op::Mod if b == 0 || (a == i32::MIN && b == -1) => return None,
+                Op::Div => IRExp::ConstInt(a / b),
+                Op::Mod => IRExp::ConstInt(a % b),
+                Op::LessThan => IRExp::ConstBool(a < b),
+                Op::LessEqual => IRExp::ConstBool(a <= b),
+                Op::GreaterThan => IRExp::ConstBool(a > b),
+                Op::GreaterEqual => IRExp::ConstBool(a >= b),
+                Op::Equals => IRExp::ConstBool(a == b),
+                Op::NotEqual => IRExp::ConstBool(a != b),
+                Op::BitAnd => IRExp::ConstInt(a & b),
+                Op::BitXor => IRExp::ConstInt(a ^ b),
+                Op::BitOr => IRExp::ConstInt(a | b),
+                /* Like sal and sar, only the lowest five bits of the shift count are used */
+                Op::LShift => IRExp::ConstInt(a.wrapping_shl(b as u32)),
+                Op::RShift => IRExp::ConstInt(a.wrapping_shr(b as u32)),
+            })
+        }
+        (IRExp::ConstBool(a), IRExp::ConstBool(b)) => match op {
+            Op::Equals => Some(IRExp::ConstBool(a == b)),
+            Op::NotEqual => Some(IRExp::ConstBool(a != b)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/* Replaces operations on constants by their value. Returns the number of folded operations. */
+fn fold(exp: &mut IRExp) -> usize {
+    let mut folded = match exp {
+        IRExp::Neg(e) | IRExp::NotBool(e) | IRExp::NotInt(e) => fold(e),
+        IRExp::Exp(b) => fold(&mut b.0) + fold(&mut b.2),
+        _ => 0,
+    };
+    let value = match exp {
+        IRExp::Neg(e) => match **e {
+            IRExp::ConstInt(a) => Some(IRExp::ConstInt(a.wrapping_neg())),
+            _ => None,
+        },
+        IRExp::NotInt(e) => match **e {
+            IRExp::ConstInt(a) => Some(IRExp::ConstInt(!a)),
+            _ => None,
+        },
+        IRExp::NotBool(e) => match **e {
+            IRExp::ConstBool(a) => Some(IRExp::ConstBool(!a)),
+            _ => None,
+        },
+        IRExp::Exp(b) => evaluate(&b.0, &b.1, &b.2),
+        _ => None,
+    };
+    if let Some(value) = value {
+        *exp = value;
+        folded += 1;
+    }
+    folded
+}
+
+/* Propagates constants inside basic blocks and folds operations on them. Branches on a constant
+ * become jumps or disappear. Returns the number of folded operations and branches. */
+pub fn fold_constants(f: &mut IRFunction) -> usize {
+    let mut folded = 0;
+    let mut constants: HashMap<usize, IRExp> = HashMap::new();
+    let mut instructions = Vec::with_capacity(f.instructions.len());
+    for mut cmd in mem::take(&mut f.instructions) {
+        if let IRCmd::Label(_) = cmd {
+            constants.clear();
+        }
+        for exp in operands_mut(&mut cmd) {
+            replace_temps(exp, &|t| constants.get(&t.name).cloned());
+            folded += fold(exp);
+        }
+        match cmd {
+            IRCmd::JumpIf(IRExp::ConstBool(true), label) => {
+                folded += 1;
+                instructions.push(IRCmd::Jump(label));
+                continue;
+            }
+            IRCmd::JumpIf(IRExp::ConstBool(false), _) => {
+                folded += 1;
+                continue;
+            }
+            _ => (),
+        }
+        if let IRCmd::Load(IRExp::Temp(t), exp) = &cmd {
+            if is_constant(exp) {
+                constants.insert(t.name, exp.clone());
+            } else {
+                constants.remove(&t.name);
+            }
+        }
+        instructions.push(cmd);
+    }
+    f.instructions = instructions;
+    folded
+}
+
+/* Replaces uses of a temp that was copied from another temp by the original, inside basic
+ * blocks. Returns the number of replaced uses. */
+pub fn propagate_copies(f: &mut IRFunction) -> usize {
+    let mut propagated = 0;
+    let mut copies: HashMap<usize, Temp> = HashMap::new();
+    for cmd in f.instructions.iter_mut() {
+        if let IRCmd::Label(_) = cmd {
+            copies.clear();
+        }
+        for exp in operands_mut(cmd) {
+            propagated += replace_temps(exp, &|t| copies.get(&t.name).cloned().map(IRExp::Temp));
+        }
+        if let Some(t) = defined(cmd) {
+            copies.remove(&t);
+            copies.retain(|_, source| source.name != t);
+            if let IRCmd::Load(_, IRExp::Temp(source)) = cmd
+                && source.name != t
+            {
+                copies.insert(t, source.clone());
+            }
+        }
+    }
+    f.instructions.retain(
+        |cmd| !matches!(cmd, IRCmd::Load(IRExp::Temp(t), IRExp::Temp(s)) if t.name == s.name),
+    );
+    propagated
+}
+
+/* Reuses the value of an operation that was already computed in the same basic block, as long
+ * as neither its operands nor the temp holding it changed since. Returns the number of reused
+ * operations. */
+pub fn eliminate_common_subexpressions(f: &mut IRFunction) -> usize {
+    let mut eliminated = 0;
+    let mut available: Vec<(IRExp, Temp)> = Vec::new();
+    for cmd in f.instructions.iter_mut() {
+        if let IRCmd::Label(_) = cmd {
+            available.clear();
+        }
+        let IRCmd::Load(IRExp::Temp(t), exp) = cmd else {
+            continue;
+        };
+        /* A repeated division traps the first time already, so it can be reused as well */
+        if is_operation(exp)
+            && let Some((_, holder)) = available.iter().find(|(e, _)| e == exp)
+        {
+            *exp = IRExp::Temp(holder.clone());
+            eliminated += 1;
+        }
+        available.retain(|(e, holder)| holder.name != t.name && !mentions(e, t.name));
+        if is_operation(exp) && !mentions(exp, t.name) {
+            available.push((exp.clone(), t.clone()));
+        }
+    }
+    if eliminated > 0 {
+        propagate_copies(f);
+    }
+    eliminated
+}
+
+/* Removes unreachable instructions, jumps to the next instruction, unused labels and loads of
+ * temps that are never read. Returns the number of removed instructions. */
+pub fn eliminate_dead_code(f: &mut IRFunction) -> usize {
+    let before = f.instructions.len();
+    loop {
+        let len = f.instructions.len();
+        remove_unreachable(&mut f.instructions);
+        simplify_jumps(&mut f.instructions);
+        remove_dead_stores(&mut f.instructions);
+        if f.instructions.len() == len {
+            break;
+        }
+    }
+    before - f.instructions.len()
+}
+
+/* Instructions after a jump or return are unreachable until the next label */
+fn remove_unreachable(cmds: &mut Vec<IRCmd>) {
+    let mut reachable = true;
+    cmds.retain(|cmd| match cmd {
+        IRCmd::Label(_) => {
+            reachable = true;
+            true
+        }
+        _ if !reachable => false,
+        IRCmd::Jump(_) | IRCmd::Return(_) => {
+            reachable = false;
+            true
+        }
+        _ => true,
+    });
+}
+
+fn negate(exp: IRExp) -> IRExp {
+    match exp {
+        IRExp::NotBool(e) => *e,
+        e => IRExp::NotBool(Box::new(e)),
+    }
+}
+
+fn simplify_jumps(cmds: &mut Vec<IRCmd>) {
+    /* if c goto L1; goto L2; L1: becomes if !c goto L2; L1: */
+    let mut removed = vec![false; cmds.len()];
+    for i in 0..cmds.len() {
+        if let [
+            IRCmd::JumpIf(cond, then),
+            IRCmd::Jump(other),
+            IRCmd::Label(next),
+            ..,
+        ] = &mut cmds[i..]
+            && then == next
+        {
+            *cond = negate(mem::replace(cond, IRExp::ConstBool(false)));
+            *then = *other;
+            removed[i + 1] = true;
+        }
+    }
+    let mut index = 0;
+    cmds.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+    /* A jump to the label right after it does nothing */
+    let mut removed = vec![false; cmds.len()];
+    for i in 0..cmds.len() {
+        if let [
+            IRCmd::Jump(target) | IRCmd::JumpIf(_, target),
+            IRCmd::Label(next),
+            ..,
+        ] = &cmds[i..]
+            && target == next
+            && operands(&cmds[i]).iter().all(|e| !e.has_side_effects())
+        {
+            removed[i] = true;
+        }
+    }
+    let mut index = 0;
+    cmds.retain(|_| {
+        index += 1;
+        !removed[index - 1]
+    });
+    /* Labels without jumps to them merge basic blocks */
+    let targets: Vec<usize> = cmds
+        .iter()
+        .filter_map(|cmd| match cmd {
+            IRCmd::Jump(label) | IRCmd::JumpIf(_, label) => Some(*label),
+            _ => None,
+        })
+        .collect();
+    cmds.retain(|cmd| !matches!(cmd, IRCmd::Label(label) if !targets.contains(label)));
+}
+
+/* Removes the stores whose temp is not live after them. Liveness follows every path, so a store
+ * is removed even if its temp is read elsewhere after another store. */
+fn remove_dead_stores(cmds: &mut Vec<IRCmd>) {
+    let mut dead = find_dead_stores(cmds, &analyze_func(cmds)).into_iter();
+    cmds.retain(|_| !dead.next().unwrap());
+}
+
+/* The label an instruction defines or jumps to */
+fn label_mut(cmd: &mut IRCmd) -> Option<&mut usize> {
+    match cmd {
+        IRCmd::Label(label) | IRCmd::Jump(label) | IRCmd::JumpIf(_, label) => Some(label),
+        _ => None,
+    }
+}
+
+/* Small functions that do not call other functions are copied into their callers. A return
+ * of an inlined function still flushes the output like the return of a call does. Only the
+ * functions with callers[i] set are changed. Returns the number of inlined calls. */
+pub fn inline_calls(funcs: &mut [IRFunction], callers: &[bool]) -> usize {
+    let inlinable: Vec<bool> = funcs
+        .iter()
+        .map(|f| {
+            f.instructions.len() <= INLINE_LIMIT
+                && !f.instructions.iter().any(|cmd| {
+                    let mut calls = matches!(cmd, IRCmd::Call(Call::Func(..)));
+                    for exp in operands(cmd) {
+                        calls |=
+                            matches!(exp, IRExp::Call(call) if matches!(**call, Call::Func(..)));
+                    }
+                    calls
+                })
+        })
+        .collect();
+    let mut inlined = 0;
+    for caller in (0..funcs.len()).filter(|i| callers[*i]) {
+        /* Labels are numbered per function */
+        let mut next_label = funcs[caller]
+            .instructions
+            .iter()
+            .filter_map(|cmd| match cmd {
+                IRCmd::Label(label) | IRCmd::Jump(label) | IRCmd::JumpIf(_, label) => {
+                    Some(label + 1)
+                }
+                _ => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let mut num_temps = funcs[caller].num_temps;
+        let mut instructions = Vec::new();
+        for cmd in mem::take(&mut funcs[caller].instructions) {
+            let (result, name, args) = match &cmd {
+                IRCmd::Load(IRExp::Temp(t), IRExp::Call(call)) => match &**call {
+                    Call::Func(name, args) => (Some(t.clone()), name, args),
+                    _ => {
+                        instructions.push(cmd);
+                        continue;
+                    }
+                },
+                IRCmd::Call(Call::Func(name, args)) => (None, name, args),
+                _ => {
+                    instructions.push(cmd);
+                    continue;
+                }
+            };
+            let callee = funcs.iter().position(|f| *name == symbol(&f.name));
+            let Some(callee) = callee.filter(|c| *c != caller && inlinable[*c]) else {
+                instructions.push(cmd);
+                continue;
+            };
+            let callee = &funcs[callee];
+            let offset = num_temps;
+            num_temps += callee.num_temps;
+            let result = result.unwrap_or_else(|| {
+                num_temps += 1;
+                Temp {
+                    name: num_temps - 1,
+                    ver: 0,
+                }
+            });
+            for (param, arg) in args.iter().enumerate() {
+                instructions.push(IRCmd::Load(
+                    IRExp::Temp(Temp {
+                        name: offset + param,
+                        ver: 0,
+                    }),
+                    arg.clone(),
+                ));
+            }
+            let end = next_label;
+            next_label += 1;
+            let mut labels = HashMap::new();
+            for cmd in callee.instructions.iter() {
+                let mut cmd = cmd.clone();
+                let rename = |t: &Temp| {
+                    Some(IRExp::Temp(Temp {
+                        name: t.name + offset,
+                        ver: t.ver,
+                    }))
+                };
+                if let IRCmd::Load(dest, _) = &mut cmd {
+                    replace_temps(dest, &rename);
+                }
+                for exp in operands_mut(&mut cmd) {
+                    replace_temps(exp, &rename);
+                }
+                if let Some(label) = label_mut(&mut cmd) {
+                    *label = *labels.entry(*label).or_insert_with(|| {
+                        next_label += 1;
+                        next_label - 1
+                    });
+                }
+                match cmd {
+                    IRCmd::Return(exp) => {
+                        instructions.push(IRCmd::Load(IRExp::Temp(result.clone()), exp));
+                        instructions.push(IRCmd::Call(Call::Flush));
+                        instructions.push(IRCmd::Jump(end));
+                    }
+                    cmd => instructions.push(cmd),
+                }
+            }
+            instructions.push(IRCmd::Label(end));
+            inlined += 1;
+        }
+        funcs[caller].instructions = instructions;
+        funcs[caller].num_temps = num_temps;
+    }
+    inlined
+}
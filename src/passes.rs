@@ -0,0 +1,241 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    fmt,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::{Duration, Instant},
+};
+
+/* A phase of the compiler that reads and extends the state S. Returns false if it failed, which
+ * stops the pipeline. */
+pub struct Pass<S> {
+    pub name: &'static str,
+    /* Passes that have to run before this one */
+    pub requires: Vec<&'static str>,
+    pub run: fn(&mut S) -> bool,
+}
+
+/* Runs registered passes in an order that respects their dependencies and measures every pass.
+ * Passes that already succeeded are not run again, so a state can be taken through the pipeline in
+ * several steps. */
+pub struct PassManager<S> {
+    passes: Vec<Pass<S>>,
+    completed: Vec<&'static str>,
+    timings: Vec<PassTiming>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PassTiming {
+    pub name: &'static str,
+    pub time: Duration,
+    /* Bytes allocated during the pass and the largest heap size while it ran. Both are zero
+     * unless TrackingAllocator is the global allocator. */
+    pub allocated: usize,
+    pub peak: usize,
+}
+
+impl<S> Default for PassManager<S> {
+    fn default() -> Self {
+        PassManager {
+            passes: Vec::new(),
+            completed: Vec::new(),
+            timings: Vec::new(),
+        }
+    }
+}
+
+impl<S> PassManager<S> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Registers a pass. Its dependencies must be registered before it, which rules out cycles. */
+    pub fn register(&mut self, pass: Pass<S>) {
+        for dependency in pass.requires.iter() {
+            assert!(
+                self.passes.iter().any(|p| p.name == *dependency),
+                "pass {} requires the unknown pass {dependency}",
+                pass.name
+            );
+        }
+        assert!(
+            self.passes.iter().all(|p| p.name != pass.name),
+            "pass {} is registered twice",
+            pass.name
+        );
+        self.passes.push(pass);
+    }
+
+    /* The passes needed to run target, every pass after its dependencies */
+    pub fn schedule(&self, target: &str) -> Vec<&'static str> {
+        let mut order = Vec::new();
+        self.visit(target, &mut order);
+        order
+    }
+
+    fn visit(&self, name: &str, order: &mut Vec<&'static str>) {
+        let pass = self
+            .passes
+            .iter()
+            .find(|p| p.name == name)
+            .unwrap_or_else(|| panic!("unknown pass {name}"));
+        if order.contains(&pass.name) {
+            return;
+        }
+        for dependency in pass.requires.iter() {
+            self.visit(dependency, order);
+        }
+        order.push(pass.name);
+    }
+
+    /* Runs target and everything it depends on that has not run yet. Stops at the first pass that
+     * fails and returns false in that case. */
+    pub fn run(&mut self, state: &mut S, target: &str) -> bool {
+        for name in self.schedule(target) {
+            if self.completed.contains(&name) {
+                continue;
+            }
+            let pass = self.passes.iter().find(|p| p.name == name).unwrap();
+            let allocated = ALLOCATED.load(Ordering::Relaxed);
+            PEAK.store(LIVE.load(Ordering::Relaxed), Ordering::Relaxed);
+            let start = Instant::now();
+            let ok = (pass.run)(state);
+            self.timings.push(PassTiming {
+                name,
+                time: start.elapsed(),
+                allocated: ALLOCATED.load(Ordering::Relaxed) - allocated,
+                peak: PEAK.load(Ordering::Relaxed),
+            });
+            if !ok {
+                return false;
+            }
+            self.completed.push(name);
+        }
+        true
+    }
+
+    pub fn into_timings(self) -> Vec<PassTiming> {
+        self.timings
+    }
+}
+
+fn format_bytes(bytes: usize) -> String {
+    if bytes < 1024 {
+        format!("{bytes} B")
+    } else if bytes < 1024 * 1024 {
+        format!("{:.1} KiB", bytes as f64 / 1024.0)
+    } else {
+        format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+    }
+}
+
+/* The report of --time-passes, one line per pass and a total */
+pub fn format_timings(timings: &[PassTiming]) -> String {
+    let mut report = format!(
+        "{:<12} {:>12} {:>12} {:>12}\n",
+        "pass", "time", "allocated", "peak heap"
+    );
+    let line = |name: &str, time: Duration, allocated: usize, peak: usize| {
+        format!(
+            "{name:<12} {:>9.3} ms {:>12} {:>12}\n",
+            time.as_secs_f64() * 1000.0,
+            format_bytes(allocated),
+            format_bytes(peak)
+        )
+    };
+    for t in timings {
+        report.push_str(&line(t.name, t.time, t.allocated, t.peak));
+    }
+    report.push_str(&line(
+        "total",
+        timings.iter().map(|t| t.time).sum(),
+        timings.iter().map(|t| t.allocated).sum(),
+        timings.iter().map(|t| t.peak).max().unwrap_or(0),
+    ));
+    report
+}
+
+/* Counters collected by the passes, reported by --stats in the order they were first added */
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    counters: Vec<(&'static str, usize)>,
+}
+
+impl Stats {
+    pub fn add(&mut self, name: &'static str, value: usize) {
+        match self.counters.iter_mut().find(|(n, _)| *n == name) {
+            Some((_, count)) => *count += value,
+            None => self.counters.push((name, value)),
+        }
+    }
+
+    /* Adds the counters of other, e.g. to sum up the units of a program */
+    pub fn merge(&mut self, other: &Stats) {
+        for (name, count) in other.counters.iter() {
+            self.add(name, *count);
+        }
+    }
+
+    pub fn get(&self, name: &str) -> usize {
+        self.counters
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map_or(0, |(_, count)| *count)
+    }
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, count) in self.counters.iter() {
+            writeln!(f, "{count:>10} {name}")?;
+        }
+        Ok(())
+    }
+}
+
+static ALLOCATED: AtomicUsize = AtomicUsize::new(0);
+static LIVE: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+/* The system allocator, counting allocated bytes for the memory columns of --time-passes.
+ * Binaries opt in with #[global_allocator]. */
+pub struct TrackingAllocator;
+
+impl TrackingAllocator {
+    fn grow(size: usize) {
+        ALLOCATED.fetch_add(size, Ordering::Relaxed);
+        let live = LIVE.fetch_add(size, Ordering::Relaxed) + size;
+        PEAK.fetch_max(live, Ordering::Relaxed);
+    }
+}
+
+unsafe impl GlobalAlloc for TrackingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc_zeroed(layout) };
+        if !ptr.is_null() {
+            Self::grow(layout.size());
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_ptr = unsafe { System.realloc(ptr, layout, new_size) };
+        if !new_ptr.is_null() {
+            LIVE.fetch_sub(layout.size(), Ordering::Relaxed);
+            Self::grow(new_size);
+        }
+        new_ptr
+    }
+}
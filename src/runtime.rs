@@ -0,0 +1,79 @@
+/* The runtime of freestanding executables, which are linked without the C library. It defines
+ * the functions of the C library the generated code calls, putchar, getchar and fflush, and the
+ * stdout it passes to fflush, so that the same code links with and without the C library. They
+ * only clobber the registers callers save. Output is buffered until fflush or a full buffer and
+ * written with raw syscalls. _start calls the main of the program, which exits with its return
+ * value. */
+pub const RUNTIME: &str = ".intel_syntax noprefix
+.global _start
+.global putchar
+.global getchar
+.global fflush
+.global stdout
+.bss
+.align 8
+stdout:
+        .zero 8
+length:
+        .zero 8
+buffer:
+        .zero 4096
+.text
+_start:
+        xor ebp, ebp
+        and rsp, -16
+        call main
+
+putchar:
+        mov rax, QWORD PTR length[rip]
+        lea rcx, buffer[rip]
+        mov BYTE PTR [rcx+rax], dil
+        inc rax
+        mov QWORD PTR length[rip], rax
+        cmp rax, 4096
+        jne 1f
+        push rdi
+        call fflush
+        pop rdi
+1:
+        movzx eax, dil
+        ret
+
+getchar:
+        sub rsp, 8
+        xor eax, eax
+        xor edi, edi
+        mov rsi, rsp
+        mov edx, 1
+        syscall
+        cmp rax, 1
+        jne 1f
+        movzx eax, BYTE PTR [rsp]
+        add rsp, 8
+        ret
+1:
+        mov eax, -1
+        add rsp, 8
+        ret
+
+fflush:
+        lea rsi, buffer[rip]
+        mov rdx, QWORD PTR length[rip]
+1:
+        test rdx, rdx
+        jle 2f
+        mov eax, 1
+        mov edi, 1
+        syscall
+        test rax, rax
+        jle 2f
+        add rsi, rax
+        sub rdx, rax
+        jmp 1b
+2:
+        mov QWORD PTR length[rip], 0
+        xor eax, eax
+        ret
+
+.section .note.GNU-stack,\"\",@progbits
+";
@@ -1,433 +1,545 @@
-use std::{
-    collections::{HashMap, HashSet},
-    iter,
-    process::exit,
-};
+use std::fmt;
 
 use crate::{
-    ast::{Binop, Exp, Function, Param, Program, Statement, Type},
-    elaboration::{Abs, translate_statement},
+    arena::Arena,
+    ast::{Binop, Exp, ExpId, Param, Program, ShowExp, Type, Var},
+    definite_assignment::definite_assignment_check,
+    diagnostics::{Diagnostics, Span, closest_name},
+    elaboration::{Abs, AbsId, elaborate, fmt_abs},
+    error_codes,
+    symbols::{self, Declaration, Interner, Symbol, SymbolKind, SymbolTable},
 };
 
-#[derive(Debug)]
-pub struct AbsFunction<'a> {
-    pub name: &'a [u8],
-    pub param_names: Vec<&'a [u8]>,
-    pub body: Abs<'a>,
+#[derive(Debug, Clone)]
+pub struct AbsFunction {
+    pub name: Symbol,
+    pub params: Vec<Var>,
+    /* The statements of the function and the sequence of its body among them. Their expressions
+     * are in the arena of the program. */
+    pub nodes: Arena<Abs>,
+    pub body: AbsId,
+    /* The span of the function name and of every parameter, used for warnings */
+    pub span: Span,
+    pub param_spans: Vec<Span>,
 }
 
-pub fn check_semantics<'a>(program: Program<'a>) -> Vec<AbsFunction<'a>> {
-    let funcs = program.into_functions();
-    if !check_function_names(funcs) {
-        exit(7);
+impl AbsFunction {
+    /* Prints the function given the expressions and names of its program, e.g. for --emit=abs */
+    pub fn show<'a>(&'a self, exps: &'a Arena<Exp>, names: &'a Interner) -> ShowFunction<'a> {
+        ShowFunction {
+            function: self,
+            exps,
+            names,
+        }
     }
-    check_function_semantics(funcs)
 }
 
-fn check_function_names(funcs: &Vec<Function>) -> bool {
-    let mut names = HashSet::new();
-    let mut main = false;
-    for f in funcs.iter() {
-        let f_name = f.get_name();
-        if f_name == b"print" || f_name == b"read" || f_name == b"flush" {
-            println!("Error: built-in functions cannot be redefined.");
-            return false;
-        }
-        if !names.insert(f_name) {
-            println!(
-                "Error: Function \"{}\" is declared more than once.",
-                str::from_utf8(f_name).unwrap()
-            );
-            return false;
-        }
-        let mut params: Vec<&[u8]> = vec![];
-        for p in f.get_params().iter() {
-            if params.contains(&p.get_name()) {
-                println!(
-                    "Error: Function \"{}\" has duplicate parameter names.",
-                    str::from_utf8(f_name).unwrap()
+pub struct ShowFunction<'a> {
+    function: &'a AbsFunction,
+    exps: &'a Arena<Exp>,
+    names: &'a Interner,
+}
+
+impl fmt::Display for ShowFunction<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let func = self.function;
+        let params: Vec<&str> = func
+            .params
+            .iter()
+            .map(|p| self.names.name(p.name))
+            .collect();
+        writeln!(
+            f,
+            "function {}({})",
+            self.names.name(func.name),
+            params.join(", ")
+        )?;
+        fmt_abs(&func.nodes, self.exps, self.names, func.body, f, 1)
+    }
+}
+
+/* Reports every semantic error into diags. The result is only meaningful if no errors were reported.
+ * Calls are checked against the functions in symbols, which check_function_names collects from
+ * every unit. The variables of program must have been resolved into symbols by resolve_names. */
+pub fn check_semantics(
+    diags: &mut Diagnostics,
+    program: &mut Program,
+    symbols: &SymbolTable,
+) -> Vec<AbsFunction> {
+    check_function_semantics(diags, program, symbols)
+}
+
+/* Checks the function names of all units of a program and declares them in symbols, which
+ * already holds the names the units were lexed with. units holds the path and the program of
+ * every unit, diags the diagnostics of every unit. A function that is already defined in an
+ * earlier unit is reported in the later one. A declaration must match the definition of its
+ * function if any unit has one, and the first declaration otherwise. */
+pub fn check_function_names(
+    diags: &mut [&mut Diagnostics],
+    units: &[(&str, &Program)],
+    symbols: &mut SymbolTable,
+) {
+    for (unit, (_, program)) in units.iter().enumerate() {
+        let diags = &mut *diags[unit];
+        for f in program.functions.iter() {
+            let f_name = f.get_name();
+            let name = symbols.name(f_name).to_owned();
+            if f_name.is_builtin() {
+                diags.error(
+                    error_codes::BUILTIN_REDEFINED,
+                    f.span(),
+                    "built-in functions cannot be redefined.",
                 );
-                return false;
+            } else if let Some(first) = symbols.function(f_name) {
+                let message = format!("Function \"{name}\" is declared more than once.");
+                if first.unit == unit {
+                    diags
+                        .error(error_codes::DUPLICATE_FUNCTION, f.span(), &message)
+                        .label(first.span, "first declared here");
+                } else {
+                    diags.error(
+                        error_codes::DUPLICATE_FUNCTION,
+                        f.span(),
+                        &format!("{message} It is first declared in {}.", units[first.unit].0),
+                    );
+                }
+            } else {
+                symbols.declare(Declaration {
+                    name: f_name,
+                    kind: SymbolKind::Function(
+                        f.get_params().iter().map(|p| *p.get_type()).collect(),
+                    ),
+                    typ: *f.get_type(),
+                    unit,
+                    span: f.span(),
+                });
             }
-            params.push(p.get_name());
-        }
+            check_parameters(diags, &name, f.get_params());
 
-        if f.get_name() == b"main" {
-            main = true;
-            if !f.get_params().is_empty() {
-                println!("Error: main function cannot take any arguments.");
-                return false;
+            if f_name == symbols::MAIN {
+                let params = f.get_params();
+                if let (Some(first), Some(last)) = (params.first(), params.last()) {
+                    diags
+                        .error(
+                            error_codes::MAIN_WITH_PARAMETERS,
+                            f.span(),
+                            "main function cannot take any arguments.",
+                        )
+                        .suggest(first.span().to(last.span()), "", "remove the parameters");
+                }
+                if !(*f.get_type() == Type::Int) {
+                    diags.error(
+                        error_codes::MAIN_RETURN_TYPE,
+                        f.span(),
+                        "main function should have return type Int.",
+                    );
+                }
             }
-            if !(*f.get_type() == Type::Int) {
-                println!("Error: main function should have return type Int.",);
-                return false;
+        }
+    }
+    /* Only now every definition is known */
+    for (unit, (_, program)) in units.iter().enumerate() {
+        let diags = &mut *diags[unit];
+        for d in program.declarations.iter() {
+            let d_name = d.get_name();
+            let name = symbols.name(d_name).to_owned();
+            let params: Vec<Type> = d.get_params().iter().map(|p| *p.get_type()).collect();
+            if d_name.is_builtin() {
+                diags.error(
+                    error_codes::BUILTIN_REDEFINED,
+                    d.span(),
+                    "built-in functions cannot be redeclared.",
+                );
+            } else if let Some(first) = symbols.function(d_name) {
+                let (SymbolKind::Function(first_params) | SymbolKind::ExternFunction(first_params)) =
+                    &first.kind
+                else {
+                    unreachable!("functions are only declared as functions");
+                };
+                if *first_params != params || first.typ != *d.get_type() {
+                    let defined = matches!(first.kind, SymbolKind::Function(_));
+                    let what = if defined { "defined" } else { "first declared" };
+                    let message = format!(
+                        "Function \"{name}\" is declared as `{}`, but {what} as `{}`.",
+                        signature(&name, &params, *d.get_type()),
+                        signature(&name, first_params, first.typ),
+                    );
+                    if first.unit == unit {
+                        diags
+                            .error(error_codes::CONFLICTING_DECLARATION, d.span(), &message)
+                            .label(first.span, &format!("{what} here"));
+                    } else {
+                        diags.error(
+                            error_codes::CONFLICTING_DECLARATION,
+                            d.span(),
+                            &format!("{message} It is {what} in {}.", units[first.unit].0),
+                        );
+                    }
+                }
+            } else {
+                symbols.declare(Declaration {
+                    name: d_name,
+                    kind: SymbolKind::ExternFunction(params),
+                    typ: *d.get_type(),
+                    unit,
+                    span: d.span(),
+                });
             }
+            check_parameters(diags, &name, d.get_params());
         }
     }
-    if !main {
-        println!("Error: missing main function.");
-        return false;
+}
+
+fn check_parameters(diags: &mut Diagnostics, name: &str, params: &[Param]) {
+    let mut seen: Vec<&Param> = vec![];
+    for p in params.iter() {
+        if let Some(first) = seen.iter().find(|q| q.get_name() == p.get_name()) {
+            diags
+                .error(
+                    error_codes::DUPLICATE_PARAMETER,
+                    p.span(),
+                    &format!("Function \"{name}\" has duplicate parameter names."),
+                )
+                .label(first.span(), "first declared here");
+        }
+        seen.push(p);
     }
-    true
 }
 
-fn check_function_semantics<'a>(funcs: &Vec<Function<'a>>) -> Vec<AbsFunction<'a>> {
-    let mut abs_funcs = Vec::new();
-    let func_params = funcs
-        .iter()
-        .map(|f| (f.get_name(), (f.get_params(), f.get_type())))
-        .collect();
-    for f in funcs.iter() {
-        let mut declared: Vec<&'a [u8]> = f.get_params().iter().map(|p| p.get_name()).collect();
-        let mut assigned = declared.clone();
-        let stmts = translate_statement(
-            &mut iter::once(Statement::Block(f.clone().get_block())).peekable(),
-        );
-        if !return_check(&stmts) {
-            println!(
-                "Error: Function \"{}\" does not return.",
-                str::from_utf8(f.get_name()).unwrap()
+/* The signature of a function as it is written without parameter names, e.g. int f(int, bool) */
+fn signature(name: &str, params: &[Type], typ: Type) -> String {
+    let params: Vec<String> = params.iter().map(|t| t.to_string()).collect();
+    format!("{typ} {name}({})", params.join(", "))
+}
+
+/* A program that is linked on its own needs a definition of every function it declares, in one
+ * of its units. The missing ones are reported at their first declaration. */
+pub fn check_definitions(diags: &mut [&mut Diagnostics], symbols: &SymbolTable) {
+    for d in symbols.declarations() {
+        if let SymbolKind::ExternFunction(_) = d.kind {
+            diags[d.unit].error(
+                error_codes::UNDEFINED_FUNCTION,
+                d.span,
+                &format!(
+                    "Function \"{}\" is declared but not defined.",
+                    symbols.name(d.name)
+                ),
             );
-            exit(7)
         }
-        if !decl_check(&stmts, &mut assigned, &mut declared) {
-            println!(
-                "Error: Function \"{}\" has undeclared or unassigned variables.",
-                str::from_utf8(f.get_name()).unwrap()
+    }
+}
+
+/* A program needs a main function once it is linked, a single unit compiled on its own does not */
+pub fn check_main(diags: &mut Diagnostics, symbols: &SymbolTable) {
+    if symbols.function(symbols::MAIN).is_none() {
+        diags.error_file(error_codes::MISSING_MAIN, "missing main function.");
+    }
+}
+
+fn check_function_semantics(
+    diags: &mut Diagnostics,
+    program: &mut Program,
+    symbols: &SymbolTable,
+) -> Vec<AbsFunction> {
+    let mut abs_funcs = Vec::new();
+    for f in program.functions.iter() {
+        let params: Vec<Var> = f.get_params().iter().map(|p| p.get_var()).collect();
+        let (nodes, body) = elaborate(diags, &program.statements, &mut program.exps, f.get_block());
+        let checker = Checker {
+            nodes: &nodes,
+            exps: &program.exps,
+            symbols,
+        };
+        if !checker.return_check(body) {
+            diags.error(
+                error_codes::MISSING_RETURN,
+                f.span(),
+                &format!(
+                    "Function \"{}\" does not return.",
+                    symbols.name(f.get_name())
+                ),
             );
-            exit(7)
-        }
-        let mut variables = f
-            .get_params()
-            .iter()
-            .map(|p| (p.get_name(), *p.get_type()))
-            .collect();
-        if !type_check(f.get_type(), &stmts, &func_params, &mut variables) {
-            exit(7);
         }
+        /* Both skip the variables that are not declared, which name resolution reported */
+        definite_assignment_check(diags, &nodes, &program.exps, symbols, &params, body);
+        checker.type_check(diags, f.get_type(), body);
         let loop_counter = 0;
-        if !break_coninue_check(loop_counter, &stmts) {
-            println!("Error: Break and continue found outside of loop.");
-            exit(7)
-        }
-        let mut param_names = Vec::new();
-        for p in f.get_params() {
-            param_names.push(p.get_name());
-        }
-
+        checker.break_coninue_check(diags, loop_counter, body);
         abs_funcs.push(AbsFunction {
             name: f.get_name(),
-            param_names,
-            body: stmts,
+            params,
+            nodes,
+            body,
+            span: f.span(),
+            param_spans: f.get_params().iter().map(|p| p.span()).collect(),
         });
     }
     abs_funcs
 }
 
-fn arg_type_check<'a>(
-    f_name: &'a [u8],
-    func_params: &HashMap<&'a [u8], (&Vec<Param<'a>>, &Type)>,
-    args: &[Exp<'a>],
-    variables: &HashMap<&[u8], Type>,
-) -> bool {
-    if let Some(f_args) = func_params.get(f_name) {
-        if f_args.0.len() == args.len() {
-            let res = f_args.0.iter().enumerate().any(|(i, param)| {
-                type_check_exp(&args[i], param.get_type(), func_params, variables).is_err()
-            });
-            if res {
-                println!(
-                    "Error: Function \"{}\" was called with parameters of the wrong type.",
-                    str::from_utf8(f_name).unwrap(),
-                );
-                false
-            } else {
-                true
-            }
-        } else {
-            println!(
-                "Error: Function \"{}\" takes {} arguments, but {} were provided.",
-                str::from_utf8(f_name).unwrap(),
-                f_args.0.len(),
-                args.len(),
-            );
-            false
-        }
-    } else {
-        println!(
-            "Error: No function with name \"{}\" found.",
-            str::from_utf8(f_name).unwrap()
-        );
-        false
-    }
+/* What the checks of a function read: its nodes, the expressions of the program and the
+ * declarations */
+struct Checker<'c> {
+    nodes: &'c Arena<Abs>,
+    exps: &'c Arena<Exp>,
+    symbols: &'c SymbolTable,
 }
 
-fn return_check<'a>(s: &Abs<'a>) -> bool {
-    match s {
-        Abs::RET(_) => true,
-        Abs::DECL(_, _, seq) => return_check(seq),
-        Abs::IF(_, abs1, abs2) => return_check(abs1) && return_check(abs2),
-        Abs::SEQ(items) => {
-            for s in items {
-                if return_check(s) {
-                    return true;
-                }
-            }
-            false
-        }
-        _ => false,
+impl Checker<'_> {
+    fn show(&self, exp: ExpId) -> ShowExp<'_> {
+        self.exps.show(exp, self.symbols.names())
     }
-}
-
-fn is_contained<'a>(e: &Exp<'a>, vec: &mut Vec<&'a [u8]>) -> bool {
-    match e {
-        Exp::Ident(ident) => vec.contains(ident),
-        Exp::Arithmetic(exps) => is_contained(&exps.0, vec) && is_contained(&exps.2, vec),
-        Exp::Negative(exp) => is_contained(exp, vec),
-        Exp::Not(exp) => is_contained(exp, vec),
-        Exp::BitNot(exp) => is_contained(exp, vec),
-        Exp::Ternary(exps) => {
-            is_contained(&exps.0, vec) && is_contained(&exps.1, vec) && is_contained(&exps.2, vec)
-        }
-        _ => true,
-    }
-}
 
-fn decl_check<'a>(
-    abs: &Abs<'a>,
-    assigned: &mut Vec<&'a [u8]>,
-    declared: &mut Vec<&'a [u8]>,
-) -> bool {
-    match abs {
-        Abs::ASGN(name, exp) => {
-            if declared.contains(name) && is_contained(exp, assigned) {
-                if !assigned.contains(name) {
-                    assigned.push(name);
+    fn return_check(&self, id: AbsId) -> bool {
+        match &self.nodes[id] {
+            Abs::RET(..) => true,
+            Abs::DECL(_, _, seq, _) => self.return_check(*seq),
+            Abs::IF(_, abs1, abs2, _) => self.return_check(*abs1) && self.return_check(*abs2),
+            Abs::SEQ(items) => {
+                for s in items {
+                    if self.return_check(*s) {
+                        return true;
+                    }
                 }
-                return true;
-            }
-            false
-        }
-        Abs::WHILE(exp, abs) => {
-            if is_contained(exp, assigned) {
-                let mut temp_assigned = assigned.clone();
-                return decl_check(abs, &mut temp_assigned, declared);
+                false
             }
-            false
+            _ => false,
         }
-        Abs::CONT => {
-            *assigned = declared.clone();
-            true
-        }
-        Abs::RET(exp) => {
-            let res = is_contained(exp, assigned);
-            *assigned = declared.clone();
-            res
-        }
-        Abs::DECL(name, _, abs) => {
-            if declared.contains(name) {
-                return false;
+    }
+
+    /* Reports every break or continue that is not inside a loop */
+    fn break_coninue_check(&self, diags: &mut Diagnostics, counter: usize, id: AbsId) {
+        match &self.nodes[id] {
+            Abs::WHILE(_, abs, _) | Abs::FOR(abs, _) => {
+                self.break_coninue_check(diags, counter + 1, *abs)
             }
-            declared.push(name);
-            if decl_check(abs, assigned, declared) {
-                declared.remove(declared.iter().position(|n| n == name).unwrap());
-                if let Some(index) = assigned.iter().position(|x| x == name) {
-                    assigned.remove(index);
+            Abs::CONT(span) | Abs::BRK(span) => {
+                if counter == 0 {
+                    diags.error(
+                        error_codes::JUMP_OUTSIDE_LOOP,
+                        *span,
+                        "Break and continue found outside of loop.",
+                    );
                 }
-                return true;
             }
-            false
-        }
-        Abs::IF(exp, abs1, abs2) => {
-            let return_exp = is_contained(exp, assigned);
-            let mut temp_assigned = assigned.clone();
-            let return_then = decl_check(abs1, &mut temp_assigned, declared);
-            let return_else = decl_check(abs2, assigned, declared);
-            assigned.retain(|x| temp_assigned.contains(x));
-            return_exp && return_then && return_else
-        }
-        Abs::FOR(abs) => {
-            if let Abs::SEQ(vec) = &**abs {
-                let mut temp_assigned = assigned.clone();
-                if matches!(vec[0], Abs::ASGN(..)) {
-                    decl_check(&vec[0], assigned, declared);
-                }
-                return decl_check(abs, &mut temp_assigned, declared);
+            Abs::DECL(_, _, abs, _) => self.break_coninue_check(diags, counter, *abs),
+            Abs::IF(_, abs1, abs2, _) => {
+                self.break_coninue_check(diags, counter, *abs1);
+                self.break_coninue_check(diags, counter, *abs2);
             }
-            decl_check(abs, assigned, declared)
-        }
-        Abs::BRK => {
-            *assigned = declared.clone();
-            true
-        }
-        Abs::SEQ(items) => {
-            for abs in items {
-                if !decl_check(abs, assigned, declared) {
-                    return false;
-                }
-            }
-            true
-        }
-        Abs::EXP(exp) => is_contained(exp, assigned),
-        Abs::CALL(_, exps) => {
-            for abs in exps {
-                if !is_contained(abs, assigned) {
-                    return false;
+            Abs::SEQ(items) => {
+                for abs in items.iter() {
+                    self.break_coninue_check(diags, counter, *abs);
+                    if matches!(self.nodes[*abs], Abs::BRK(_) | Abs::CONT(_) | Abs::RET(..)) {
+                        return;
+                    }
                 }
             }
-            true
+            Abs::ASGN(..) | Abs::EXP(..) | Abs::RET(..) => (),
+            Abs::CALL(..) => (),
         }
     }
-}
 
-fn break_coninue_check(counter: usize, abs: &Abs) -> bool {
-    match abs {
-        Abs::WHILE(_, abs) | Abs::FOR(abs) => break_coninue_check(counter + 1, abs),
-        Abs::CONT => counter > 0,
-        Abs::DECL(_, _, abs) => break_coninue_check(counter, abs),
-        Abs::IF(_, abs1, abs2) => {
-            break_coninue_check(counter, abs1) && break_coninue_check(counter, abs2)
-        }
-        Abs::BRK => counter > 0,
-        Abs::SEQ(items) => {
-            for abs in items.iter() {
-                if matches!(abs, Abs::BRK | Abs::CONT | Abs::RET(_)) {
-                    return break_coninue_check(counter, abs);
-                } else if !break_coninue_check(counter, abs) {
-                    return false;
-                }
-            }
-            true
-        }
-        Abs::ASGN(..) | Abs::EXP(..) | Abs::RET(..) => true,
-        Abs::CALL(_items, _exps) => true,
+    /* Reports that exp does not have the expected type. what describes the position of exp,
+     * e.g. "Operand of `+`". */
+    fn expected_type(
+        &self,
+        diags: &mut Diagnostics,
+        exp: ExpId,
+        what: &str,
+        expected: Type,
+        found: Type,
+    ) {
+        diags.error(
+            error_codes::TYPE_MISMATCH,
+            self.exps.span(exp),
+            &format!(
+                "{what} must be {expected}, but `{}` has type {found}.",
+                self.show(exp)
+            ),
+        );
     }
-}
-fn type_check_exp<'a>(
-    exp: &Exp,
-    t: &Type,
-    func_params: &HashMap<&'a [u8], (&Vec<Param<'a>>, &Type)>,
-    variables: &HashMap<&[u8], Type>,
-) -> Result<Type, Type> {
-    match exp {
-        Exp::True | Exp::False => {
-            if *t == Type::Bool {
-                Ok(Type::Bool)
-            } else {
-                Err(Type::Bool)
-            }
-        }
 
-        Exp::Intconst(_) => {
-            if *t == Type::Int {
-                Ok(Type::Int)
-            } else {
-                Err(Type::Int)
+    /* Checks that exp has type t and reports the innermost subexpression that does not. Returns
+     * false if an error was reported. */
+    fn check_exp(&self, diags: &mut Diagnostics, exp: ExpId, t: Type, what: &str) -> bool {
+        match self.type_check_exp(diags, exp) {
+            Some(found) if found != t => {
+                self.expected_type(diags, exp, what, t, found);
+                false
             }
+            Some(_) => true,
+            None => false,
         }
-        Exp::Ident(name) => {
-            let ident_type = *variables.get(name).unwrap();
-            if ident_type == *t {
-                Ok(ident_type)
-            } else {
-                Err(ident_type)
+    }
+
+    /* Infers the type of exp. Errors are reported at the subexpression where they occur and
+     * result in None, so that enclosing expressions do not report them again. */
+    fn type_check_exp(&self, diags: &mut Diagnostics, id: ExpId) -> Option<Type> {
+        let exps = self.exps;
+        match &exps[id] {
+            Exp::True(_) | Exp::False(_) => Some(Type::Bool),
+            Exp::Intconst(..) => Some(Type::Int),
+            /* Undeclared variables are reported by name resolution */
+            Exp::Ident(var, _) => var.id.map(|id| self.symbols.get(id).typ),
+            Exp::Arithmetic(e1, binop, e2, span) => {
+                let (e1, e2) = (*e1, *e2);
+                if let Some(operand_type) = type_check_arithmetic(binop) {
+                    let what = format!("Operand of `{binop}`");
+                    let ok1 = self.check_exp(diags, e1, operand_type, &what);
+                    let ok2 = self.check_exp(diags, e2, operand_type, &what);
+                    (ok1 && ok2).then_some(binop_return_type(binop))
+                } else {
+                    let t1 = self.type_check_exp(diags, e1)?;
+                    let t2 = self.type_check_exp(diags, e2)?;
+                    if t1 != t2 {
+                        diags.error(
+                            error_codes::OPERAND_TYPES_DIFFER,
+                            *span,
+                            &format!(
+                                "Operands of `{binop}` must have the same type, but `{}` has type {t1} and `{}` has type {t2}.",
+                                self.show(e1),
+                                self.show(e2)
+                            ),
+                        );
+                        return None;
+                    }
+                    Some(binop_return_type(binop))
+                }
             }
-        }
-        Exp::Arithmetic(b) => {
-            let (e1, binop, e2) = &**b;
-            if let Some(binop_type) = type_check_arithmetic(binop) {
-                type_check_exp(e1, &binop_type, func_params, variables)?;
-                type_check_exp(e2, &binop_type, func_params, variables)?;
-            } else if type_check_exp(e1, &Type::Bool, func_params, variables).is_ok() {
-                if type_check_exp(e2, &Type::Bool, func_params, variables).is_err() {
-                    return Err(Type::Int);
+            Exp::Negative(e, _) => self
+                .check_exp(diags, *e, Type::Int, "Operand of `-`")
+                .then_some(Type::Int),
+            Exp::BitNot(e, _) => self
+                .check_exp(diags, *e, Type::Int, "Operand of `~`")
+                .then_some(Type::Int),
+            Exp::Not(e, _) => self
+                .check_exp(diags, *e, Type::Bool, "Operand of `!`")
+                .then_some(Type::Bool),
+            Exp::Ternary(e1, e2, e3, span) => {
+                let cond = self.check_exp(diags, *e1, Type::Bool, "Condition of `?:`");
+                let t2 = self.type_check_exp(diags, *e2);
+                let t3 = self.type_check_exp(diags, *e3);
+                let (t2, t3) = (t2?, t3?);
+                if t2 != t3 {
+                    diags.error(
+                        error_codes::BRANCH_TYPES_DIFFER,
+                        *span,
+                        &format!(
+                            "Branches of `?:` must have the same type, but `{}` has type {t2} and `{}` has type {t3}.",
+                            self.show(*e2),
+                            self.show(*e3)
+                        ),
+                    );
+                    return None;
                 }
-            } else if type_check_exp(e2, &Type::Int, func_params, variables).is_err() {
-                return Err(Type::Bool);
+                cond.then_some(t2)
             }
-            if binop_return_type(binop) == *t {
-                Ok(*t)
-            } else {
-                Err(binop_return_type(binop))
+            Exp::Call(call, span) => {
+                self.check_call(diags, call.get_name(), call.get_args(), *span)
             }
         }
-        Exp::Negative(exp) | Exp::BitNot(exp) => {
-            type_check_exp(exp, &Type::Int, func_params, variables)
-        }
-        Exp::Not(exp) => type_check_exp(exp, &Type::Bool, func_params, variables),
+    }
 
-        Exp::Ternary(b) => {
-            let (e1, e2, e3) = &**b;
-            type_check_exp(e1, &Type::Bool, func_params, variables)?;
-            type_check_exp(e2, t, func_params, variables)?;
-            if let Err(err) = type_check_exp(e3, t, func_params, variables) {
-                Err(err)
-            } else {
-                Ok(*t)
-            }
-        }
-        Exp::Call(call) => match call {
-            crate::ast::Call::Print(arg_list) => {
-                let args = arg_list.get_args();
-                if args.len() == 1 {
-                    if type_check_exp(&args[0], &Type::Int, func_params, variables).is_err() {
-                        return Err(Type::Bool);
-                    } else if *t == Type::Int {
-                        return Ok(Type::Int);
+    /* Checks the arguments of a call and returns the return type of the function. The return
+     * type is known even if the arguments are wrong, so it is returned in that case too. */
+    fn check_call(
+        &self,
+        diags: &mut Diagnostics,
+        name: Symbol,
+        args: &[ExpId],
+        span: Span,
+    ) -> Option<Type> {
+        let symbols = self.symbols;
+        let name_str = symbols.name(name);
+        let (param_types, return_type) = match name {
+            symbols::PRINT => (vec![Type::Int], Type::Int),
+            symbols::READ | symbols::FLUSH => (vec![], Type::Int),
+            _ => match symbols.function(name) {
+                Some(Declaration {
+                    kind: SymbolKind::Function(params) | SymbolKind::ExternFunction(params),
+                    typ,
+                    ..
+                }) => (params.clone(), *typ),
+                _ => {
+                    let diag = diags.error(
+                        error_codes::UNKNOWN_FUNCTION,
+                        span,
+                        &format!("No function with name \"{name_str}\" found."),
+                    );
+                    let candidates = symbols.function_names().chain(symbols::BUILTINS);
+                    if let Some(similar) = closest_name(name, candidates, symbols.names()) {
+                        let message = if similar.is_builtin() {
+                            "a built-in function with a similar name exists"
+                        } else {
+                            "a function with a similar name exists"
+                        };
+                        diag.suggest(
+                            Span::new(span.start, span.start + name_str.len()),
+                            symbols.name(similar),
+                            message,
+                        );
                     }
-                    return Err(Type::Int);
+                    return None;
                 }
-                println!(
-                    "Error: \"print\" function takes 1 argument but {} were provided.",
-                    args.len()
-                );
-                exit(7);
+            },
+        };
+        if param_types.len() != args.len() {
+            diags.error(
+                error_codes::WRONG_ARGUMENT_COUNT,
+                span,
+                &format!(
+                    "Function \"{name_str}\" takes {} argument{}, but {} {} provided.",
+                    param_types.len(),
+                    if param_types.len() == 1 { "" } else { "s" },
+                    args.len(),
+                    if args.len() == 1 { "was" } else { "were" },
+                ),
+            );
+            return Some(return_type);
+        }
+        for (i, (arg, t)) in args.iter().zip(param_types).enumerate() {
+            let what = format!("Argument {} of \"{name_str}\"", i + 1);
+            self.check_exp(diags, *arg, t, &what);
+        }
+        Some(return_type)
+    }
+
+    fn type_check(&self, diags: &mut Diagnostics, return_type: &Type, id: AbsId) -> bool {
+        match &self.nodes[id] {
+            Abs::ASGN(var, exp, _) => {
+                let Some(id) = var.id else {
+                    return self.type_check_exp(diags, *exp).is_some();
+                };
+                let t = self.symbols.get(id).typ;
+                let what = format!("Value assigned to \"{}\"", self.symbols.name(var.name));
+                self.check_exp(diags, *exp, t, &what)
             }
-            crate::ast::Call::Func(name, arg_list) => {
-                if let Some(data) = func_params.get(name) {
-                    if data.1 == t
-                        && arg_type_check(name, func_params, arg_list.get_args(), variables)
-                    {
-                        return Ok(*data.1);
-                    } else {
-                        return Err(*data.1);
-                    }
-                }
-                println!(
-                    "Error: No function with name \"{}\" found.",
-                    str::from_utf8(name).unwrap()
-                );
-                exit(7);
+            Abs::WHILE(exp, statements, _) => {
+                let cond = self.check_exp(diags, *exp, Type::Bool, "Condition of the while loop");
+                self.type_check(diags, return_type, *statements) && cond
             }
-            crate::ast::Call::Read(arg_list) => {
-                let args = arg_list.get_args();
-                if args.is_empty() {
-                    if *t == Type::Int {
-                        return Ok(Type::Int);
-                    }
-                    return Err(Type::Int);
-                }
-                println!(
-                    "Error: \"read\" function takes zero arguments but {} were provided.",
-                    args.len()
-                );
-                exit(7);
+            Abs::CONT(_) | Abs::BRK(_) => true,
+            Abs::RET(exp, _) => self.check_exp(diags, *exp, *return_type, "Return value"),
+            Abs::DECL(_, _, abs, _) => self.type_check(diags, return_type, *abs),
+            Abs::IF(exp, abs1, abs2, _) => {
+                let mut res =
+                    self.check_exp(diags, *exp, Type::Bool, "Condition of the if statement");
+                res &= self.type_check(diags, return_type, *abs1);
+                res & self.type_check(diags, return_type, *abs2)
             }
-            crate::ast::Call::Flush(arg_list) => {
-                let args = arg_list.get_args();
-                if args.is_empty() {
-                    if *t == Type::Int {
-                        return Ok(Type::Int);
-                    }
-                    return Err(Type::Int);
+            Abs::FOR(abs, _) => self.type_check(diags, return_type, *abs),
+            Abs::SEQ(items) => {
+                let mut res = true;
+                for abs in items {
+                    res &= self.type_check(diags, return_type, *abs);
                 }
-                println!(
-                    "Error: \"flush\" function takes zero arguments but {} were provided.",
-                    args.len()
-                );
-                exit(7);
+                res
             }
-        },
+            /* Only the condition of a for loop is elaborated into an expression statement */
+            Abs::EXP(exp) => self.check_exp(diags, *exp, Type::Bool, "Condition of the for loop"),
+            Abs::CALL(name, args, span) => self.check_call(diags, *name, args, *span).is_some(),
+        }
     }
 }
 
@@ -452,80 +564,3 @@ fn binop_return_type(binop: &Binop) -> Type {
         _ => Type::Int,
     }
 }
-
-fn type_check<'a>(
-    return_type: &Type,
-    abs: &Abs<'a>,
-    func_params: &HashMap<&'a [u8], (&Vec<Param<'a>>, &Type)>,
-    variables: &mut HashMap<&'a [u8], Type>,
-) -> bool {
-    match abs {
-        Abs::ASGN(name, exp) => {
-            if let Err(t) =
-                type_check_exp(exp, variables.get(name).unwrap(), func_params, variables)
-            {
-                println!("Type Error: Wrong use of type {t:?} in expression {exp:?}");
-                false
-            } else {
-                true
-            }
-        }
-        Abs::WHILE(exp, statements) => {
-            if type_check_exp(exp, &Type::Bool, func_params, variables).is_err() {
-                println!("Type Error: While condition {exp:?} should evaluate to bool");
-                false
-            } else {
-                type_check(return_type, statements, func_params, variables)
-            }
-        }
-        Abs::CONT | Abs::BRK => true,
-        Abs::RET(exp) => {
-            if let Err(err_type) = type_check_exp(exp, return_type, func_params, variables) {
-                println!(
-                    "Type Error: Function should return {return_type:?} but it currently returns {err_type:?}"
-                );
-                false
-            } else {
-                true
-            }
-        }
-        Abs::DECL(name, t, abs) => {
-            variables.insert(name, *t);
-            type_check(return_type, abs, func_params, variables)
-        }
-        Abs::IF(exp, abs1, abs2) => {
-            if type_check_exp(exp, &Type::Bool, func_params, variables).is_err() {
-                println!("Type Error: If condition need to evaluate to bool");
-                false
-            } else {
-                type_check(return_type, abs1, func_params, variables)
-                    && type_check(return_type, abs2, func_params, variables)
-            }
-        }
-        Abs::FOR(abs) => type_check(return_type, abs, func_params, variables),
-        Abs::SEQ(items) => {
-            for abs in items {
-                if !type_check(return_type, abs, func_params, variables) {
-                    return false;
-                }
-            }
-            true
-        }
-        Abs::EXP(exp) => {
-            if type_check_exp(exp, &Type::Bool, func_params, variables).is_err() {
-                println!("Type Error: The for loops break condition should evaluate to bool");
-                false
-            } else {
-                true
-            }
-        }
-        Abs::CALL(name, args) => match *name {
-            b"print" => {
-                args.len() == 1
-                    && type_check_exp(&args[0], &Type::Int, func_params, variables).is_ok()
-            }
-            b"read" | b"flush" => args.is_empty(),
-            _ => arg_type_check(name, func_params, args, variables),
-        },
-    }
-}
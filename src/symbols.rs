@@ -0,0 +1,202 @@
+use std::collections::HashMap;
+
+use crate::{ast::Type, diagnostics::Span};
+
+/* An interned identifier. Symbols of the same name are equal, so comparing and hashing them is
+ * as cheap as for an integer. A symbol belongs to the Interner of its program, which gives its
+ * name. The ids depend on the order names are first seen in, so they must not end up in the
+ * output or in the keys of the cache; use the name for that. */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+/* Symbols the compiler itself refers to, interned in this order before any other name */
+const PREDEFINED: [&str; 4] = ["main", "print", "read", "flush"];
+pub const MAIN: Symbol = Symbol(0);
+pub const PRINT: Symbol = Symbol(1);
+pub const READ: Symbol = Symbol(2);
+pub const FLUSH: Symbol = Symbol(3);
+pub const BUILTINS: [Symbol; 3] = [PRINT, READ, FLUSH];
+
+/* The names of the symbols of one program. The lexer adds the identifiers of every unit, after
+ * that the names are only read. */
+#[derive(Debug, Clone)]
+pub struct Interner {
+    ids: HashMap<String, Symbol>,
+    names: Vec<String>,
+}
+
+impl Default for Interner {
+    fn default() -> Self {
+        let mut interner = Interner {
+            ids: HashMap::new(),
+            names: Vec::new(),
+        };
+        for name in PREDEFINED {
+            interner.intern(name);
+        }
+        interner
+    }
+}
+
+impl Interner {
+    pub fn new() -> Interner {
+        Interner::default()
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.ids.get(name) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.names.len() as u32);
+        self.names.push(name.to_owned());
+        self.ids.insert(name.to_owned(), symbol);
+        symbol
+    }
+
+    pub fn name(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+}
+
+impl Symbol {
+    pub fn is_builtin(self) -> bool {
+        BUILTINS.contains(&self)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolKind {
+    /* A function with the types of its parameters, its type is the return type */
+    Function(Vec<Type>),
+    /* A function that is declared but defined in no unit, e.g. in an object file it is linked
+     * with */
+    ExternFunction(Vec<Type>),
+    Parameter,
+    Variable,
+}
+
+/* Where and as what a name is declared */
+#[derive(Debug, Clone)]
+pub struct Declaration {
+    pub name: Symbol,
+    pub kind: SymbolKind,
+    pub typ: Type,
+    /* The unit of the program the declaration is in and its span there */
+    pub unit: usize,
+    pub span: Span,
+}
+
+/* Refers to a declaration in a SymbolTable */
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DeclId(u32);
+
+/* A set of declarations with one bit per DeclId. The ids of a SymbolTable are dense, so the set
+ * takes a bit for every declaration up to the largest one it contains. */
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeclSet {
+    words: Vec<u64>,
+}
+
+impl DeclSet {
+    pub fn new() -> DeclSet {
+        DeclSet::default()
+    }
+
+    pub fn contains(&self, id: DeclId) -> bool {
+        let i = id.0 as usize;
+        self.words
+            .get(i / 64)
+            .is_some_and(|word| word & (1 << (i % 64)) != 0)
+    }
+
+    pub fn insert(&mut self, id: DeclId) {
+        let i = id.0 as usize;
+        if self.words.len() <= i / 64 {
+            self.words.resize(i / 64 + 1, 0);
+        }
+        self.words[i / 64] |= 1 << (i % 64);
+    }
+
+    /* Keeps only the declarations that are in other as well */
+    pub fn intersect(&mut self, other: &DeclSet) {
+        self.words.truncate(other.words.len());
+        for (word, other) in self.words.iter_mut().zip(other.words.iter()) {
+            *word &= other;
+        }
+    }
+}
+
+impl FromIterator<DeclId> for DeclSet {
+    fn from_iter<I: IntoIterator<Item = DeclId>>(ids: I) -> DeclSet {
+        let mut set = DeclSet::new();
+        for id in ids {
+            set.insert(id);
+        }
+        set
+    }
+}
+
+/* Every declaration of a program and the names of its symbols. Functions are global and can be
+ * looked up by name, parameters and variables are scoped and are found through the DeclId their
+ * declaration returned. */
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    names: Interner,
+    declarations: Vec<Declaration>,
+    functions: HashMap<Symbol, DeclId>,
+}
+
+impl SymbolTable {
+    pub fn new() -> SymbolTable {
+        SymbolTable::default()
+    }
+
+    pub fn names(&self) -> &Interner {
+        &self.names
+    }
+
+    pub fn names_mut(&mut self) -> &mut Interner {
+        &mut self.names
+    }
+
+    /* The name of a symbol, e.g. for a message */
+    pub fn name(&self, symbol: Symbol) -> &str {
+        self.names.name(symbol)
+    }
+
+    /* Records a declaration. A function is only recorded under its name if there is no function
+     * of that name yet. */
+    pub fn declare(&mut self, declaration: Declaration) -> DeclId {
+        let id = DeclId(self.declarations.len() as u32);
+        if let SymbolKind::Function(_) | SymbolKind::ExternFunction(_) = declaration.kind {
+            self.functions.entry(declaration.name).or_insert(id);
+        }
+        self.declarations.push(declaration);
+        id
+    }
+
+    pub fn get(&self, id: DeclId) -> &Declaration {
+        &self.declarations[id.0 as usize]
+    }
+
+    pub fn function(&self, name: Symbol) -> Option<&Declaration> {
+        self.functions.get(&name).map(|id| self.get(*id))
+    }
+
+    /* The names of the functions in the order of their declarations */
+    pub fn function_names(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.declarations
+            .iter()
+            .filter(|d| {
+                matches!(
+                    d.kind,
+                    SymbolKind::Function(_) | SymbolKind::ExternFunction(_)
+                )
+            })
+            .map(|d| d.name)
+    }
+
+    pub fn declarations(&self) -> &[Declaration] {
+        &self.declarations
+    }
+}
@@ -1,8 +1,14 @@
 use std::fmt;
 
+use crate::{
+    diagnostics::Span,
+    error_codes,
+    symbols::{Interner, Symbol},
+};
+
 #[derive(Debug, Clone)]
-pub enum Token<'a> {
-    Identifier(&'a [u8]),
+pub enum Token {
+    Identifier(Symbol),
     NumericValue(i32),
     ArithmeticSymbol(ArithmeticSymbol),
     ArithmeticSymbolEqual(ArithmeticSymbolEqual),
@@ -17,9 +23,24 @@ pub enum Token<'a> {
     Keyword(Keyword),
 }
 
-impl<'a> fmt::Display for Token<'a> {
+impl Token {
+    /* Prints the token given the names of its program, e.g. for --emit=tokens */
+    pub fn show<'a>(&'a self, names: &'a Interner) -> ShowToken<'a> {
+        ShowToken { token: self, names }
+    }
+}
+
+pub struct ShowToken<'a> {
+    token: &'a Token,
+    names: &'a Interner,
+}
+
+impl fmt::Display for ShowToken<'_> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{self:?}")
+        match self.token {
+            Token::Identifier(name) => write!(f, "Identifier({})", self.names.name(*name)),
+            token => write!(f, "{token:?}"),
+        }
     }
 }
 
@@ -111,94 +132,183 @@ fn convert_digit(digit: &u8) -> Option<u32> {
     })
 }
 
-pub fn tokenize<'a>(
-    input_string: &'a [u8],
-    semantic_error: &mut bool,
-    tokens: &mut Vec<Token<'a>>,
-) -> Result<i32, i32> {
+/* A token together with the byte offsets of its first and one-past-last character */
+pub type SpannedToken = (usize, Token, usize);
+
+/* A lexical error. Out of range literals are only reported after parsing, as semantic errors. */
+#[derive(Debug, Clone)]
+pub enum LexError {
+    UnknownCharacter(Span),
+    UnterminatedComment(Span),
+    EmptyHexLiteral(Span),
+    HexLiteralTooLong(Span),
+    DecimalOutOfRange(Span),
+}
+
+impl LexError {
+    pub fn span(&self) -> Span {
+        match self {
+            LexError::UnknownCharacter(span)
+            | LexError::UnterminatedComment(span)
+            | LexError::EmptyHexLiteral(span)
+            | LexError::HexLiteralTooLong(span)
+            | LexError::DecimalOutOfRange(span) => *span,
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexError::UnknownCharacter(_) => error_codes::UNKNOWN_CHARACTER,
+            LexError::UnterminatedComment(_) => error_codes::UNTERMINATED_COMMENT,
+            LexError::EmptyHexLiteral(_) => error_codes::EMPTY_HEX_LITERAL,
+            LexError::HexLiteralTooLong(_) => error_codes::HEX_LITERAL_TOO_LONG,
+            LexError::DecimalOutOfRange(_) => error_codes::DECIMAL_OUT_OF_RANGE,
+        }
+    }
+
+    /* Whether the literal is well formed but its value does not fit into an int */
+    pub fn is_out_of_range(&self) -> bool {
+        matches!(
+            self,
+            LexError::HexLiteralTooLong(_) | LexError::DecimalOutOfRange(_)
+        )
+    }
+
+    pub fn message(&self, input: &[u8]) -> String {
+        let span = self.span();
+        let text = String::from_utf8_lossy(&input[span.start..span.end]);
+        match self {
+            LexError::UnknownCharacter(_) => {
+                format!("Unknown character '{}'.", text.escape_debug())
+            }
+            LexError::UnterminatedComment(_) => "Block comment is never closed.".to_owned(),
+            LexError::EmptyHexLiteral(_) => "Hexadecimal literal has no digits.".to_owned(),
+            LexError::HexLiteralTooLong(_) => {
+                format!("Hexadecimal literal {text} is too long, it does not fit into 32 bits.")
+            }
+            LexError::DecimalOutOfRange(_) => {
+                format!("Decimal literal {text} is out of range, the maximum is 2147483648.")
+            }
+        }
+    }
+}
+
+/* The byte at index i, or 0 past the end of the input */
+fn peek(input_string: &[u8], i: usize) -> u8 {
+    input_string.get(i).copied().unwrap_or(0)
+}
+
+/* The number of bytes of the UTF-8 encoded character starting with byte b */
+fn char_len(b: u8) -> usize {
+    match b {
+        0xC0..=0xDF => 2,
+        0xE0..=0xEF => 3,
+        0xF0..=0xF7 => 4,
+        _ => 1,
+    }
+}
+
+/* Tokenizes the whole input and interns its identifiers into names. Errors do not stop lexing, so
+ * that all of them can be reported. */
+pub fn tokenize(
+    input_string: &[u8],
+    errors: &mut Vec<LexError>,
+    tokens: &mut Vec<SpannedToken>,
+    names: &mut Interner,
+) {
     let end = input_string.len();
     let mut i = 0;
     loop {
         if i == end {
-            return Ok(0);
+            return;
         }
+        let start = i;
         let equals = b'=';
         match input_string[i] {
             b',' => {
-                tokens.push(Token::Comma);
+                tokens.push((i, Token::Comma, i + 1));
                 i += 1;
                 continue;
             }
             b'?' => {
-                tokens.push(Token::TernaryIf);
+                tokens.push((i, Token::TernaryIf, i + 1));
                 i += 1;
                 continue;
             }
             b':' => {
-                tokens.push(Token::TernaryThen);
+                tokens.push((i, Token::TernaryThen, i + 1));
                 i += 1;
                 continue;
             }
             b'+' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::PlusEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::PlusEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Plus));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Plus), i + 1));
                     i += 1;
                 }
                 continue;
             }
             b'-' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::MinusEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::MinusEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Minus));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Minus), i + 1));
                     i += 1;
                 }
                 continue;
             }
             b'*' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::MultEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::MultEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Mult));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Mult), i + 1));
                     i += 1;
                 }
                 continue;
             }
             b'/' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::DivEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::DivEqual),
+                        i + 2,
                     ));
                     i += 2;
                     continue;
                 }
-                if input_string[i + 1] == b'/' {
+                if peek(input_string, i + 1) == b'/' {
                     while i != end && input_string[i] != b'\n' {
                         i += 1;
                     }
                     continue;
-                } else if input_string[i + 1] == b'*' {
+                } else if peek(input_string, i + 1) == b'*' {
                     i += 2;
                     let mut open = 1;
                     while open > 0 {
                         if i + 1 >= end {
-                            return Err(42);
+                            errors.push(LexError::UnterminatedComment(Span::new(start, start + 2)));
+                            return;
                         }
-                        if input_string[i] == b'/' && input_string[i + 1] == b'*' {
+                        if input_string[i] == b'/' && peek(input_string, i + 1) == b'*' {
                             i += 1;
                             open += 1;
-                        } else if input_string[i] == b'*' && input_string[i + 1] == b'/' {
+                        } else if input_string[i] == b'*' && peek(input_string, i + 1) == b'/' {
                             i += 1;
                             open -= 1;
                         }
@@ -206,172 +316,200 @@ pub fn tokenize<'a>(
                     }
                     continue;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Div));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Div), i + 1));
                     i += 1;
                 }
                 continue;
             }
             b'%' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::ModEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::ModEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Mod));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Mod), i + 1));
                     i += 1;
                 }
                 continue;
             }
             b'=' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::DoubleEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::DoubleEqual),
+                        i + 2,
                     ));
                     i += 2;
                     continue;
                 } else {
-                    tokens.push(Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::Equal));
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::Equal),
+                        i + 1,
+                    ));
                     i += 1;
                     continue;
                 }
             }
             b'(' => {
-                tokens.push(Token::ParenthOpen);
+                tokens.push((i, Token::ParenthOpen, i + 1));
                 i += 1;
                 continue;
             }
             b')' => {
-                tokens.push(Token::ParenthClose);
+                tokens.push((i, Token::ParenthClose, i + 1));
                 i += 1;
                 continue;
             }
             b'{' => {
-                tokens.push(Token::BraceOpen);
+                tokens.push((i, Token::BraceOpen, i + 1));
                 i += 1;
                 continue;
             }
             b'}' => {
-                tokens.push(Token::BraceClose);
+                tokens.push((i, Token::BraceClose, i + 1));
                 i += 1;
                 continue;
             }
             b';' => {
-                tokens.push(Token::StatementEnd);
+                tokens.push((i, Token::StatementEnd, i + 1));
                 i += 1;
                 continue;
             }
             b'<' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::LessEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::LessEqual),
+                        i + 2,
                     ));
                     i += 2;
                     continue;
-                } else if input_string[i + 1] == b'<' {
-                    if input_string[i + 2] == equals {
-                        tokens.push(Token::ArithmeticSymbolEqual(
-                            ArithmeticSymbolEqual::LShiftEqual,
+                } else if peek(input_string, i + 1) == b'<' {
+                    if peek(input_string, i + 2) == equals {
+                        tokens.push((
+                            i,
+                            Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::LShiftEqual),
+                            i + 3,
                         ));
                         i += 3;
                     } else {
-                        tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::LShift));
+                        tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::LShift), i + 2));
                         i += 2;
                     }
                     continue;
                 }
 
-                tokens.push(Token::ArithmeticSymbolEqual(
-                    ArithmeticSymbolEqual::LessThan,
+                tokens.push((
+                    i,
+                    Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::LessThan),
+                    i + 1,
                 ));
                 i += 1;
                 continue;
             }
             b'>' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::GreaterEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::GreaterEqual),
+                        i + 2,
                     ));
                     i += 2;
                     continue;
-                } else if input_string[i + 1] == b'>' {
-                    if input_string[i + 2] == equals {
-                        tokens.push(Token::ArithmeticSymbolEqual(
-                            ArithmeticSymbolEqual::RShiftEqual,
+                } else if peek(input_string, i + 1) == b'>' {
+                    if peek(input_string, i + 2) == equals {
+                        tokens.push((
+                            i,
+                            Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::RShiftEqual),
+                            i + 3,
                         ));
                         i += 3;
                     } else {
-                        tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::RShift));
+                        tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::RShift), i + 2));
                         i += 2;
                     }
                     continue;
                 }
 
-                tokens.push(Token::ArithmeticSymbolEqual(
-                    ArithmeticSymbolEqual::GreaterThan,
+                tokens.push((
+                    i,
+                    Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::GreaterThan),
+                    i + 1,
                 ));
                 i += 1;
                 continue;
             }
             b'!' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::NotEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::NotEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Not));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Not), i + 1));
                     i += 1;
                     continue;
                 }
                 continue;
             }
             b'~' => {
-                tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::BitNot));
+                tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::BitNot), i + 1));
                 i += 1;
                 continue;
             }
             b'&' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::BitAndEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::BitAndEqual),
+                        i + 2,
                     ));
                     i += 2;
                     continue;
-                } else if input_string[i + 1] == b'&' {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::And));
+                } else if peek(input_string, i + 1) == b'&' {
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::And), i + 2));
                     i += 2;
                     continue;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::BitAnd));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::BitAnd), i + 1));
                     i += 1;
                     continue;
                 }
             }
             b'|' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::BitOrEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::BitOrEqual),
+                        i + 2,
                     ));
                     i += 2;
-                } else if input_string[i + 1] == b'|' {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::Or));
+                } else if peek(input_string, i + 1) == b'|' {
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::Or), i + 2));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::BitOr));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::BitOr), i + 1));
                     i += 1;
                     continue;
                 }
                 continue;
             }
             b'^' => {
-                if input_string[i + 1] == equals {
-                    tokens.push(Token::ArithmeticSymbolEqual(
-                        ArithmeticSymbolEqual::BitXorEqual,
+                if peek(input_string, i + 1) == equals {
+                    tokens.push((
+                        i,
+                        Token::ArithmeticSymbolEqual(ArithmeticSymbolEqual::BitXorEqual),
+                        i + 2,
                     ));
                     i += 2;
                 } else {
-                    tokens.push(Token::ArithmeticSymbol(ArithmeticSymbol::BitXor));
+                    tokens.push((i, Token::ArithmeticSymbol(ArithmeticSymbol::BitXor), i + 1));
                     i += 1;
                     continue;
                 }
@@ -401,154 +539,144 @@ pub fn tokenize<'a>(
                 i += word.len();
                 match word {
                     b"struct" => {
-                        tokens.push(Token::Keyword(Keyword::Struct));
+                        tokens.push((start, Token::Keyword(Keyword::Struct), i));
                         continue;
                     }
                     b"if" => {
-                        tokens.push(Token::Keyword(Keyword::If));
+                        tokens.push((start, Token::Keyword(Keyword::If), i));
                         continue;
                     }
                     b"else" => {
-                        tokens.push(Token::Keyword(Keyword::Else));
+                        tokens.push((start, Token::Keyword(Keyword::Else), i));
                         continue;
                     }
                     b"while" => {
-                        tokens.push(Token::Keyword(Keyword::While));
+                        tokens.push((start, Token::Keyword(Keyword::While), i));
                         continue;
                     }
                     b"for" => {
-                        tokens.push(Token::Keyword(Keyword::For));
+                        tokens.push((start, Token::Keyword(Keyword::For), i));
                         continue;
                     }
                     b"continue" => {
-                        tokens.push(Token::Keyword(Keyword::Continue));
+                        tokens.push((start, Token::Keyword(Keyword::Continue), i));
                         continue;
                     }
                     b"break" => {
-                        tokens.push(Token::Keyword(Keyword::Break));
+                        tokens.push((start, Token::Keyword(Keyword::Break), i));
                         continue;
                     }
                     b"return" => {
-                        tokens.push(Token::Keyword(Keyword::Return));
+                        tokens.push((start, Token::Keyword(Keyword::Return), i));
                         continue;
                     }
                     b"assert" => {
-                        tokens.push(Token::Keyword(Keyword::Assert));
+                        tokens.push((start, Token::Keyword(Keyword::Assert), i));
                         continue;
                     }
                     b"true" => {
-                        tokens.push(Token::Keyword(Keyword::True));
+                        tokens.push((start, Token::Keyword(Keyword::True), i));
                         continue;
                     }
                     b"false" => {
-                        tokens.push(Token::Keyword(Keyword::False));
+                        tokens.push((start, Token::Keyword(Keyword::False), i));
                         continue;
                     }
                     b"NULL" => {
-                        tokens.push(Token::Keyword(Keyword::Null));
+                        tokens.push((start, Token::Keyword(Keyword::Null), i));
                         continue;
                     }
                     b"print" => {
-                        tokens.push(Token::Keyword(Keyword::Print));
+                        tokens.push((start, Token::Keyword(Keyword::Print), i));
                         continue;
                     }
                     b"read" => {
-                        tokens.push(Token::Keyword(Keyword::Read));
+                        tokens.push((start, Token::Keyword(Keyword::Read), i));
                         continue;
                     }
                     b"alloc" => {
-                        tokens.push(Token::Keyword(Keyword::Alloc));
+                        tokens.push((start, Token::Keyword(Keyword::Alloc), i));
                         continue;
                     }
                     b"alloc_array" => {
-                        tokens.push(Token::Keyword(Keyword::AllocArray));
+                        tokens.push((start, Token::Keyword(Keyword::AllocArray), i));
                         continue;
                     }
                     b"int" => {
-                        tokens.push(Token::Keyword(Keyword::Int));
+                        tokens.push((start, Token::Keyword(Keyword::Int), i));
                         continue;
                     }
                     b"bool" => {
-                        tokens.push(Token::Keyword(Keyword::Bool));
+                        tokens.push((start, Token::Keyword(Keyword::Bool), i));
                         continue;
                     }
                     b"void" => {
-                        tokens.push(Token::Keyword(Keyword::Void));
+                        tokens.push((start, Token::Keyword(Keyword::Void), i));
                         continue;
                     }
                     b"char" => {
-                        tokens.push(Token::Keyword(Keyword::Char));
+                        tokens.push((start, Token::Keyword(Keyword::Char), i));
                         continue;
                     }
                     b"string" => {
-                        tokens.push(Token::Keyword(Keyword::String));
+                        tokens.push((start, Token::Keyword(Keyword::String), i));
                         continue;
                     }
                     b"flush" => {
-                        tokens.push(Token::Keyword(Keyword::Flush));
+                        tokens.push((start, Token::Keyword(Keyword::Flush), i));
                         continue;
                     }
                     _ => {
-                        tokens.push(Token::Identifier(word));
+                        tokens.push((
+                            start,
+                            Token::Identifier(names.intern(&String::from_utf8_lossy(word))),
+                            i,
+                        ));
                         continue;
                     }
                 }
             }
             b'0'..=b'9' => {
-                if input_string[i] == b'0' {
-                    i += 1;
-                    if input_string[i] == b'x' || input_string[i] == b'X' {
+                if input_string[i] == b'0' && matches!(peek(input_string, i + 1), b'x' | b'X') {
+                    i += 2;
+                    let mut hexval: u32 = 0;
+                    let mut too_long = false;
+                    while let Some(digit) = convert_digit(&peek(input_string, i)) {
+                        too_long |= hexval > 0x0FFF_FFFF;
+                        hexval = (hexval << 4) + digit;
                         i += 1;
-                        let mut temp_i = 0;
-                        let mut hexval: u32 = 0;
-                        while let Some(digit) = convert_digit(&input_string[i + temp_i]) {
-                            temp_i += 1;
-                            if temp_i > 8 {
-                                *semantic_error = true;
-                            }
-
-                            hexval = (hexval << 4) + digit;
-                            if i + temp_i >= end {
-                                break;
-                            }
-                        }
-                        if temp_i == 0 {
-                            return Err(42);
-                        }
-                        tokens.push(Token::NumericValue(hexval.cast_signed()));
-                        i += temp_i;
-                        continue;
-                    } else {
-                        tokens.push(Token::NumericValue(0));
-                        continue;
                     }
+                    if i == start + 2 {
+                        errors.push(LexError::EmptyHexLiteral(Span::new(start, i)));
+                    } else if too_long {
+                        errors.push(LexError::HexLiteralTooLong(Span::new(start, i)));
+                    }
+                    tokens.push((start, Token::NumericValue(hexval.cast_signed()), i));
+                } else if input_string[i] == b'0' {
+                    i += 1;
+                    tokens.push((start, Token::NumericValue(0), i));
                 } else {
                     let mut decval: u32 = 0;
-                    while let Some(digit) = convert_digit(&input_string[i]) {
-                        if digit > 9 {
-                            break;
-                        }
+                    let mut out_of_range = false;
+                    while let Some(digit) = convert_digit(&peek(input_string, i))
+                        && digit <= 9
+                    {
+                        decval = decval.saturating_mul(10).saturating_add(digit);
+                        /* 2147483648 wraps around, so that -2147483648 can be written */
+                        out_of_range |= decval > 0x8000_0000;
                         i += 1;
-
-                        if let Some(new_mul) = decval.checked_mul(10) {
-                            if let Some(new_add) = new_mul.checked_add(digit) {
-                                decval = new_add;
-                                if decval > 0x80000000 {
-                                    *semantic_error = true;
-                                }
-                                if i >= end {
-                                    break;
-                                }
-                                continue;
-                            }
-                        }
-                        *semantic_error = true;
                     }
-                    tokens.push(Token::NumericValue(decval.cast_signed()));
-                    continue;
+                    if out_of_range {
+                        errors.push(LexError::DecimalOutOfRange(Span::new(start, i)));
+                    }
+                    tokens.push((start, Token::NumericValue(decval.cast_signed()), i));
                 }
+                continue;
+            }
+            _ => {
+                i = (i + char_len(input_string[i])).min(end);
+                errors.push(LexError::UnknownCharacter(Span::new(start, i)));
             }
-            _ => return Err(42),
         }
     }
 }
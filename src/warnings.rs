@@ -0,0 +1,446 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::{
+    arena::Arena,
+    ast::{Call, Exp, ExpId, Var},
+    diagnostics::{Diagnostics, Span},
+    elaboration::{Abs, AbsId},
+    error_codes,
+    semantics::AbsFunction,
+    symbols::{self, DeclSet, Interner, Symbol},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warning {
+    UnusedVariable,
+    UnusedParameter,
+    UnreachableCode,
+    ConstantCondition,
+    UnreachableFunction,
+}
+
+const ALL_WARNINGS: [Warning; 5] = [
+    Warning::UnusedVariable,
+    Warning::UnusedParameter,
+    Warning::UnreachableCode,
+    Warning::ConstantCondition,
+    Warning::UnreachableFunction,
+];
+
+impl Warning {
+    /* The name used in -W<name> and -Wno-<name> */
+    pub fn name(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => "unused-variable",
+            Warning::UnusedParameter => "unused-parameter",
+            Warning::UnreachableCode => "unreachable-code",
+            Warning::ConstantCondition => "constant-condition",
+            Warning::UnreachableFunction => "unreachable-function",
+        }
+    }
+
+    pub fn code(&self) -> &'static str {
+        match self {
+            Warning::UnusedVariable => error_codes::UNUSED_VARIABLE,
+            Warning::UnusedParameter => error_codes::UNUSED_PARAMETER,
+            Warning::UnreachableCode => error_codes::UNREACHABLE_CODE,
+            Warning::ConstantCondition => error_codes::CONSTANT_CONDITION,
+            Warning::UnreachableFunction => error_codes::UNREACHABLE_FUNCTION,
+        }
+    }
+}
+
+/* Every warning is off until it is enabled with -W<name> or -Wall */
+#[derive(Debug, Clone, Default)]
+pub struct WarningOptions {
+    enabled: Vec<Warning>,
+    pub werror: bool,
+}
+
+impl WarningOptions {
+    /* Applies a -W flag without its "-W" prefix, e.g. "error", "all" or "no-unused-variable".
+     * Returns false if the warning is unknown. */
+    pub fn apply_flag(&mut self, flag: &str) -> bool {
+        let (name, enable) = match flag.strip_prefix("no-") {
+            Some(name) => (name, false),
+            None => (flag, true),
+        };
+        let warnings: Vec<Warning> = match name {
+            "error" => {
+                self.werror = enable;
+                return true;
+            }
+            "all" => ALL_WARNINGS.to_vec(),
+            _ => match ALL_WARNINGS.iter().find(|w| w.name() == name) {
+                Some(w) => vec![*w],
+                None => return false,
+            },
+        };
+        self.enabled.retain(|w| !warnings.contains(w));
+        if enable {
+            self.enabled.extend(warnings);
+        }
+        true
+    }
+
+    pub fn is_enabled(&self, warning: Warning) -> bool {
+        self.enabled.contains(&warning)
+    }
+}
+
+struct WarningSink<'d> {
+    diags: &'d mut Diagnostics,
+    options: &'d WarningOptions,
+    names: &'d Interner,
+}
+
+impl<'d> WarningSink<'d> {
+    fn is_enabled(&self, warning: Warning) -> bool {
+        self.options.is_enabled(warning)
+    }
+
+    fn name(&self, symbol: Symbol) -> &'d str {
+        self.names.name(symbol)
+    }
+
+    /* Reports a warning, or an error if -Werror is given */
+    fn report(&mut self, warning: Warning, span: Span, message: &str) {
+        let message = format!("{message} [-W{}]", warning.name());
+        if self.options.werror {
+            self.diags.error(warning.code(), span, &message);
+        } else {
+            self.diags.warning(warning.code(), span, &message);
+        }
+    }
+}
+
+/* Reports unused variables and parameters, unreachable statements, constant loop conditions
+ * and functions that are never called from main. Only the enabled warnings are looked for. funcs
+ * are the functions of a unit, exps its expressions and names its symbols. reachable comes from reachable_functions
+ * and is None if the program has no main, e.g. when a unit is compiled on its own. */
+pub fn check_warnings(
+    diags: &mut Diagnostics,
+    options: &WarningOptions,
+    funcs: &[AbsFunction],
+    exps: &Arena<Exp>,
+    names: &Interner,
+    reachable: Option<&[Symbol]>,
+) {
+    let mut sink = WarningSink {
+        diags,
+        options,
+        names,
+    };
+    for f in funcs.iter() {
+        let body = Body {
+            nodes: &f.nodes,
+            exps,
+        };
+        if sink.is_enabled(Warning::UnusedParameter) || sink.is_enabled(Warning::UnusedVariable) {
+            let reads = body.reads(f.body);
+            if sink.is_enabled(Warning::UnusedParameter) {
+                for (var, span) in f.params.iter().zip(f.param_spans.iter()) {
+                    if !var.id.is_some_and(|id| reads.contains(id)) {
+                        sink.report(
+                            Warning::UnusedParameter,
+                            *span,
+                            &format!("Parameter \"{}\" is never used.", sink.name(var.name)),
+                        );
+                    }
+                }
+            }
+            if sink.is_enabled(Warning::UnusedVariable) {
+                body.unused_variables(&mut sink, &reads, f.body);
+            }
+        }
+        if sink.is_enabled(Warning::UnreachableCode) {
+            body.unreachable_code(&mut sink, f.body);
+        }
+        if sink.is_enabled(Warning::ConstantCondition) {
+            body.constant_conditions(&mut sink, f.body);
+        }
+    }
+    if let Some(reachable) = reachable
+        && sink.is_enabled(Warning::UnreachableFunction)
+    {
+        unreachable_functions(&mut sink, funcs, reachable);
+    }
+}
+
+/* The statements of a function and the expressions of its unit */
+#[derive(Clone, Copy)]
+struct Body<'b> {
+    nodes: &'b Arena<Abs>,
+    exps: &'b Arena<Exp>,
+}
+
+impl Body<'_> {
+    /* Calls f for every expression directly contained in a statement */
+    fn visit_exps(self, abs: AbsId, f: &mut impl FnMut(&Exp)) {
+        let exps = self.exps;
+        match &self.nodes[abs] {
+            Abs::ASGN(_, exp, _) | Abs::RET(exp, _) | Abs::EXP(exp) => exps.visit(*exp, f),
+            Abs::WHILE(exp, body, _) => {
+                exps.visit(*exp, f);
+                self.visit_exps(*body, f);
+            }
+            Abs::IF(exp, abs1, abs2, _) => {
+                exps.visit(*exp, f);
+                self.visit_exps(*abs1, f);
+                self.visit_exps(*abs2, f);
+            }
+            Abs::DECL(_, _, body, _) | Abs::FOR(body, _) => self.visit_exps(*body, f),
+            Abs::SEQ(items) => items.iter().for_each(|abs| self.visit_exps(*abs, f)),
+            Abs::CALL(_, args, _) => args.iter().for_each(|exp| exps.visit(*exp, f)),
+            Abs::CONT(_) | Abs::BRK(_) => (),
+        }
+    }
+
+    /* The variables whose value is read anywhere in abs. Assignments do not count as reads. */
+    fn reads(self, abs: AbsId) -> DeclSet {
+        let mut reads = DeclSet::new();
+        self.visit_exps(abs, &mut |exp| {
+            if let Exp::Ident(Var { id: Some(id), .. }, _) = exp {
+                reads.insert(*id);
+            }
+        });
+        reads
+    }
+
+    /* Every declaration has an id of its own, so a variable is read in its scope if it is read
+     * anywhere in the function */
+    fn unused_variables(self, sink: &mut WarningSink, reads: &DeclSet, abs: AbsId) {
+        match &self.nodes[abs] {
+            Abs::DECL(var, _, body, span) => {
+                if !var.id.is_some_and(|id| reads.contains(id)) {
+                    sink.report(
+                        Warning::UnusedVariable,
+                        *span,
+                        &format!("Variable \"{}\" is never read.", sink.name(var.name)),
+                    );
+                }
+                self.unused_variables(sink, reads, *body);
+            }
+            Abs::WHILE(_, body, _) | Abs::FOR(body, _) => self.unused_variables(sink, reads, *body),
+            Abs::IF(_, abs1, abs2, _) => {
+                self.unused_variables(sink, reads, *abs1);
+                self.unused_variables(sink, reads, *abs2);
+            }
+            Abs::SEQ(items) => items
+                .iter()
+                .for_each(|abs| self.unused_variables(sink, reads, *abs)),
+            Abs::ASGN(..)
+            | Abs::RET(..)
+            | Abs::EXP(..)
+            | Abs::CALL(..)
+            | Abs::CONT(_)
+            | Abs::BRK(_) => {}
+        }
+    }
+
+    /* The span of the first statement in abs, empty blocks have none */
+    fn first_span(self, abs: AbsId) -> Option<Span> {
+        match &self.nodes[abs] {
+            Abs::SEQ(items) => items.iter().find_map(|abs| self.first_span(*abs)),
+            abs => abs.span(self.exps),
+        }
+    }
+
+    /* Reports the unreachable statements in abs and returns whether control never continues
+     * after it, because every path returns, breaks or continues */
+    fn unreachable_code(self, sink: &mut WarningSink, abs: AbsId) -> bool {
+        match &self.nodes[abs] {
+            Abs::SEQ(items) => self.unreachable_in_seq(sink, items),
+            Abs::DECL(_, _, body, _) => self.unreachable_code(sink, *body),
+            Abs::WHILE(_, body, _) => {
+                self.unreachable_code(sink, *body);
+                false
+            }
+            Abs::IF(_, abs1, abs2, _) => {
+                let jumps1 = self.unreachable_code(sink, *abs1);
+                let jumps2 = self.unreachable_code(sink, *abs2);
+                jumps1 && jumps2
+            }
+            Abs::FOR(body, _) => {
+                self.unreachable_in_for(sink, *body);
+                false
+            }
+            Abs::RET(..) | Abs::BRK(_) | Abs::CONT(_) => true,
+            Abs::ASGN(..) | Abs::EXP(..) | Abs::CALL(..) => false,
+        }
+    }
+
+    /* Only the first unreachable statement of a sequence is reported */
+    fn unreachable_in_seq(self, sink: &mut WarningSink, items: &[AbsId]) -> bool {
+        for (i, abs) in items.iter().enumerate() {
+            if self.unreachable_code(sink, *abs) {
+                if let Some(span) = items[i + 1..].iter().find_map(|abs| self.first_span(*abs)) {
+                    sink.report(
+                        Warning::UnreachableCode,
+                        span,
+                        "Statement is never executed.",
+                    );
+                }
+                return true;
+            }
+        }
+        false
+    }
+
+    /* A for loop is elaborated into its initializer, condition, body and step. The step is
+     * always last and is reached by continue, so it is never unreachable. */
+    fn unreachable_in_for(self, sink: &mut WarningSink, abs: AbsId) {
+        match &self.nodes[abs] {
+            Abs::DECL(_, _, body, _) => self.unreachable_in_for(sink, *body),
+            Abs::SEQ(items) if !items.is_empty() => {
+                self.unreachable_in_seq(sink, &items[..items.len() - 1]);
+            }
+            _ => {
+                self.unreachable_code(sink, abs);
+            }
+        }
+    }
+
+    /* A literal true is the usual way to write a loop that ends through break or return, so it
+     * does not count as constant */
+    fn is_constant(self, exp: ExpId) -> bool {
+        if let Exp::True(_) = self.exps[exp] {
+            return false;
+        }
+        let mut constant = true;
+        self.exps.visit(exp, &mut |e| {
+            constant &= !matches!(e, Exp::Ident(..) | Exp::Call(..));
+        });
+        constant
+    }
+
+    fn constant_conditions(self, sink: &mut WarningSink, abs: AbsId) {
+        let nodes = self.nodes;
+        match &nodes[abs] {
+            Abs::WHILE(exp, body, _) => {
+                if self.is_constant(*exp) {
+                    sink.report(
+                        Warning::ConstantCondition,
+                        self.exps.span(*exp),
+                        "Loop condition is constant.",
+                    );
+                }
+                self.constant_conditions(sink, *body);
+            }
+            Abs::FOR(body, _) => {
+                /* The condition is the first expression statement of the loop */
+                let mut seq = &nodes[*body];
+                while let Abs::DECL(_, _, inner, _) = seq {
+                    seq = &nodes[*inner];
+                }
+                if let Abs::SEQ(items) = seq
+                    && let Some(Abs::EXP(exp)) = items
+                        .iter()
+                        .map(|abs| &nodes[*abs])
+                        .find(|abs| matches!(abs, Abs::EXP(_)))
+                    && self.is_constant(*exp)
+                {
+                    sink.report(
+                        Warning::ConstantCondition,
+                        self.exps.span(*exp),
+                        "Loop condition is constant.",
+                    );
+                }
+                self.constant_conditions(sink, *body);
+            }
+            Abs::DECL(_, _, body, _) => self.constant_conditions(sink, *body),
+            Abs::IF(_, abs1, abs2, _) => {
+                self.constant_conditions(sink, *abs1);
+                self.constant_conditions(sink, *abs2);
+            }
+            Abs::SEQ(items) => items
+                .iter()
+                .for_each(|abs| self.constant_conditions(sink, *abs)),
+            Abs::ASGN(..)
+            | Abs::RET(..)
+            | Abs::EXP(..)
+            | Abs::CALL(..)
+            | Abs::CONT(_)
+            | Abs::BRK(_) => {}
+        }
+    }
+
+    /* The calls that are statements, those in expressions are found by visit_exps */
+    fn call_statements(self, abs: AbsId, names: &mut Vec<Symbol>) {
+        match &self.nodes[abs] {
+            Abs::CALL(name, ..) => names.push(*name),
+            Abs::WHILE(_, body, _) | Abs::DECL(_, _, body, _) | Abs::FOR(body, _) => {
+                self.call_statements(*body, names)
+            }
+            Abs::IF(_, abs1, abs2, _) => {
+                self.call_statements(*abs1, names);
+                self.call_statements(*abs2, names);
+            }
+            Abs::SEQ(items) => items
+                .iter()
+                .for_each(|abs| self.call_statements(*abs, names)),
+            Abs::ASGN(..) | Abs::RET(..) | Abs::EXP(..) | Abs::CONT(_) | Abs::BRK(_) => {}
+        }
+    }
+
+    fn calls(self, abs: AbsId, names: &mut Vec<Symbol>) {
+        self.call_statements(abs, names);
+        self.visit_exps(abs, &mut |exp| {
+            if let Exp::Call(Call::Func(name, _), _) = exp {
+                names.push(*name);
+            }
+        });
+    }
+}
+
+/* Collects the names of all functions f calls, exps are the expressions of its unit */
+pub(crate) fn calls(f: &AbsFunction, exps: &Arena<Exp>, names: &mut Vec<Symbol>) {
+    let body = Body {
+        nodes: &f.nodes,
+        exps,
+    };
+    body.calls(f.body, names);
+}
+
+/* The functions main calls directly or indirectly, including main itself. funcs are the
+ * functions of every unit of the program, each with the expressions of its unit. Returns None if
+ * none of them is main. */
+pub fn reachable_functions(funcs: &[(&AbsFunction, &Arena<Exp>)]) -> Option<Vec<Symbol>> {
+    let by_name: HashMap<Symbol, (&AbsFunction, &Arena<Exp>)> = funcs
+        .iter()
+        .map(|(f, exps)| (f.name, (*f, *exps)))
+        .collect();
+    if !by_name.contains_key(&symbols::MAIN) {
+        return None;
+    }
+    let mut reached = vec![symbols::MAIN];
+    let mut queue = VecDeque::from([symbols::MAIN]);
+    while let Some(name) = queue.pop_front() {
+        let mut callees = Vec::new();
+        if let Some((f, exps)) = by_name.get(&name) {
+            calls(f, exps, &mut callees);
+        }
+        for callee in callees {
+            if !reached.contains(&callee) {
+                reached.push(callee);
+                queue.push_back(callee);
+            }
+        }
+    }
+    Some(reached)
+}
+
+fn unreachable_functions(sink: &mut WarningSink, funcs: &[AbsFunction], reachable: &[Symbol]) {
+    for f in funcs.iter() {
+        if !reachable.contains(&f.name) {
+            sink.report(
+                Warning::UnreachableFunction,
+                f.span,
+                &format!(
+                    "Function \"{}\" is never called from main.",
+                    sink.name(f.name)
+                ),
+            );
+        }
+    }
+}
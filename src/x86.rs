@@ -0,0 +1,748 @@
+use std::{collections::HashMap, fmt};
+
+/* The instructions instruction selection chooses from. They are printed in Intel syntax for the
+ * assembler, or encoded into machine code by encode for the built-in assembler. Operands are 32
+ * bit unless an instruction says otherwise. */
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    /* A jump target local to the unit */
+    Label(String),
+    Mov(Operand, Operand),
+    Alu(AluOp, Operand, Operand),
+    Unary(UnaryOp, Operand),
+    /* Sign extends eax into edx for idiv */
+    Cdq,
+    /* Sets al to the condition of the last comparison */
+    Set(Cond),
+    /* movzx eax, al */
+    MovzxAl,
+    /* Shifts eax by cl */
+    Shift(ShiftOp),
+    Push(Reg),
+    Pop(Reg),
+    /* 64 bit operations, for the stack pointer */
+    Alu64(AluOp, Reg, i32),
+    Mov64(Reg, Reg),
+    Mov64Imm(Reg, i32),
+    /* Loads a 64 bit global of the C library relative to rip, e.g. stdout */
+    LoadGlobal(Reg, String),
+    Call(String),
+    Jmp(String),
+    Je(String),
+    Ret,
+    Syscall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+const REGS: [Reg; 16] = [
+    Reg::Rax,
+    Reg::Rcx,
+    Reg::Rdx,
+    Reg::Rbx,
+    Reg::Rsp,
+    Reg::Rbp,
+    Reg::Rsi,
+    Reg::Rdi,
+    Reg::R8,
+    Reg::R9,
+    Reg::R10,
+    Reg::R11,
+    Reg::R12,
+    Reg::R13,
+    Reg::R14,
+    Reg::R15,
+];
+
+const NAMES_64: [&str; 16] = [
+    "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11", "r12", "r13",
+    "r14", "r15",
+];
+
+const NAMES_32: [&str; 16] = [
+    "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d", "r12d",
+    "r13d", "r14d", "r15d",
+];
+
+impl Reg {
+    /* The number of the register in the encoding of instructions */
+    fn number(self) -> u8 {
+        self as u8
+    }
+
+    pub fn name_64(self) -> &'static str {
+        NAMES_64[self as usize]
+    }
+
+    pub fn name_32(self) -> &'static str {
+        NAMES_32[self as usize]
+    }
+
+    fn parse_64(name: &str) -> Option<Reg> {
+        NAMES_64.iter().position(|n| *n == name).map(|i| REGS[i])
+    }
+
+    fn parse_32(name: &str) -> Option<Reg> {
+        NAMES_32.iter().position(|n| *n == name).map(|i| REGS[i])
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operand {
+    Reg(Reg),
+    Imm(i32),
+    /* The spill slot at this many bytes below rsp */
+    Stack(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AluOp {
+    Add,
+    Sub,
+    Imul,
+    And,
+    Or,
+    Xor,
+    Cmp,
+}
+
+const ALU_OPS: [AluOp; 7] = [
+    AluOp::Add,
+    AluOp::Sub,
+    AluOp::Imul,
+    AluOp::And,
+    AluOp::Or,
+    AluOp::Xor,
+    AluOp::Cmp,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+    Idiv,
+}
+
+const UNARY_OPS: [UnaryOp; 3] = [UnaryOp::Neg, UnaryOp::Not, UnaryOp::Idiv];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cond {
+    E,
+    Ne,
+    L,
+    Le,
+    G,
+    Ge,
+}
+
+const CONDS: [Cond; 6] = [Cond::E, Cond::Ne, Cond::L, Cond::Le, Cond::G, Cond::Ge];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShiftOp {
+    Sal,
+    Sar,
+}
+
+const SHIFT_OPS: [ShiftOp; 2] = [ShiftOp::Sal, ShiftOp::Sar];
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Operand::Reg(reg) => write!(f, "{}", reg.name_32()),
+            Operand::Imm(val) => write!(f, "{val}"),
+            Operand::Stack(offset) => write!(f, "DWORD PTR [rsp-{offset}]"),
+        }
+    }
+}
+
+impl fmt::Display for AluOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AluOp::Add => "add",
+            AluOp::Sub => "sub",
+            AluOp::Imul => "imul",
+            AluOp::And => "and",
+            AluOp::Or => "or",
+            AluOp::Xor => "xor",
+            AluOp::Cmp => "cmp",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for UnaryOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            UnaryOp::Neg => "neg",
+            UnaryOp::Not => "not",
+            UnaryOp::Idiv => "idiv",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Cond {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Cond::E => "e",
+            Cond::Ne => "ne",
+            Cond::L => "l",
+            Cond::Le => "le",
+            Cond::G => "g",
+            Cond::Ge => "ge",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for ShiftOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ShiftOp::Sal => "sal",
+            ShiftOp::Sar => "sar",
+        };
+        write!(f, "{name}")
+    }
+}
+
+impl fmt::Display for Inst {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Inst::Label(label) => write!(f, "{label}:"),
+            Inst::Mov(dest, src) => write!(f, "mov {dest}, {src}"),
+            Inst::Alu(op, dest, src) => write!(f, "{op} {dest}, {src}"),
+            Inst::Unary(op, operand) => write!(f, "{op} {operand}"),
+            Inst::Cdq => write!(f, "cdq"),
+            Inst::Set(cond) => write!(f, "set{cond} al"),
+            Inst::MovzxAl => write!(f, "movzx eax, al"),
+            Inst::Shift(op) => write!(f, "{op} eax, cl"),
+            Inst::Push(reg) => write!(f, "push {}", reg.name_64()),
+            Inst::Pop(reg) => write!(f, "pop {}", reg.name_64()),
+            Inst::Alu64(op, reg, val) => write!(f, "{op} {}, {val}", reg.name_64()),
+            Inst::Mov64(dest, src) => write!(f, "mov {}, {}", dest.name_64(), src.name_64()),
+            Inst::Mov64Imm(reg, val) => write!(f, "mov {}, 0x{val:X}", reg.name_64()),
+            Inst::LoadGlobal(reg, global) => {
+                write!(f, "mov {}, QWORD PTR {global}[rip]", reg.name_64())
+            }
+            Inst::Call(function) => write!(f, "call {function}"),
+            Inst::Jmp(label) => write!(f, "jmp {label}"),
+            Inst::Je(label) => write!(f, "je {label}"),
+            Inst::Ret => write!(f, "ret"),
+            Inst::Syscall => write!(f, "syscall"),
+        }
+    }
+}
+
+fn parse_operand(text: &str) -> Option<Operand> {
+    if let Some(offset) = text
+        .strip_prefix("DWORD PTR [rsp-")
+        .and_then(|t| t.strip_suffix(']'))
+    {
+        return Some(Operand::Stack(offset.parse().ok()?));
+    }
+    match Reg::parse_32(text) {
+        Some(reg) => Some(Operand::Reg(reg)),
+        None => Some(Operand::Imm(text.parse().ok()?)),
+    }
+}
+
+/* Reads an instruction back from its text, e.g. from the cache */
+pub fn parse(line: &str) -> Option<Inst> {
+    if let Some(label) = line.strip_suffix(':') {
+        return Some(Inst::Label(label.to_owned()));
+    }
+    let (mnemonic, operands) = line.split_once(' ').unwrap_or((line, ""));
+    let operands: Vec<&str> = match operands {
+        "" => Vec::new(),
+        operands => operands.split(", ").collect(),
+    };
+    let alu = ALU_OPS.into_iter().find(|op| op.to_string() == mnemonic);
+    let unary = UNARY_OPS.into_iter().find(|op| op.to_string() == mnemonic);
+    let shift = SHIFT_OPS.into_iter().find(|op| op.to_string() == mnemonic);
+    let cond = CONDS
+        .into_iter()
+        .find(|cond| mnemonic.strip_prefix("set") == Some(&cond.to_string()));
+    let inst = match (mnemonic, &operands[..]) {
+        ("cdq", []) => Inst::Cdq,
+        ("ret", []) => Inst::Ret,
+        ("syscall", []) => Inst::Syscall,
+        ("movzx", ["eax", "al"]) => Inst::MovzxAl,
+        ("push", [reg]) => Inst::Push(Reg::parse_64(reg)?),
+        ("pop", [reg]) => Inst::Pop(Reg::parse_64(reg)?),
+        ("call", [function]) => Inst::Call((*function).to_owned()),
+        ("jmp", [label]) => Inst::Jmp((*label).to_owned()),
+        ("je", [label]) => Inst::Je((*label).to_owned()),
+        ("mov", [dest, src]) => match Reg::parse_64(dest) {
+            Some(dest) => {
+                if let Some(src) = Reg::parse_64(src) {
+                    Inst::Mov64(dest, src)
+                } else if let Some(val) = src.strip_prefix("0x") {
+                    Inst::Mov64Imm(dest, i32::from_str_radix(val, 16).ok()?)
+                } else {
+                    let global = src.strip_prefix("QWORD PTR ")?.strip_suffix("[rip]")?;
+                    Inst::LoadGlobal(dest, global.to_owned())
+                }
+            }
+            None => Inst::Mov(parse_operand(dest)?, parse_operand(src)?),
+        },
+        (_, [dest, src]) if alu.is_some() => match Reg::parse_64(dest) {
+            Some(dest) => Inst::Alu64(alu?, dest, src.parse().ok()?),
+            None => Inst::Alu(alu?, parse_operand(dest)?, parse_operand(src)?),
+        },
+        (_, [operand]) if unary.is_some() => Inst::Unary(unary?, parse_operand(operand)?),
+        (_, ["al"]) if cond.is_some() => Inst::Set(cond?),
+        (_, ["eax", "cl"]) if shift.is_some() => Inst::Shift(shift?),
+        _ => return None,
+    };
+    Some(inst)
+}
+
+/* How the linker patches a reference to a symbol */
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RelocationKind {
+    /* The address relative to the end of the field */
+    Pc32,
+    /* The same for a function, which may be reached through the PLT */
+    Plt32,
+}
+
+/* A reference to a symbol the linker fills in: the 4 byte field at offset in the code */
+#[derive(Debug, Clone)]
+pub struct Relocation {
+    pub offset: usize,
+    pub symbol: String,
+    pub kind: RelocationKind,
+}
+
+/* Machine code with the offsets of the symbols it defines and the references it leaves to the
+ * linker */
+#[derive(Debug, Default)]
+pub struct Code {
+    pub bytes: Vec<u8>,
+    pub symbols: Vec<(String, usize)>,
+    pub relocations: Vec<Relocation>,
+    labels: HashMap<String, usize>,
+    /* Jumps to labels that are patched once every label is known */
+    jumps: Vec<(usize, String)>,
+}
+
+impl Code {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /* Defines a global symbol at the current offset */
+    pub fn symbol(&mut self, name: &str) {
+        self.symbols.push((name.to_owned(), self.bytes.len()));
+    }
+
+    /* Resolves the jumps to the labels. Every label is local to the code. */
+    pub fn finish(&mut self) {
+        for (offset, label) in self.jumps.drain(..) {
+            let target = self.labels[&label];
+            let relative = target as i64 - (offset as i64 + 4);
+            self.bytes[offset..offset + 4].copy_from_slice(&(relative as i32).to_le_bytes());
+        }
+    }
+
+    fn imm32(&mut self, val: i32) {
+        self.bytes.extend(val.to_le_bytes());
+    }
+
+    /* A 4 byte field for the linker or for a jump */
+    fn field(&mut self) -> usize {
+        self.imm32(0);
+        self.bytes.len() - 4
+    }
+
+    /* The REX prefix, left out if no field needs it. w selects 64 bit operands, reg and rm are
+     * the register numbers that go into the ModRM byte. */
+    fn rex(&mut self, w: bool, reg: u8, rm: u8) {
+        let rex = 0x40 | u8::from(w) << 3 | (reg >> 3) << 2 | rm >> 3;
+        if rex != 0x40 {
+            self.bytes.push(rex);
+        }
+    }
+
+    /* An instruction with a ModRM operand: opcode, reg field and register or spill slot */
+    fn modrm(&mut self, w: bool, opcode: &[u8], reg: u8, rm: Operand) {
+        let rm_number = match rm {
+            Operand::Reg(r) => r.number(),
+            _ => Reg::Rsp.number(),
+        };
+        self.rex(w, reg, rm_number);
+        self.bytes.extend(opcode);
+        let reg = (reg & 7) << 3;
+        match rm {
+            Operand::Reg(r) => self.bytes.push(0xC0 | reg | (r.number() & 7)),
+            /* rsp as base needs a SIB byte */
+            Operand::Stack(0) => self.bytes.extend([reg | 0x04, 0x24]),
+            Operand::Stack(offset) if offset <= 128 => {
+                self.bytes
+                    .extend([0x40 | reg | 0x04, 0x24, (offset as i8).wrapping_neg() as u8])
+            }
+            Operand::Stack(offset) => {
+                self.bytes.extend([0x80 | reg | 0x04, 0x24]);
+                self.imm32(-(offset as i32));
+            }
+            Operand::Imm(_) => panic!("an immediate is not a ModRM operand"),
+        }
+    }
+
+    /* An ALU instruction with an immediate, the short form for values that fit a byte */
+    fn alu_imm(&mut self, w: bool, extension: u8, rm: Operand, val: i32) {
+        match i8::try_from(val) {
+            Ok(byte) => {
+                self.modrm(w, &[0x83], extension, rm);
+                self.bytes.push(byte as u8);
+            }
+            Err(_) => {
+                self.modrm(w, &[0x81], extension, rm);
+                self.imm32(val);
+            }
+        }
+    }
+
+    pub fn encode(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Label(label) => {
+                self.labels.insert(label.clone(), self.bytes.len());
+            }
+            Inst::Mov(dest, src) => match (*dest, *src) {
+                (Operand::Reg(dest), Operand::Imm(val)) => {
+                    self.rex(false, 0, dest.number());
+                    self.bytes.push(0xB8 + (dest.number() & 7));
+                    self.imm32(val);
+                }
+                (dest, Operand::Imm(val)) => {
+                    self.modrm(false, &[0xC7], 0, dest);
+                    self.imm32(val);
+                }
+                (dest, Operand::Reg(src)) => self.modrm(false, &[0x89], src.number(), dest),
+                (Operand::Reg(dest), src) => self.modrm(false, &[0x8B], dest.number(), src),
+                _ => panic!("{inst} cannot be encoded"),
+            },
+            Inst::Alu(AluOp::Imul, dest, src) => match (*dest, *src) {
+                (Operand::Reg(dest), Operand::Imm(val)) => match i8::try_from(val) {
+                    Ok(byte) => {
+                        self.modrm(false, &[0x6B], dest.number(), Operand::Reg(dest));
+                        self.bytes.push(byte as u8);
+                    }
+                    Err(_) => {
+                        self.modrm(false, &[0x69], dest.number(), Operand::Reg(dest));
+                        self.imm32(val);
+                    }
+                },
+                (Operand::Reg(dest), src) => self.modrm(false, &[0x0F, 0xAF], dest.number(), src),
+                _ => panic!("{inst} cannot be encoded"),
+            },
+            Inst::Alu(op, dest, src) => {
+                /* The opcodes with a register source and the extension of the immediate forms */
+                let (opcode, extension) = match op {
+                    AluOp::Add => (0x01, 0),
+                    AluOp::Or => (0x09, 1),
+                    AluOp::And => (0x21, 4),
+                    AluOp::Sub => (0x29, 5),
+                    AluOp::Xor => (0x31, 6),
+                    AluOp::Cmp => (0x39, 7),
+                    AluOp::Imul => unreachable!(),
+                };
+                match (*dest, *src) {
+                    (Operand::Imm(_), _) => panic!("{inst} cannot be encoded"),
+                    (dest, Operand::Imm(val)) => self.alu_imm(false, extension, dest, val),
+                    (dest, Operand::Reg(src)) => self.modrm(false, &[opcode], src.number(), dest),
+                    (Operand::Reg(dest), src) => {
+                        self.modrm(false, &[opcode + 2], dest.number(), src)
+                    }
+                    _ => panic!("{inst} cannot be encoded"),
+                }
+            }
+            Inst::Unary(op, operand) => {
+                let extension = match op {
+                    UnaryOp::Not => 2,
+                    UnaryOp::Neg => 3,
+                    UnaryOp::Idiv => 7,
+                };
+                self.modrm(false, &[0xF7], extension, *operand);
+            }
+            Inst::Cdq => self.bytes.push(0x99),
+            Inst::Set(cond) => {
+                let code = match cond {
+                    Cond::E => 0x4,
+                    Cond::Ne => 0x5,
+                    Cond::L => 0xC,
+                    Cond::Ge => 0xD,
+                    Cond::Le => 0xE,
+                    Cond::G => 0xF,
+                };
+                self.bytes.extend([0x0F, 0x90 + code, 0xC0]);
+            }
+            Inst::MovzxAl => self.bytes.extend([0x0F, 0xB6, 0xC0]),
+            Inst::Shift(op) => {
+                let extension = match op {
+                    ShiftOp::Sal => 4,
+                    ShiftOp::Sar => 7,
+                };
+                self.modrm(false, &[0xD3], extension, Operand::Reg(Reg::Rax));
+            }
+            Inst::Push(reg) => {
+                self.rex(false, 0, reg.number());
+                self.bytes.push(0x50 + (reg.number() & 7));
+            }
+            Inst::Pop(reg) => {
+                self.rex(false, 0, reg.number());
+                self.bytes.push(0x58 + (reg.number() & 7));
+            }
+            Inst::Alu64(op, reg, val) => {
+                let extension = match op {
+                    AluOp::Add => 0,
+                    AluOp::Sub => 5,
+                    _ => panic!("{inst} cannot be encoded"),
+                };
+                self.alu_imm(true, extension, Operand::Reg(*reg), *val);
+            }
+            Inst::Mov64(dest, src) => self.modrm(true, &[0x89], src.number(), Operand::Reg(*dest)),
+            Inst::Mov64Imm(reg, val) => {
+                self.modrm(true, &[0xC7], 0, Operand::Reg(*reg));
+                self.imm32(*val);
+            }
+            Inst::LoadGlobal(reg, global) => {
+                self.rex(true, reg.number(), 0);
+                /* mod 00 and rm 101 address relative to rip */
+                self.bytes.extend([0x8B, (reg.number() & 7) << 3 | 0x05]);
+                let offset = self.field();
+                self.relocations.push(Relocation {
+                    offset,
+                    symbol: global.clone(),
+                    kind: RelocationKind::Pc32,
+                });
+            }
+            Inst::Call(function) => {
+                self.bytes.push(0xE8);
+                let offset = self.field();
+                self.relocations.push(Relocation {
+                    offset,
+                    symbol: function.clone(),
+                    kind: RelocationKind::Plt32,
+                });
+            }
+            Inst::Jmp(label) => {
+                self.bytes.push(0xE9);
+                let offset = self.field();
+                self.jumps.push((offset, label.clone()));
+            }
+            Inst::Je(label) => {
+                self.bytes.extend([0x0F, 0x84]);
+                let offset = self.field();
+                self.jumps.push((offset, label.clone()));
+            }
+            Inst::Ret => self.bytes.push(0xC3),
+            Inst::Syscall => self.bytes.extend([0x0F, 0x05]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(inst: &Inst) -> Vec<u8> {
+        let mut code = Code::new();
+        code.encode(inst);
+        code.finish();
+        code.bytes
+    }
+
+    /* Every instruction must encode to the bytes GNU as makes of its text, and the text must read
+     * back as the instruction */
+    fn check(cases: &[(Inst, &[u8])]) {
+        for (inst, bytes) in cases {
+            assert_eq!(encoded(inst), *bytes, "{inst}");
+            assert_eq!(parse(&inst.to_string()).as_ref(), Some(inst), "{inst}");
+        }
+    }
+
+    #[test]
+    fn encodes_moves() {
+        use Operand::{Imm, Reg as R, Stack};
+        check(&[
+            (Inst::Mov(R(Reg::Rax), Imm(5)), &[0xB8, 0x05, 0, 0, 0]),
+            (
+                Inst::Mov(R(Reg::R9), Imm(-1)),
+                &[0x41, 0xB9, 0xFF, 0xFF, 0xFF, 0xFF],
+            ),
+            (
+                Inst::Mov(Stack(8), Imm(7)),
+                &[0xC7, 0x44, 0x24, 0xF8, 7, 0, 0, 0],
+            ),
+            (
+                Inst::Mov(Stack(200), Imm(7)),
+                &[0xC7, 0x84, 0x24, 0x38, 0xFF, 0xFF, 0xFF, 7, 0, 0, 0],
+            ),
+            (Inst::Mov(R(Reg::Rcx), R(Reg::R12)), &[0x44, 0x89, 0xE1]),
+            (Inst::Mov(Stack(0), R(Reg::Rax)), &[0x89, 0x04, 0x24]),
+            (
+                Inst::Mov(R(Reg::Rdx), Stack(128)),
+                &[0x8B, 0x54, 0x24, 0x80],
+            ),
+            (
+                Inst::Mov(R(Reg::R10), Stack(132)),
+                &[0x44, 0x8B, 0x94, 0x24, 0x7C, 0xFF, 0xFF, 0xFF],
+            ),
+        ]);
+    }
+
+    #[test]
+    fn encodes_arithmetic() {
+        use Operand::{Imm, Reg as R, Stack};
+        check(&[
+            (
+                Inst::Alu(AluOp::Add, R(Reg::Rax), R(Reg::Rcx)),
+                &[0x01, 0xC8],
+            ),
+            (
+                Inst::Alu(AluOp::Sub, R(Reg::Rbx), Imm(1000)),
+                &[0x81, 0xEB, 0xE8, 0x03, 0, 0],
+            ),
+            (
+                Inst::Alu(AluOp::And, R(Reg::Rdx), Imm(3)),
+                &[0x83, 0xE2, 0x03],
+            ),
+            (
+                Inst::Alu(AluOp::Or, R(Reg::Rax), Stack(12)),
+                &[0x0B, 0x44, 0x24, 0xF4],
+            ),
+            (
+                Inst::Alu(AluOp::Xor, Stack(12), R(Reg::R8)),
+                &[0x44, 0x31, 0x44, 0x24, 0xF4],
+            ),
+            (
+                Inst::Alu(AluOp::Cmp, R(Reg::R11), Imm(0)),
+                &[0x41, 0x83, 0xFB, 0],
+            ),
+            (
+                Inst::Alu(AluOp::Imul, R(Reg::Rax), R(Reg::Rcx)),
+                &[0x0F, 0xAF, 0xC1],
+            ),
+            (
+                Inst::Alu(AluOp::Imul, R(Reg::Rdx), Imm(10)),
+                &[0x6B, 0xD2, 0x0A],
+            ),
+            (
+                Inst::Alu(AluOp::Imul, R(Reg::Rsi), Imm(100000)),
+                &[0x69, 0xF6, 0xA0, 0x86, 0x01, 0],
+            ),
+            (
+                Inst::Alu(AluOp::Imul, R(Reg::Rax), Stack(16)),
+                &[0x0F, 0xAF, 0x44, 0x24, 0xF0],
+            ),
+            (Inst::Unary(UnaryOp::Neg, R(Reg::Rax)), &[0xF7, 0xD8]),
+            (Inst::Unary(UnaryOp::Not, R(Reg::R13)), &[0x41, 0xF7, 0xD5]),
+            (
+                Inst::Unary(UnaryOp::Idiv, Stack(4)),
+                &[0xF7, 0x7C, 0x24, 0xFC],
+            ),
+            (Inst::Cdq, &[0x99]),
+            (Inst::Shift(ShiftOp::Sal), &[0xD3, 0xE0]),
+            (Inst::Shift(ShiftOp::Sar), &[0xD3, 0xF8]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_conditions() {
+        check(&[
+            (Inst::Set(Cond::E), &[0x0F, 0x94, 0xC0]),
+            (Inst::Set(Cond::Ne), &[0x0F, 0x95, 0xC0]),
+            (Inst::Set(Cond::L), &[0x0F, 0x9C, 0xC0]),
+            (Inst::Set(Cond::Le), &[0x0F, 0x9E, 0xC0]),
+            (Inst::Set(Cond::G), &[0x0F, 0x9F, 0xC0]),
+            (Inst::Set(Cond::Ge), &[0x0F, 0x9D, 0xC0]),
+            (Inst::MovzxAl, &[0x0F, 0xB6, 0xC0]),
+        ]);
+    }
+
+    #[test]
+    fn encodes_stack_and_64_bit_operations() {
+        check(&[
+            (Inst::Push(Reg::Rbp), &[0x55]),
+            (Inst::Push(Reg::R12), &[0x41, 0x54]),
+            (Inst::Pop(Reg::R15), &[0x41, 0x5F]),
+            (
+                Inst::Alu64(AluOp::Sub, Reg::Rsp, 8),
+                &[0x48, 0x83, 0xEC, 0x08],
+            ),
+            (
+                Inst::Alu64(AluOp::Add, Reg::Rsp, 4096),
+                &[0x48, 0x81, 0xC4, 0, 0x10, 0, 0],
+            ),
+            (Inst::Mov64(Reg::Rbp, Reg::Rsp), &[0x48, 0x89, 0xE5]),
+            (Inst::Mov64(Reg::Rdi, Reg::R8), &[0x4C, 0x89, 0xC7]),
+            (
+                Inst::Mov64Imm(Reg::Rax, 60),
+                &[0x48, 0xC7, 0xC0, 0x3C, 0, 0, 0],
+            ),
+            (Inst::Ret, &[0xC3]),
+            (Inst::Syscall, &[0x0F, 0x05]),
+        ]);
+    }
+
+    #[test]
+    fn leaves_symbols_to_the_linker() {
+        let mut code = Code::new();
+        code.encode(&Inst::LoadGlobal(Reg::Rdi, "stdout".to_owned()));
+        code.encode(&Inst::Call("_f".to_owned()));
+        code.finish();
+        assert_eq!(code.bytes, [0x48, 0x8B, 0x3D, 0, 0, 0, 0, 0xE8, 0, 0, 0, 0]);
+        let relocations: Vec<_> = code
+            .relocations
+            .iter()
+            .map(|r| (r.offset, r.symbol.as_str(), r.kind))
+            .collect();
+        assert_eq!(
+            relocations,
+            [
+                (3, "stdout", RelocationKind::Pc32),
+                (8, "_f", RelocationKind::Plt32)
+            ]
+        );
+    }
+
+    #[test]
+    fn resolves_jumps_to_labels() {
+        let mut code = Code::new();
+        for inst in [
+            Inst::Label(".L0".to_owned()),
+            Inst::Je(".L1".to_owned()),
+            Inst::Ret,
+            Inst::Label(".L1".to_owned()),
+            Inst::Jmp(".L0".to_owned()),
+        ] {
+            code.encode(&inst);
+        }
+        code.finish();
+        /* Both are relative to the end of the jump, the first one skips the ret */
+        assert_eq!(
+            code.bytes,
+            [0x0F, 0x84, 1, 0, 0, 0, 0xC3, 0xE9, 0xF4, 0xFF, 0xFF, 0xFF]
+        );
+        assert!(code.relocations.is_empty());
+    }
+}
@@ -0,0 +1,131 @@
+/* Checks what the compiler writes for the options of the command line */
+mod common;
+
+use std::{
+    fs,
+    io::Write,
+    path::Path,
+    process::{Command, Output, Stdio},
+};
+
+use common::{compdesign, workdir};
+
+const PROGRAM: &str = "int main() { return 3; }";
+
+fn assert_success(output: &Output) {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
+
+fn exit_code(executable: &Path) -> i32 {
+    Command::new(executable).status().unwrap().code().unwrap()
+}
+
+#[test]
+fn writes_the_executable_to_the_output() {
+    let dir = workdir("output");
+    fs::write(dir.join("main.l4"), PROGRAM).unwrap();
+    assert_success(&compdesign(&dir, &["main.l4", "-o", "prog"]));
+    assert_eq!(exit_code(&dir.join("prog")), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn names_assembly_and_objects_after_the_input() {
+    let dir = workdir("per-input");
+    fs::write(dir.join("main.l4"), PROGRAM).unwrap();
+    assert_success(&compdesign(&dir, &["-S", "main.l4"]));
+    let assembly = fs::read_to_string(dir.join("main.s")).unwrap();
+    assert!(assembly.contains("c0.main:"));
+
+    assert_success(&compdesign(&dir, &["-c", "main.l4"]));
+    let object = fs::read(dir.join("main.o")).unwrap();
+    assert!(object.starts_with(b"\x7fELF"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn dumps_every_stage_to_stdout() {
+    let dir = workdir("emit");
+    fs::write(dir.join("main.l4"), PROGRAM).unwrap();
+    let stages = [
+        ("tokens", "0..3 Keyword(Int)\n"),
+        ("ast", "int main() {\n    return 3;\n}\n"),
+        ("abs", "function main()\n    return 3\n"),
+        ("ir", "main():\n    t0 = 3\n    return t0\n"),
+    ];
+    for (stage, start) in stages {
+        let output = compdesign(&dir, &[&format!("--emit={stage}"), "main.l4"]);
+        assert_success(&output);
+        let dump = String::from_utf8(output.stdout).unwrap();
+        assert!(dump.starts_with(start), "{stage}: {dump}");
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reports_diagnostics_as_json_lines() {
+    let dir = workdir("json");
+    fs::write(dir.join("main.l4"), "int main() {\n    return y;\n}").unwrap();
+    let output = compdesign(&dir, &["--error-format=json", "main.l4"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        concat!(
+            r#"{"severity":"error","code":"E0201","message":"Variable \"y\" is not declared.","#,
+            r#""file":"main.l4","span":{"start":24,"end":25,"line":2,"column":12,"end_line":2,"#,
+            r#""end_column":13},"labels":[],"suggestion":null}"#,
+            "\n"
+        )
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn reads_the_program_from_stdin() {
+    let dir = workdir("stdin");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_compdesign"))
+        .current_dir(&dir)
+        .args(["-", "-o", "prog"])
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(PROGRAM.as_bytes())
+        .unwrap();
+    assert_success(&child.wait_with_output().unwrap());
+    assert_eq!(exit_code(&dir.join("prog")), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn writes_to_the_second_path_of_the_old_form() {
+    let dir = workdir("old-form");
+    fs::write(dir.join("main.l4"), PROGRAM).unwrap();
+    assert_success(&compdesign(&dir, &["main.l4", "prog"]));
+    assert_eq!(exit_code(&dir.join("prog")), 3);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn compiles_an_existing_source_instead_of_overwriting_it() {
+    let dir = workdir("old-form-sources");
+    let helper = "int helper() { return 4; }";
+    fs::write(
+        dir.join("main.l4"),
+        "int helper(); int main() { return helper(); }",
+    )
+    .unwrap();
+    fs::write(dir.join("helper.l3"), helper).unwrap();
+    assert_success(&compdesign(&dir, &["main.l4", "helper.l3"]));
+    assert_eq!(fs::read_to_string(dir.join("helper.l3")).unwrap(), helper);
+    assert_eq!(exit_code(&dir.join("a.out")), 4);
+    fs::remove_dir_all(&dir).unwrap();
+}
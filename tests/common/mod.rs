@@ -0,0 +1,21 @@
+/* Runs the compiler in a directory of its own for every test, since the tests run in parallel */
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+pub fn workdir(test: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("compdesign-{}-{test}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+pub fn compdesign(dir: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_compdesign"))
+        .current_dir(dir)
+        .args(args)
+        .output()
+        .unwrap()
+}
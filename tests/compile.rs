@@ -0,0 +1,272 @@
+/* Compiles small programs through compile() and checks what the diagnostics and the optimizer
+ * make of them */
+use std::{env, fs, process};
+
+use compdesign::{
+    Options, compile,
+    diagnostics::{Diagnostic, Severity},
+    error_codes,
+};
+
+fn options(opt_level: u8) -> Options {
+    Options {
+        opt_level,
+        jobs: 1,
+        ..Options::default()
+    }
+}
+
+fn errors(source: &str) -> Vec<Diagnostic> {
+    match compile(source.as_bytes(), &options(0)) {
+        Ok(_) => panic!("{source} compiled"),
+        Err(diagnostics) => diagnostics,
+    }
+}
+
+/* The code of every diagnostic and the text it points at */
+fn located<'s>(source: &'s str, diagnostics: &[Diagnostic]) -> Vec<(&'static str, &'s str)> {
+    diagnostics
+        .iter()
+        .map(|d| {
+            let span = d.span.unwrap();
+            (d.code, &source[span.start..span.end])
+        })
+        .collect()
+}
+
+/* The assembly of the unit */
+fn assembly(source: &str, options: &Options) -> String {
+    let artifact = compile(source.as_bytes(), options).unwrap();
+    let mut out = Vec::new();
+    artifact.assembly.write_to(&mut out).unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+/* The IR of every function after the optimizations of the options */
+fn ir(source: &str, options: &Options) -> String {
+    let artifact = compile(source.as_bytes(), options).unwrap();
+    artifact.ir.iter().map(|f| f.to_string()).collect()
+}
+
+#[test]
+fn reports_type_errors_at_the_offending_expression() {
+    let source = "int main() { int x = 1 + true; return x; }";
+    assert_eq!(
+        located(source, &errors(source)),
+        [(error_codes::TYPE_MISMATCH, "true")]
+    );
+}
+
+#[test]
+fn reports_the_errors_of_every_function_in_order() {
+    let source = "int f() { int x; return x; }
+int main() { return y; }";
+    assert_eq!(
+        located(source, &errors(source)),
+        [
+            (error_codes::UNASSIGNED_VARIABLE, "x"),
+            (error_codes::UNDECLARED_VARIABLE, "y"),
+        ]
+    );
+}
+
+#[test]
+fn type_checks_a_function_with_an_undeclared_variable() {
+    let source = "int main() { int x = y; bool b = 1; return x; }";
+    assert_eq!(
+        located(source, &errors(source)),
+        [
+            (error_codes::UNDECLARED_VARIABLE, "y"),
+            (error_codes::TYPE_MISMATCH, "1"),
+        ]
+    );
+}
+
+#[test]
+fn reports_the_errors_of_a_for_loop_once() {
+    let source = "int main() { int x; for (int i = x + y; i < 3; i += true) {} return 0; }";
+    assert_eq!(
+        located(source, &errors(source)),
+        [
+            (error_codes::UNASSIGNED_VARIABLE, "x"),
+            (error_codes::UNDECLARED_VARIABLE, "y"),
+            (error_codes::TYPE_MISMATCH, "true"),
+        ]
+    );
+}
+
+#[test]
+fn reports_every_kind_of_lexer_error() {
+    let cases = [
+        (
+            "int main() { return 1 @ 2; }",
+            error_codes::UNKNOWN_CHARACTER,
+            "@",
+        ),
+        (
+            "int main() { return 1; } /* open",
+            error_codes::UNTERMINATED_COMMENT,
+            "/*",
+        ),
+        (
+            "int main() { return 0x; }",
+            error_codes::EMPTY_HEX_LITERAL,
+            "0x",
+        ),
+        (
+            "int main() { return 0x1ffffffff; }",
+            error_codes::HEX_LITERAL_TOO_LONG,
+            "0x1ffffffff",
+        ),
+        (
+            "int main() { return 2147483649; }",
+            error_codes::DECIMAL_OUT_OF_RANGE,
+            "2147483649",
+        ),
+    ];
+    for (source, code, text) in cases {
+        assert_eq!(located(source, &errors(source)), [(code, text)], "{source}");
+    }
+}
+
+#[test]
+fn recovers_from_syntax_errors_in_several_functions() {
+    let source = "int f() { return 1 +; }
+int g( { return 2; }
+int main() { int x = ; return 0; }";
+    let diagnostics = errors(source);
+    assert_eq!(diagnostics.len(), 3);
+    assert!(
+        diagnostics
+            .iter()
+            .all(|d| d.code == error_codes::UNEXPECTED_TOKEN)
+    );
+}
+
+#[test]
+fn suggests_a_similar_function() {
+    let source = "int main() { return prnt(65); }";
+    let diagnostics = errors(source);
+    assert_eq!(
+        located(source, &diagnostics),
+        [(error_codes::UNKNOWN_FUNCTION, "prnt(65)")]
+    );
+    let suggestion = diagnostics[0].suggestion.as_ref().unwrap();
+    assert_eq!(suggestion.replacement, "print");
+}
+
+#[test]
+fn requires_a_main_function() {
+    let diagnostics = errors("int f() { return 0; }");
+    assert_eq!(diagnostics.len(), 1);
+    assert_eq!(diagnostics[0].code, error_codes::MISSING_MAIN);
+    assert_eq!(diagnostics[0].span, None);
+}
+
+#[test]
+fn warns_only_when_enabled() {
+    let source = "int main() { int unused = 1; while (true) { return 0; print(1); } return 1; }";
+    let artifact = compile(source.as_bytes(), &options(0)).unwrap();
+    assert!(artifact.warnings.is_empty());
+
+    let mut options = options(0);
+    options.warnings.apply_flag("all");
+    let artifact = compile(source.as_bytes(), &options).unwrap();
+    let warnings: Vec<_> = artifact.warnings.iter().map(|d| d.code).collect();
+    assert_eq!(
+        warnings,
+        [error_codes::UNUSED_VARIABLE, error_codes::UNREACHABLE_CODE]
+    );
+    assert!(
+        artifact
+            .warnings
+            .iter()
+            .all(|d| d.severity == Severity::Warning)
+    );
+}
+
+#[test]
+fn folds_constants_and_copies() {
+    let source = "int main() { int x = 2 + 3 * 4; int y = x; return y; }";
+    assert_eq!(ir(source, &options(1)), "main():\n    return 14\n");
+}
+
+#[test]
+fn keeps_a_division_by_zero() {
+    let source = "int main() { int x = 1 / 0; return x; }";
+    assert!(ir(source, &options(1)).contains("1 / 0"));
+}
+
+#[test]
+fn inlines_calls_at_o2() {
+    let source = "int sq(int a) { return a * a; }
+int main() { return sq(7); }";
+    assert!(ir(source, &options(1)).contains("c0.sq(7)"));
+    let inlined = ir(source, &options(2));
+    assert!(!inlined.contains("c0.sq"));
+    assert!(inlined.contains("49"));
+
+    let mut options = options(2);
+    options.disabled_optimizations.push("inline");
+    assert!(ir(source, &options).contains("c0.sq(7)"));
+}
+
+#[test]
+fn does_not_optimize_at_o0() {
+    let source = "int main() { return 2 + 3; }";
+    assert_eq!(
+        ir(source, &options(0)),
+        "main():\n    t0 = 2\n    t1 = 3\n    t2 = t0 + t1\n    return t2\n"
+    );
+}
+
+#[test]
+fn reuses_the_cached_functions_that_did_not_change() {
+    let dir = env::temp_dir().join(format!("compdesign-{}-cache", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let mut options = options(1);
+    options.cache_dir = Some(dir.clone());
+    let cached = |source: &str| {
+        let artifact = compile(source.as_bytes(), &options).unwrap();
+        artifact.stats.get("functions from cache")
+    };
+    let source = "int f(int a) { return a / 2; }
+int main() { return f(8); }";
+    assert_eq!(cached(source), 0);
+    assert_eq!(cached(source), 2);
+    assert_eq!(
+        assembly(source, &options),
+        assembly(source, &self::options(1))
+    );
+
+    let changed = "int f(int a) { return a / 4; }
+int main() { return f(8); }";
+    assert_eq!(cached(changed), 1);
+    assert_eq!(
+        assembly(changed, &options),
+        assembly(changed, &self::options(1))
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn generates_the_same_code_with_any_number_of_threads() {
+    let mut source: String = (0..16)
+        .map(|i| {
+            format!(
+                "int f{i}(int a) {{ int b = a * {i}; if (b > 7) {{ b = b / 3; }} return b + a; }}\n"
+            )
+        })
+        .collect();
+    source.push_str("int main() { return f0(1) + f7(2) + f15(3); }");
+    for level in 0..=2 {
+        let expected = assembly(&source, &options(level));
+        for jobs in [2, 8] {
+            let options = Options {
+                jobs,
+                ..options(level)
+            };
+            assert_eq!(assembly(&source, &options), expected, "-O{level} -j{jobs}");
+        }
+    }
+}
@@ -0,0 +1,99 @@
+/* Builds programs with the compiler and runs them */
+mod common;
+
+use std::{fs, process::Command};
+
+use common::{compdesign, workdir};
+
+/* The exit code and the output of the executable built from the source with the flags */
+fn run(test: &str, source: &str, flags: &[&str]) -> (i32, String) {
+    let dir = workdir(test);
+    fs::write(dir.join("main.l4"), source).unwrap();
+    let output = compdesign(&dir, &[flags, &["main.l4", "-o", "main"]].concat());
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let output = Command::new(dir.join("main")).output().unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+    )
+}
+
+#[test]
+fn keeps_a_store_that_is_live_on_another_path() {
+    let source = "int g(int a) { int r = 0; if (a > 0) { r = a; } else { r = -a; } return r; }
+int main() { return g(-5); }";
+    for level in ["-O0", "-O1", "-O2"] {
+        assert_eq!(run("live-store", source, &[level]).0, 5, "{level}");
+    }
+}
+
+#[test]
+fn keeps_the_parameters_in_their_registers() {
+    let source = "int add3(int a, int b, int c) { return a + b + c; }
+int main() { return add3(1, add3(2, 2, 2), 3); }";
+    for level in ["-O0", "-O1"] {
+        assert_eq!(run("params", source, &[level]).0, 10, "{level}");
+    }
+}
+
+#[test]
+fn links_functions_named_like_the_startup_code() {
+    let source = "int start() { return 1; }
+int init() { return 2; }
+int fini() { return 3; }
+int main() { return start() * 100 + init() * 10 + fini(); }";
+    for flags in [&[][..], &["--integrated-as"], &["--freestanding"]] {
+        assert_eq!(run("startup-names", source, flags).0, 123, "{flags:?}");
+    }
+}
+
+#[test]
+fn links_units_that_declare_each_others_functions() {
+    let dir = workdir("units");
+    fs::write(
+        dir.join("main.l4"),
+        "int twice(int x);
+int main() { return twice(21); }",
+    )
+    .unwrap();
+    fs::write(dir.join("twice.l4"), "int twice(int x) { return x * 2; }").unwrap();
+    let exit_code = |executable: &str| {
+        let status = Command::new(dir.join(executable)).status().unwrap();
+        status.code().unwrap()
+    };
+    assert!(
+        compdesign(&dir, &["main.l4", "twice.l4", "-o", "both"])
+            .status
+            .success()
+    );
+    assert_eq!(exit_code("both"), 42);
+
+    assert!(compdesign(&dir, &["-c", "twice.l4"]).status.success());
+    let output = compdesign(&dir, &["main.l4", "twice.o", "-o", "linked"]);
+    assert!(output.status.success());
+    assert_eq!(exit_code("linked"), 42);
+
+    /* Without the object the declared function is defined nowhere */
+    let output = compdesign(&dir, &["main.l4", "-o", "missing"]);
+    assert_eq!(output.status.code(), Some(7));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("E0109"));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn runs_without_the_c_library() {
+    let source = "int main() { print(72); print(105); print(10); return 7; }";
+    assert_eq!(
+        run("freestanding", source, &["--freestanding"]),
+        (7, "Hi\n".to_owned())
+    );
+    assert_eq!(
+        run("freestanding-o2", source, &["--freestanding", "-O2"]),
+        (7, "Hi\n".to_owned())
+    );
+}